@@ -0,0 +1,89 @@
+use crate::data_dir;
+use crate::market::Exchange;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// Last fetched price of a ticker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedQuote {
+    pub ticker: String,
+    pub price: f64,
+    /// Unix time the price was fetched
+    pub fetched_at: i64,
+}
+
+/// Latest quotes persisted in `~/.stocks/cache/quotes.csv`
+pub struct QuoteCache {
+    path: PathBuf,
+    quotes: HashMap<String, CachedQuote>,
+}
+
+impl QuoteCache {
+    /// Loads the cache from a file; a missing or unreadable file gives an empty cache
+    pub fn load(path: PathBuf) -> QuoteCache {
+        let mut quotes = HashMap::new();
+        if let Ok(mut reader) = csv::Reader::from_path(&path) {
+            for quote in reader.deserialize::<CachedQuote>().flatten() {
+                quotes.insert(quote.ticker.clone(), quote);
+            }
+        }
+        QuoteCache { path, quotes }
+    }
+
+    /// Loads the cache from its default location
+    pub fn default_location() -> QuoteCache {
+        QuoteCache::load(data_dir().join("cache").join("quotes.csv"))
+    }
+
+    /// The cached quote for a ticker, however old
+    pub fn get(&self, ticker: &str) -> Option<&CachedQuote> {
+        self.quotes.get(ticker)
+    }
+
+    /// Stores a freshly fetched price
+    pub fn insert(&mut self, ticker: &str, price: f64, fetched_at: i64) {
+        self.quotes.insert(
+            ticker.to_string(),
+            CachedQuote {
+                ticker: ticker.to_string(),
+                price,
+                fetched_at,
+            },
+        );
+    }
+
+    /// Returns the cached price if the market cannot have moved since it was fetched
+    ///
+    /// That is the case when the ticker's exchange is known, its session is
+    /// not in progress, and the quote was fetched after the last close.
+    pub fn settled_price(&self, ticker: &str, now: i64) -> Option<f64> {
+        let exchange = Exchange::for_ticker(ticker)?;
+        let quote = self.get(ticker)?;
+        if exchange.is_open(now) || quote.fetched_at < exchange.last_close(now) {
+            return None;
+        }
+        Some(quote.price)
+    }
+
+    /// Writes the cache back to disk
+    pub fn save(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut quotes: Vec<_> = self.quotes.values().collect();
+        quotes.sort_by(|a, b| a.ticker.cmp(&b.ticker));
+
+        let tmp = self.path.with_extension("csv.tmp");
+        let mut writer = csv::Writer::from_path(&tmp)?;
+        for quote in quotes {
+            writer.serialize(quote)?;
+        }
+        writer.flush()?;
+        fs::rename(tmp, &self.path)?;
+        Ok(())
+    }
+}
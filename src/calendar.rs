@@ -0,0 +1,174 @@
+use crate::date::Date;
+use crate::market::Exchange;
+
+const MONDAY: u32 = 0;
+const THURSDAY: u32 = 3;
+/// Weekday index of Sunday as returned by [`Date::weekday`]
+pub const SUNDAY_INDEX: u32 = 6;
+
+/// True if the exchange is shut for a public holiday on `date`
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::calendar::is_holiday;
+/// use xbar_stocks::date::Date;
+/// use xbar_stocks::market::Exchange;
+///
+/// let thanksgiving = Date::parse("2024-11-28").unwrap();
+/// assert!(is_holiday(Exchange::Us, thanksgiving));
+/// assert!(!is_holiday(Exchange::Gpw, thanksgiving));
+/// ```
+pub fn is_holiday(exchange: Exchange, date: Date) -> bool {
+    holidays(exchange, date.year).contains(&date)
+}
+
+/// True if the exchange holds a regular session on `date`
+pub fn is_trading_day(exchange: Exchange, date: Date) -> bool {
+    !date.is_weekend() && !is_holiday(exchange, date)
+}
+
+/// Full-day closures of an exchange in the given year
+pub fn holidays(exchange: Exchange, year: i32) -> Vec<Date> {
+    let easter = easter_sunday(year);
+    let fixed = |month, day| Date { year, month, day };
+
+    match exchange {
+        Exchange::Us => {
+            let mut days = vec![
+                nth_weekday(year, 1, MONDAY, 3),    // Martin Luther King Jr. Day
+                nth_weekday(year, 2, MONDAY, 3),    // Washington's Birthday
+                easter.add_days(-2),                // Good Friday
+                last_weekday(year, 5, MONDAY),      // Memorial Day
+                observed(fixed(7, 4)),              // Independence Day
+                nth_weekday(year, 9, MONDAY, 1),    // Labor Day
+                nth_weekday(year, 11, THURSDAY, 4), // Thanksgiving
+                observed(fixed(12, 25)),            // Christmas
+            ];
+            // New Year's Day on a Saturday is not moved to the previous Friday
+            let new_year = fixed(1, 1);
+            if new_year.weekday() != 5 {
+                days.push(observed(new_year));
+            }
+            if year >= 2022 {
+                days.push(observed(fixed(6, 19))); // Juneteenth
+            }
+            days
+        }
+        Exchange::Gpw => vec![
+            fixed(1, 1),
+            fixed(1, 6),         // Epiphany
+            easter.add_days(-2), // Good Friday
+            easter.add_days(1),  // Easter Monday
+            fixed(5, 1),
+            fixed(5, 3),         // Constitution Day
+            easter.add_days(60), // Corpus Christi
+            fixed(8, 15),
+            fixed(11, 1),
+            fixed(11, 11), // Independence Day
+            fixed(12, 24),
+            fixed(12, 25),
+            fixed(12, 26),
+            fixed(12, 31),
+        ],
+        Exchange::Lse => {
+            let christmas = fixed(12, 25);
+            // Christmas and Boxing Day on a weekend shift to the following weekdays
+            let (christmas, boxing_day) = match christmas.weekday() {
+                5 => (christmas.add_days(2), christmas.add_days(3)),
+                6 => (christmas.add_days(1), christmas.add_days(2)),
+                4 => (christmas, christmas.add_days(3)),
+                _ => (christmas, christmas.add_days(1)),
+            };
+            vec![
+                observed_monday(fixed(1, 1)),
+                easter.add_days(-2),
+                easter.add_days(1),
+                nth_weekday(year, 5, MONDAY, 1), // Early May bank holiday
+                last_weekday(year, 5, MONDAY),   // Spring bank holiday
+                last_weekday(year, 8, MONDAY),   // Summer bank holiday
+                christmas,
+                boxing_day,
+            ]
+        }
+        Exchange::Xetra => vec![
+            fixed(1, 1),
+            easter.add_days(-2),
+            easter.add_days(1),
+            fixed(5, 1),
+            fixed(12, 24),
+            fixed(12, 25),
+            fixed(12, 26),
+            fixed(12, 31),
+        ],
+    }
+}
+
+/// US rule: Saturday holidays are observed on Friday, Sunday ones on Monday
+fn observed(date: Date) -> Date {
+    match date.weekday() {
+        5 => date.add_days(-1),
+        6 => date.add_days(1),
+        _ => date,
+    }
+}
+
+/// UK rule: weekend holidays are observed on the following Monday
+fn observed_monday(date: Date) -> Date {
+    match date.weekday() {
+        5 => date.add_days(2),
+        6 => date.add_days(1),
+        _ => date,
+    }
+}
+
+/// Easter Sunday (anonymous Gregorian algorithm)
+pub fn easter_sunday(year: i32) -> Date {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    Date {
+        year,
+        month: month as u32,
+        day: day as u32,
+    }
+}
+
+/// The `n`th given weekday (0 = Monday) of a month
+pub fn nth_weekday(year: i32, month: u32, weekday: u32, n: i64) -> Date {
+    let first = Date {
+        year,
+        month,
+        day: 1,
+    };
+    let offset = (weekday as i64 - first.weekday() as i64).rem_euclid(7);
+    first.add_days(offset + 7 * (n - 1))
+}
+
+/// The last given weekday (0 = Monday) of a month
+pub fn last_weekday(year: i32, month: u32, weekday: u32) -> Date {
+    let last = Date {
+        year,
+        month,
+        day: 1,
+    }
+    .add_months(1)
+    .add_days(-1);
+    last.add_days(-(last.weekday() as i64 - weekday as i64).rem_euclid(7))
+}
+
+/// The last Sunday of a month, used for EU summer time
+pub fn last_sunday(year: i32, month: u32) -> Date {
+    last_weekday(year, month, SUNDAY_INDEX)
+}
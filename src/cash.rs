@@ -0,0 +1,28 @@
+use crate::date::Date;
+
+/// Returns true for cash tickers such as `CASH` or `CASH.USD`
+pub fn is_cash_ticker(ticker: &str) -> bool {
+    let upper = ticker.to_uppercase();
+    upper == "CASH" || upper.starts_with("CASH.")
+}
+
+/// Values one unit of cash including interest accrued since `since`
+///
+/// Interest compounds daily so that a full year at `apy_percent` yields
+/// exactly the advertised APY. Dates in the future accrue nothing.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::cash::accrued_price;
+/// use xbar_stocks::date::Date;
+///
+/// let since = Date::parse("2024-01-01").unwrap();
+/// let today = Date::parse("2024-12-31").unwrap();
+/// let price = accrued_price(1.0, 5.0, since, today);
+/// assert!((price - 1.05).abs() < 1e-9);
+/// ```
+pub fn accrued_price(unit_price: f64, apy_percent: f64, since: Date, today: Date) -> f64 {
+    let days = since.days_until(today).max(0) as f64;
+    unit_price * (1.0 + apy_percent / 100.0).powf(days / 365.0)
+}
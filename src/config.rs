@@ -0,0 +1,1174 @@
+use crate::data_dir;
+use crate::locale::Locale;
+use crate::market::Exchange;
+use crate::secrets::{self, SecretStore};
+use crate::transactions::CostBasis;
+use log::LevelFilter;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A value in the config file
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Bool(bool),
+    Array(Vec<Value>),
+}
+
+/// Parses the subset of TOML used by `config.toml`
+///
+/// Supports `[section]` headers, `key = value` pairs, `#` comments, and
+/// string, number, boolean and single-line array values. Keys are returned
+/// as `section.key`.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::config::{parse_toml, Value};
+///
+/// let values = parse_toml("[display]\nweekend = \"week\" # weekly summary\n").unwrap();
+/// assert_eq!(values["display.weekend"], Value::String("week".to_string()));
+/// ```
+pub fn parse_toml(text: &str) -> Result<HashMap<String, Value>, Box<dyn Error + Send + Sync>> {
+    let mut values = HashMap::new();
+    let mut section = String::new();
+
+    for (index, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected key = value", index + 1))?;
+        let value = parse_value(value.trim())
+            .ok_or_else(|| format!("line {}: invalid value '{}'", index + 1, value.trim()))?;
+        let key = key.trim().trim_matches('"');
+        let full_key = if section.is_empty() {
+            key.to_string()
+        } else {
+            format!("{}.{}", section, key)
+        };
+        values.insert(full_key, value);
+    }
+
+    Ok(values)
+}
+
+/// Removes a trailing `#` comment that is not inside a string
+pub(crate) fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    for (i, ch) in line.char_indices() {
+        match ch {
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+pub(crate) fn parse_value(text: &str) -> Option<Value> {
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return Some(Value::String(inner.replace("\\\"", "\"")));
+    }
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return inner
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(parse_value)
+            .collect::<Option<Vec<_>>>()
+            .map(Value::Array);
+    }
+    match text {
+        "true" => Some(Value::Bool(true)),
+        "false" => Some(Value::Bool(false)),
+        _ => text.replace('_', "").parse().ok().map(Value::Number),
+    }
+}
+
+/// Sections of `config.toml`, for telling them apart from keys in
+/// environment variable names
+const SECTIONS: &[&str] = &[
+    "colors",
+    "daemon",
+    "display",
+    "fetch",
+    "history",
+    "indicators",
+    "links",
+    "log",
+    "mqtt",
+    "notify",
+    "polybar",
+    "portfolio",
+    "secrets",
+    "template",
+    "transactions",
+];
+
+/// Settings with those of the `[portfolios.<name>]` section, and its
+/// subsections such as `[portfolios.<name>.display]`, in place of the
+/// top-level ones
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::config::{for_portfolio, parse_toml, Value};
+///
+/// let values = parse_toml(
+///     "base_currency = \"USD\"\n[portfolios.ike]\nbase_currency = \"PLN\"\n[portfolios.ike.display]\nprivacy = true\n",
+/// )
+/// .unwrap();
+/// let values = for_portfolio(values, "ike");
+/// assert_eq!(values["base_currency"], Value::String("PLN".to_string()));
+/// assert_eq!(values["display.privacy"], Value::Bool(true));
+/// ```
+pub fn for_portfolio(mut values: HashMap<String, Value>, name: &str) -> HashMap<String, Value> {
+    let prefix = format!("portfolios.{}.", name);
+    let overrides: Vec<(String, Value)> = values
+        .iter()
+        .filter_map(|(key, value)| Some((key.strip_prefix(&prefix)?.to_string(), value.clone())))
+        .collect();
+    values.extend(overrides);
+    values
+}
+
+/// Settings given as `STOCKS_<SECTION>_<KEY>` environment variables, keyed
+/// as [`parse_toml`] keys them
+///
+/// Values are read like TOML values, except that strings need no quotes and
+/// lists may be written as `a,b,c`. Variables that name no section, such as
+/// `STOCKS_PROVIDER`, set top-level keys.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::config::{env_overrides, Value};
+///
+/// let values = env_overrides([
+///     ("STOCKS_PROVIDER".to_string(), "stooq".to_string()),
+///     ("STOCKS_FETCH_CACHE_TTL".to_string(), "300".to_string()),
+///     ("STOCKS_DISPLAY_COLUMNS".to_string(), "price,change".to_string()),
+///     ("HOME".to_string(), "/home/me".to_string()),
+/// ]);
+/// assert_eq!(values["provider"], Value::String("stooq".to_string()));
+/// assert_eq!(values["fetch.cache_ttl"], Value::Number(300.0));
+/// assert_eq!(values["display.columns"], Value::Array(vec![
+///     Value::String("price".to_string()),
+///     Value::String("change".to_string()),
+/// ]));
+/// assert_eq!(values.len(), 3);
+/// ```
+pub fn env_overrides(vars: impl IntoIterator<Item = (String, String)>) -> HashMap<String, Value> {
+    let mut values = HashMap::new();
+    for (name, text) in vars {
+        let Some(name) = name.strip_prefix("STOCKS_") else {
+            continue;
+        };
+        let name = name.to_lowercase();
+        // Secrets and the portfolio path have their own variables
+        if name.starts_with("secret_")
+            || name.ends_with("_api_key")
+            || name == "csv"
+            || name == "config"
+        {
+            continue;
+        }
+        let key = SECTIONS
+            .iter()
+            .find_map(|section| {
+                name.strip_prefix(section)
+                    .and_then(|rest| rest.strip_prefix('_'))
+                    .map(|key| format!("{}.{}", section, key))
+            })
+            .unwrap_or(name);
+        values.insert(key, env_value(text.trim()));
+    }
+    values
+}
+
+fn env_value(text: &str) -> Value {
+    if let Some(value) = parse_value(text) {
+        return value;
+    }
+    let inner = text.strip_prefix('[').and_then(|t| t.strip_suffix(']'));
+    match inner {
+        Some(inner) => Value::Array(
+            inner
+                .split(',')
+                .map(|item| env_value(item.trim()))
+                .collect(),
+        ),
+        None if text.contains(',') => {
+            Value::Array(text.split(',').map(|item| env_value(item.trim())).collect())
+        }
+        None => Value::String(text.to_string()),
+    }
+}
+
+/// What the menu-bar line shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderMode {
+    /// Total P/L against invested capital
+    Total,
+    /// Change since the previous close
+    Today,
+    /// Change over the last week
+    Week,
+    /// Market value of the portfolio
+    Value,
+    /// Total change in percent only
+    Percent,
+}
+
+impl HeaderMode {
+    fn parse(value: &str) -> Option<HeaderMode> {
+        match value {
+            "total" => Some(HeaderMode::Total),
+            "today" => Some(HeaderMode::Today),
+            "week" => Some(HeaderMode::Week),
+            "value" => Some(HeaderMode::Value),
+            "percent" => Some(HeaderMode::Percent),
+            _ => None,
+        }
+    }
+}
+
+/// A figure shown on each position's dropdown line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    BuyPrice,
+    /// Current price
+    Price,
+    Shares,
+    /// Market value
+    Value,
+    /// Change since the previous close, in percent
+    DayChange,
+    /// Profit or loss amount
+    ProfitLoss,
+    /// Total change against the buy price, in percent
+    Change,
+    /// Share of the portfolio's value
+    Weight,
+}
+
+impl Column {
+    /// The columns of the default line,
+    /// `$150.00 @ $180.50 +$61 (+20.33%) · 12.5%`
+    pub const DEFAULT: [Column; 5] = [
+        Column::BuyPrice,
+        Column::Price,
+        Column::ProfitLoss,
+        Column::Change,
+        Column::Weight,
+    ];
+
+    fn parse(value: &str) -> Option<Column> {
+        match value {
+            "buy" => Some(Column::BuyPrice),
+            "price" => Some(Column::Price),
+            "shares" => Some(Column::Shares),
+            "value" => Some(Column::Value),
+            "day" => Some(Column::DayChange),
+            "profit" => Some(Column::ProfitLoss),
+            "change" => Some(Column::Change),
+            "weight" => Some(Column::Weight),
+            _ => None,
+        }
+    }
+}
+
+/// What the positions in the dropdown are ordered by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Ticker,
+    /// Market value in the base currency
+    Value,
+    /// Profit or loss amount in the base currency
+    ProfitLoss,
+    /// Total change against the buy price, in percent
+    Change,
+    /// Change since the previous close, in percent
+    DayChange,
+}
+
+/// Order of the positions; cash and failed quotes stay at the bottom
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub key: SortKey,
+    pub descending: bool,
+}
+
+impl Sort {
+    /// Parses `key` or `key:asc`/`key:desc`, where the key is ticker, value,
+    /// profit, change or day; tickers default to ascending, figures to
+    /// descending
+    pub fn parse(text: &str) -> Option<Sort> {
+        let (name, order) = match text.split_once(':') {
+            Some((name, order)) => (name, Some(order)),
+            None => (text, None),
+        };
+        let key = match name {
+            "ticker" => SortKey::Ticker,
+            "value" => SortKey::Value,
+            "profit" => SortKey::ProfitLoss,
+            "change" => SortKey::Change,
+            "day" => SortKey::DayChange,
+            _ => return None,
+        };
+        let descending = match order {
+            None => key != SortKey::Ticker,
+            Some("asc") => false,
+            Some("desc") => true,
+            Some(_) => return None,
+        };
+        Some(Sort { key, descending })
+    }
+}
+
+/// Writes the order as `key:asc` or `key:desc`, the form [`Sort::parse`]
+/// reads
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::config::Sort;
+///
+/// let sort = Sort::parse("profit").unwrap();
+/// assert_eq!(sort.to_string(), "profit:desc");
+/// assert_eq!(Sort::parse(&sort.to_string()), Some(sort));
+/// ```
+impl fmt::Display for Sort {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.key {
+            SortKey::Ticker => "ticker",
+            SortKey::Value => "value",
+            SortKey::ProfitLoss => "profit",
+            SortKey::Change => "change",
+            SortKey::DayChange => "day",
+        };
+        let order = if self.descending { "desc" } else { "asc" };
+        write!(f, "{}:{}", name, order)
+    }
+}
+
+/// How the menu bar shows today's direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrendIcon {
+    /// Text only
+    None,
+    /// A 📈 or 📉 prefix
+    Emoji,
+    /// A macOS SF Symbol chart image next to the text
+    Symbol,
+}
+
+impl TrendIcon {
+    fn parse(value: &str) -> Option<TrendIcon> {
+        match value {
+            "none" => Some(TrendIcon::None),
+            "emoji" => Some(TrendIcon::Emoji),
+            "sfimage" => Some(TrendIcon::Symbol),
+            _ => None,
+        }
+    }
+}
+
+/// Colors of gains and losses, as xbar color names or `#RRGGBB`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    pub gain: String,
+    pub loss: String,
+    /// Used for changes smaller than `neutral_within`
+    pub neutral: String,
+    /// % change either side of zero shown in the neutral color (0 disables)
+    pub neutral_within: f64,
+}
+
+impl Palette {
+    /// Green and dark red
+    pub fn standard() -> Palette {
+        Palette {
+            gain: "green".to_string(),
+            loss: "darkred".to_string(),
+            neutral: "gray".to_string(),
+            neutral_within: 0.0,
+        }
+    }
+
+    /// Blue and orange from the Okabe-Ito palette, distinguishable with
+    /// red-green color blindness
+    pub fn colorblind() -> Palette {
+        Palette {
+            gain: "#56B4E9".to_string(),
+            loss: "#E69F00".to_string(),
+            neutral: "#999999".to_string(),
+            neutral_within: 0.0,
+        }
+    }
+
+    /// The color of a % change
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::config::Palette;
+    ///
+    /// let mut palette = Palette::standard();
+    /// palette.neutral_within = 0.5;
+    /// assert_eq!(palette.color(0.3), "gray");
+    /// assert_eq!(palette.color(-0.5), "darkred");
+    /// assert_eq!(palette.color(2.0), "green");
+    /// ```
+    pub fn color(&self, change_percent: f64) -> &str {
+        if change_percent.abs() < self.neutral_within {
+            &self.neutral
+        } else if change_percent >= 0.0 {
+            &self.gain
+        } else {
+            &self.loss
+        }
+    }
+}
+
+/// Colors positions along a gradient by their % change, like a heatmap
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Heatmap {
+    /// RGB colors of a full loss, no change and a full gain
+    pub colors: [(u8, u8, u8); 3],
+    /// % change at which the loss and gain colors are reached
+    pub range: f64,
+}
+
+impl Heatmap {
+    /// Deep red through grey to bright green, saturating at ±10%
+    pub const DEFAULT: Heatmap = Heatmap {
+        colors: [(0x8B, 0x00, 0x00), (0x88, 0x88, 0x88), (0x00, 0xE6, 0x76)],
+        range: 10.0,
+    };
+
+    /// Orange through grey to blue, for the colorblind palette
+    pub const COLORBLIND: Heatmap = Heatmap {
+        colors: [(0xE6, 0x9F, 0x00), (0x88, 0x88, 0x88), (0x56, 0xB4, 0xE9)],
+        range: 10.0,
+    };
+
+    /// The `#RRGGBB` color of a % change
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::config::Heatmap;
+    ///
+    /// assert_eq!(Heatmap::DEFAULT.color(0.0), "#888888");
+    /// assert_eq!(Heatmap::DEFAULT.color(-25.0), "#8B0000");
+    /// assert_eq!(Heatmap::DEFAULT.color(5.0), "#44B77F");
+    /// ```
+    pub fn color(&self, change: f64) -> String {
+        let [loss, flat, gain] = self.colors;
+        let t = (change / self.range).clamp(-1.0, 1.0);
+        let target = if t < 0.0 { loss } else { gain };
+        let mix =
+            |from: u8, to: u8| (from as f64 + (to as f64 - from as f64) * t.abs()).round() as u8;
+        format!(
+            "#{:02X}{:02X}{:02X}",
+            mix(flat.0, target.0),
+            mix(flat.1, target.1),
+            mix(flat.2, target.2)
+        )
+    }
+}
+
+/// Parses a `#RRGGBB` color
+fn parse_hex_color(text: &str) -> Option<(u8, u8, u8)> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// Header modes chosen by time of day, evaluated at render time
+#[derive(Debug, Clone)]
+pub struct DisplaySchedule {
+    /// While any held exchange is in session
+    pub market_hours: HeaderMode,
+    /// On trading days outside the session
+    pub after_close: HeaderMode,
+    /// When none of the held exchanges trades today
+    pub weekend: HeaderMode,
+    /// Modes xbar rotates through in the menu bar, replacing the schedule
+    /// when not empty
+    pub cycle: Vec<HeaderMode>,
+}
+
+impl Default for DisplaySchedule {
+    fn default() -> DisplaySchedule {
+        DisplaySchedule {
+            market_hours: HeaderMode::Total,
+            after_close: HeaderMode::Total,
+            weekend: HeaderMode::Total,
+            cycle: Vec::new(),
+        }
+    }
+}
+
+impl DisplaySchedule {
+    /// The header modes shown at the moment `now` given the exchanges held:
+    /// the cycle if one is set, otherwise the scheduled mode
+    pub fn modes_at(&self, exchanges: &[Exchange], now: i64) -> Vec<HeaderMode> {
+        if self.cycle.is_empty() {
+            vec![self.mode_at(exchanges, now)]
+        } else {
+            self.cycle.clone()
+        }
+    }
+
+    /// Picks the header mode for the moment `now` given the exchanges held
+    pub fn mode_at(&self, exchanges: &[Exchange], now: i64) -> HeaderMode {
+        if exchanges.iter().any(|exchange| exchange.is_open(now)) {
+            self.market_hours
+        } else if exchanges.iter().any(|exchange| {
+            let (date, _) = exchange.local_time(now);
+            exchange.is_trading_day(date)
+        }) {
+            self.after_close
+        } else {
+            self.weekend
+        }
+    }
+}
+
+/// Price providers the fetcher can scrape
+pub const PROVIDERS: &[&str] = &["stooq"];
+
+/// Quote page URL template of a provider
+fn quote_page(provider: &str) -> &'static str {
+    match provider {
+        "stooq" => "https://stooq.com/q/?s={ticker}",
+        _ => "https://finance.yahoo.com/quote/{symbol}",
+    }
+}
+
+/// Settings from `~/.stocks/config.toml`
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Price provider name, one of [`PROVIDERS`]
+    pub provider: String,
+    /// Currency totals are reported in (ISO code)
+    pub base_currency: String,
+    /// Quotes fetched at the same time
+    pub fetch_concurrency: usize,
+    /// Seconds a request may take in total, including reading the body
+    pub fetch_timeout_secs: u64,
+    /// Seconds to wait for the provider to accept the connection
+    pub connect_timeout_secs: u64,
+    /// Reuse a cached quote younger than this many seconds instead of
+    /// fetching it again (0 fetches on every run while the market is open)
+    pub cache_ttl_secs: u64,
+    /// Also write the log to `~/.stocks/logs/stocks.log`
+    pub log_file: bool,
+    /// Most detailed records written to the log file
+    pub log_level: LevelFilter,
+    pub display: DisplaySchedule,
+    /// Figures on each position's line, in order
+    pub columns: Vec<Column>,
+    /// Fold positions below this share of the portfolio (percent) into one
+    /// "Other" line
+    pub collapse_weight: Option<f64>,
+    /// Fold positions worth less than this (base currency) into one "Other"
+    /// line
+    pub collapse_value: Option<f64>,
+    /// Order of the positions, by default biggest % change first
+    pub sort: Sort,
+    /// Hide amounts and show only percentages, unless flipped with
+    /// `xbar-stocks privacy`
+    pub privacy: bool,
+    /// Colors of gains and losses
+    pub palette: Palette,
+    /// Color positions by % change along a gradient instead of the palette
+    pub heatmap: Option<Heatmap>,
+    /// Separators and symbol placement of numbers, by default from the
+    /// system locale
+    pub locale: Locale,
+    /// Icon showing today's direction in the menu bar
+    pub trend_icon: TrendIcon,
+    /// Mark each position with ▲ or ▼ by today's change
+    pub arrows: bool,
+    /// Monospace font of the dropdown so columns line up; `None` keeps
+    /// xbar's default
+    pub font: Option<String>,
+    /// Number of recent closes drawn as a sparkline per position (0 disables)
+    pub sparkline_days: usize,
+    /// Color positions orange within this share (percent) of their 52-week
+    /// range above the low; `None` disables
+    pub near_low_percent: Option<f64>,
+    /// Grey out quotes older than this many minutes unless their market has
+    /// closed since they were fetched
+    pub stale_after_minutes: u64,
+    /// Add realized gains of sold lots to the menu-bar total
+    pub include_realized: bool,
+    /// Show SMA50/SMA200 and golden/death crosses per position
+    pub moving_averages: bool,
+    /// Show RSI(14) with overbought/oversold coloring per position
+    pub rsi: bool,
+    /// Show annualized volatility and beta per position
+    pub risk_metrics: bool,
+    /// Index positions are compared against unless they declare their own
+    pub benchmark: Option<String>,
+    /// Maximum number of tickers whose history is downloaded per run
+    pub history_fetches_per_run: usize,
+    /// Send an end-of-day summary after the held markets close (daemon and
+    /// snapshot modes)
+    pub eod_notify: bool,
+    /// Webhook to post the summary to instead of a desktop notification
+    pub notify_webhook: Option<String>,
+    /// Seconds between price refreshes in daemon mode
+    pub daemon_interval_secs: u64,
+    /// Address the daemon serves Prometheus metrics on, e.g. 127.0.0.1:9184
+    pub metrics_listen: Option<String>,
+    /// MQTT broker (host:port) the daemon publishes quotes to
+    pub mqtt_broker: Option<String>,
+    /// MQTT user name; the password is the `mqtt` key in the secret store
+    pub mqtt_username: Option<String>,
+    /// Prefix of the state topics
+    pub mqtt_topic: String,
+    /// Home Assistant discovery prefix
+    pub mqtt_discovery_prefix: String,
+    /// Lot matching used when replaying a transactions ledger
+    pub cost_basis: CostBasis,
+    /// Template file for `--format template`, relative to `~/.stocks`
+    pub template: Option<PathBuf>,
+    /// Page a position opens when clicked, with `{ticker}` (e.g. `AAPL.US`)
+    /// and `{symbol}` (`AAPL`) filled in; defaults to the provider's quote page
+    pub quote_url: String,
+    /// Broker or portfolio page the summary lines open when clicked
+    pub portfolio_url: Option<String>,
+    /// Print only the menu-bar total in Polybar output, without per-position
+    /// changes
+    pub polybar_summary_only: bool,
+    /// Leave out portfolio rows that fail to parse, listing them in the
+    /// dropdown, instead of refusing to load
+    pub skip_invalid_rows: bool,
+    /// Secret store backend: keychain, env, file or command
+    pub secret_store: String,
+    /// Command for the `command` secret store, with `{key}` as placeholder
+    pub secret_command: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            provider: "stooq".to_string(),
+            base_currency: "USD".to_string(),
+            fetch_concurrency: 7,
+            fetch_timeout_secs: 15,
+            connect_timeout_secs: 5,
+            cache_ttl_secs: 0,
+            log_file: false,
+            log_level: LevelFilter::Info,
+            display: DisplaySchedule::default(),
+            columns: Column::DEFAULT.to_vec(),
+            collapse_weight: None,
+            collapse_value: None,
+            sort: Sort {
+                key: SortKey::Change,
+                descending: true,
+            },
+            privacy: false,
+            palette: Palette::standard(),
+            heatmap: None,
+            locale: Locale::system(),
+            trend_icon: TrendIcon::None,
+            arrows: false,
+            font: Some("Menlo".to_string()),
+            sparkline_days: 10,
+            near_low_percent: None,
+            stale_after_minutes: 60,
+            include_realized: false,
+            moving_averages: false,
+            rsi: false,
+            risk_metrics: false,
+            benchmark: None,
+            history_fetches_per_run: 3,
+            eod_notify: false,
+            notify_webhook: None,
+            daemon_interval_secs: 60,
+            metrics_listen: None,
+            mqtt_broker: None,
+            mqtt_username: None,
+            mqtt_topic: "stocks".to_string(),
+            mqtt_discovery_prefix: "homeassistant".to_string(),
+            cost_basis: CostBasis::Average,
+            template: None,
+            quote_url: quote_page("stooq").to_string(),
+            portfolio_url: None,
+            polybar_summary_only: false,
+            skip_invalid_rows: false,
+            secret_store: "keychain".to_string(),
+            secret_command: None,
+        }
+    }
+}
+
+impl Config {
+    /// Default config location, `$STOCKS_CONFIG` or `~/.stocks/config.toml`
+    pub fn default_path() -> PathBuf {
+        env::var_os("STOCKS_CONFIG")
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from)
+            .unwrap_or_else(|| data_dir().join("config.toml"))
+    }
+
+    /// The page a position opens when clicked, from [`Config::quote_url`]
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::config::Config;
+    ///
+    /// let config = Config::default();
+    /// assert_eq!(config.quote_link("^SPX"), "https://stooq.com/q/?s=%5ESPX");
+    ///
+    /// let config = Config::from_toml("[links]\nquote = \"https://example.com/{symbol}\"").unwrap();
+    /// assert_eq!(config.quote_link("AAPL.US"), "https://example.com/AAPL");
+    /// ```
+    pub fn quote_link(&self, ticker: &str) -> String {
+        let symbol = ticker.split('.').next().unwrap_or(ticker);
+        self.quote_url
+            .replace("{ticker}", &url_encode(ticker))
+            .replace("{symbol}", &url_encode(symbol))
+    }
+
+    /// Loads the config file with the `[portfolios.<name>]` section of the
+    /// named portfolio and then `STOCKS_*` environment variables layered over
+    /// it; a missing file yields the defaults
+    pub fn load(
+        path: &Path,
+        portfolio: Option<&str>,
+    ) -> Result<Config, Box<dyn Error + Send + Sync>> {
+        let mut values = if path.exists() {
+            parse_toml(&fs::read_to_string(path)?)?
+        } else {
+            HashMap::new()
+        };
+        if let Some(name) = portfolio {
+            values = for_portfolio(values, name);
+        }
+        let overrides = env_overrides(env::vars());
+        let from_env: Vec<String> = overrides.keys().cloned().collect();
+        values.extend(overrides);
+        Config::from_values(&values).map_err(|e| {
+            let message = e.to_string();
+            match from_env
+                .iter()
+                .find(|key| message.starts_with(&format!("{}:", key)))
+            {
+                Some(key) => format!(
+                    "{} (set by STOCKS_{})",
+                    message,
+                    key.to_uppercase().replace('.', "_")
+                )
+                .into(),
+                None => e,
+            }
+        })
+    }
+
+    /// Builds a config from TOML text, keeping defaults for missing keys
+    pub fn from_toml(text: &str) -> Result<Config, Box<dyn Error + Send + Sync>> {
+        Config::from_values(&parse_toml(text)?)
+    }
+
+    /// Builds a config from `section.key` values, keeping defaults for
+    /// missing keys
+    pub fn from_values(
+        values: &HashMap<String, Value>,
+    ) -> Result<Config, Box<dyn Error + Send + Sync>> {
+        let mut config = Config::default();
+
+        match values.get("provider") {
+            None => {}
+            Some(Value::String(provider)) if PROVIDERS.contains(&provider.as_str()) => {
+                config.provider = provider.clone();
+                config.quote_url = quote_page(provider).to_string();
+            }
+            Some(other) => return Err(format!("provider: unsupported value {:?}", other).into()),
+        }
+        match values.get("base_currency") {
+            None => {}
+            Some(Value::String(currency)) => config.base_currency = currency.to_uppercase(),
+            Some(_) => return Err("base_currency: expected a string".into()),
+        }
+
+        match values.get("fetch.concurrency") {
+            None => {}
+            Some(Value::Number(count)) if *count >= 1.0 => {
+                config.fetch_concurrency = *count as usize
+            }
+            Some(_) => return Err("fetch.concurrency: expected a number of at least 1".into()),
+        }
+        match values.get("log.file") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.log_file = *enabled,
+            Some(_) => return Err("log.file: expected true or false".into()),
+        }
+        match values.get("log.level") {
+            None => {}
+            Some(Value::String(level)) if level.parse::<LevelFilter>().is_ok() => {
+                config.log_level = level.parse().unwrap_or(LevelFilter::Info)
+            }
+            Some(_) => {
+                return Err("log.level: expected error, warn, info, debug or trace".into());
+            }
+        }
+
+        for (key, secs, minimum) in [
+            ("fetch.timeout", &mut config.fetch_timeout_secs, 1.0),
+            (
+                "fetch.connect_timeout",
+                &mut config.connect_timeout_secs,
+                1.0,
+            ),
+            ("fetch.cache_ttl", &mut config.cache_ttl_secs, 0.0),
+        ] {
+            match values.get(key) {
+                None => {}
+                Some(Value::Number(value)) if *value >= minimum => *secs = *value as u64,
+                Some(_) => {
+                    return Err(format!(
+                        "{}: expected seconds as a number of at least {}",
+                        key, minimum
+                    )
+                    .into());
+                }
+            }
+        }
+
+        let header_mode = |key: &str, default: HeaderMode| match values.get(key) {
+            None => Ok(default),
+            Some(Value::String(mode)) => HeaderMode::parse(mode)
+                .ok_or_else(|| format!("{}: unknown display mode '{}'", key, mode)),
+            Some(_) => Err(format!("{}: expected a string", key)),
+        };
+        // `display.header` sets every slot of the schedule at once
+        let header = header_mode("display.header", HeaderMode::Total)?;
+        config.display.market_hours = header_mode("display.market_hours", header)?;
+        config.display.after_close = header_mode("display.after_close", header)?;
+        config.display.weekend = header_mode("display.weekend", header)?;
+        match values.get("display.cycle") {
+            None => {}
+            Some(Value::Array(modes)) => {
+                config.display.cycle = modes
+                    .iter()
+                    .map(|mode| match mode {
+                        Value::String(mode) => HeaderMode::parse(mode).ok_or_else(|| {
+                            format!(
+                                "display.cycle: unknown display mode '{}', expected total, today, week, value or percent",
+                                mode
+                            )
+                        }),
+                        _ => Err("display.cycle: expected display mode names".to_string()),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            Some(_) => return Err("display.cycle: expected a list of display modes".into()),
+        }
+        match values.get("display.columns") {
+            None => {}
+            Some(Value::Array(names)) => {
+                config.columns = names
+                    .iter()
+                    .map(|name| match name {
+                        Value::String(name) => Column::parse(name).ok_or_else(|| {
+                            format!(
+                                "display.columns: unknown column '{}', expected buy, price, shares, value, day, profit, change or weight",
+                                name
+                            )
+                        }),
+                        _ => Err("display.columns: expected column names".to_string()),
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+            Some(_) => return Err("display.columns: expected a list of column names".into()),
+        }
+        match values.get("display.collapse_weight") {
+            None => {}
+            Some(Value::Number(percent)) => config.collapse_weight = Some(*percent),
+            Some(_) => return Err("display.collapse_weight: expected a percentage".into()),
+        }
+        match values.get("display.collapse_value") {
+            None => {}
+            Some(Value::Number(amount)) => config.collapse_value = Some(*amount),
+            Some(_) => return Err("display.collapse_value: expected an amount".into()),
+        }
+        match values.get("display.sort") {
+            None => {}
+            Some(Value::String(text)) => {
+                config.sort = Sort::parse(text).ok_or_else(|| {
+                    format!(
+                        "display.sort: unknown order '{}', expected ticker, value, profit, change or day, optionally with :asc or :desc",
+                        text
+                    )
+                })?;
+            }
+            Some(_) => return Err("display.sort: expected a string".into()),
+        }
+        let mut heatmap = Heatmap::DEFAULT;
+        match values.get("colors.palette") {
+            None => {}
+            Some(Value::String(name)) if name == "standard" => {}
+            Some(Value::String(name)) if name == "colorblind" => {
+                config.palette = Palette::colorblind();
+                heatmap = Heatmap::COLORBLIND;
+            }
+            Some(_) => return Err("colors.palette: expected standard or colorblind".into()),
+        }
+        for (key, color) in [
+            ("colors.gain", &mut config.palette.gain),
+            ("colors.loss", &mut config.palette.loss),
+            ("colors.neutral", &mut config.palette.neutral),
+        ] {
+            match values.get(key) {
+                None => {}
+                Some(Value::String(name)) => *color = name.clone(),
+                Some(_) => {
+                    return Err(format!("{}: expected a color name or \"#RRGGBB\"", key).into());
+                }
+            }
+        }
+        match values.get("colors.neutral_within") {
+            None => {}
+            Some(Value::Number(percent)) if *percent >= 0.0 => {
+                config.palette.neutral_within = *percent;
+            }
+            Some(_) => {
+                return Err("colors.neutral_within: expected a non-negative percentage".into());
+            }
+        }
+        match values.get("display.heatmap_colors") {
+            None => {}
+            Some(Value::Array(colors)) if colors.len() == 3 => {
+                for (i, color) in colors.iter().enumerate() {
+                    heatmap.colors[i] = match color {
+                        Value::String(text) => parse_hex_color(text),
+                        _ => None,
+                    }
+                    .ok_or("display.heatmap_colors: expected \"#RRGGBB\" colors")?;
+                }
+            }
+            Some(_) => {
+                return Err(
+                    "display.heatmap_colors: expected three colors for loss, no change and gain"
+                        .into(),
+                );
+            }
+        }
+        match values.get("display.heatmap_range") {
+            None => {}
+            Some(Value::Number(percent)) if *percent > 0.0 => heatmap.range = *percent,
+            Some(_) => return Err("display.heatmap_range: expected a positive percentage".into()),
+        }
+        match values.get("display.heatmap") {
+            None | Some(Value::Bool(false)) => {}
+            Some(Value::Bool(true)) => config.heatmap = Some(heatmap),
+            Some(_) => return Err("display.heatmap: expected true or false".into()),
+        }
+        match values.get("display.sparkline") {
+            None => {}
+            Some(Value::Number(days)) if *days >= 0.0 => config.sparkline_days = *days as usize,
+            Some(_) => return Err("display.sparkline: expected a non-negative number".into()),
+        }
+        match values.get("display.locale") {
+            None => {}
+            Some(Value::String(name)) if name == "system" => {}
+            Some(Value::String(name)) => {
+                config.locale = Locale::from_name(name).ok_or_else(|| {
+                    format!(
+                        "display.locale: unknown locale '{}', expected a name such as en_US or de_DE, or system",
+                        name
+                    )
+                })?;
+            }
+            Some(_) => return Err("display.locale: expected a string".into()),
+        }
+        match values.get("display.trend_icon") {
+            None => {}
+            Some(Value::String(name)) => {
+                config.trend_icon = TrendIcon::parse(name).ok_or_else(|| {
+                    format!(
+                        "display.trend_icon: unknown icon '{}', expected none, emoji or sfimage",
+                        name
+                    )
+                })?;
+            }
+            Some(_) => return Err("display.trend_icon: expected a string".into()),
+        }
+        match values.get("display.font") {
+            None => {}
+            Some(Value::String(font)) if font.is_empty() => config.font = None,
+            Some(Value::String(font)) => config.font = Some(font.clone()),
+            Some(_) => return Err("display.font: expected a font name".into()),
+        }
+        match values.get("display.arrows") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.arrows = *enabled,
+            Some(_) => return Err("display.arrows: expected true or false".into()),
+        }
+        match values.get("display.privacy") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.privacy = *enabled,
+            Some(_) => return Err("display.privacy: expected true or false".into()),
+        }
+        match values.get("display.near_low") {
+            None => {}
+            Some(Value::Number(percent)) => config.near_low_percent = Some(*percent),
+            Some(_) => return Err("display.near_low: expected a number".into()),
+        }
+        match values.get("display.stale_after") {
+            None => {}
+            Some(Value::Number(minutes)) if *minutes >= 0.0 => {
+                config.stale_after_minutes = *minutes as u64
+            }
+            Some(_) => return Err("display.stale_after: expected minutes as a number".into()),
+        }
+        match values.get("display.include_realized") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.include_realized = *enabled,
+            Some(_) => return Err("display.include_realized: expected true or false".into()),
+        }
+
+        match values.get("indicators.moving_averages") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.moving_averages = *enabled,
+            Some(_) => return Err("indicators.moving_averages: expected true or false".into()),
+        }
+        match values.get("indicators.rsi") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.rsi = *enabled,
+            Some(_) => return Err("indicators.rsi: expected true or false".into()),
+        }
+        match values.get("indicators.risk") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.risk_metrics = *enabled,
+            Some(_) => return Err("indicators.risk: expected true or false".into()),
+        }
+        match values.get("benchmark") {
+            None => {}
+            Some(Value::String(ticker)) => config.benchmark = Some(ticker.clone()),
+            Some(_) => return Err("benchmark: expected a ticker string".into()),
+        }
+        match values.get("history.fetches_per_run") {
+            None => {}
+            Some(Value::Number(count)) if *count >= 0.0 => {
+                config.history_fetches_per_run = *count as usize
+            }
+            Some(_) => return Err("history.fetches_per_run: expected a non-negative number".into()),
+        }
+        match values.get("notify.eod") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.eod_notify = *enabled,
+            Some(_) => return Err("notify.eod: expected true or false".into()),
+        }
+        match values.get("notify.webhook") {
+            None => {}
+            Some(Value::String(url)) => config.notify_webhook = Some(url.clone()),
+            Some(_) => return Err("notify.webhook: expected a URL string".into()),
+        }
+        match values.get("daemon.interval") {
+            None => {}
+            Some(Value::Number(secs)) if *secs >= 1.0 => config.daemon_interval_secs = *secs as u64,
+            Some(_) => {
+                return Err("daemon.interval: expected seconds as a number of at least 1".into());
+            }
+        }
+        match values.get("daemon.metrics") {
+            None => {}
+            Some(Value::String(addr)) => config.metrics_listen = Some(addr.clone()),
+            Some(_) => {
+                return Err(
+                    "daemon.metrics: expected an address such as \"127.0.0.1:9184\"".into(),
+                );
+            }
+        }
+        let string = |key: &str| match values.get(key) {
+            None => Ok(None),
+            Some(Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(format!("{}: expected a string", key)),
+        };
+        config.mqtt_broker = string("mqtt.broker")?;
+        config.mqtt_username = string("mqtt.username")?;
+        if let Some(topic) = string("mqtt.topic")? {
+            config.mqtt_topic = topic;
+        }
+        if let Some(prefix) = string("mqtt.discovery_prefix")? {
+            config.mqtt_discovery_prefix = prefix;
+        }
+        if let Some(method) = string("transactions.cost_basis")? {
+            config.cost_basis = CostBasis::parse(&method).ok_or_else(|| {
+                format!(
+                    "transactions.cost_basis: unknown method '{}', expected fifo, lifo or average",
+                    method
+                )
+            })?;
+        }
+        if let Some(url) = string("links.quote")? {
+            config.quote_url = url;
+        }
+        config.portfolio_url = string("links.portfolio")?;
+        config.template = string("template.path")?.map(|path| data_dir().join(path));
+        match values.get("polybar.summary_only") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.polybar_summary_only = *enabled,
+            Some(_) => return Err("polybar.summary_only: expected true or false".into()),
+        }
+        match values.get("portfolio.skip_invalid_rows") {
+            None => {}
+            Some(Value::Bool(enabled)) => config.skip_invalid_rows = *enabled,
+            Some(_) => return Err("portfolio.skip_invalid_rows: expected true or false".into()),
+        }
+        match values.get("secrets.store") {
+            None => {}
+            Some(Value::String(store)) => config.secret_store = store.clone(),
+            Some(_) => return Err("secrets.store: expected a string".into()),
+        }
+        match values.get("secrets.command") {
+            None => {}
+            Some(Value::String(command)) => config.secret_command = Some(command.clone()),
+            Some(_) => return Err("secrets.command: expected a string".into()),
+        }
+
+        Ok(config)
+    }
+
+    /// Builds the configured secret store
+    pub fn secret_store(&self) -> Result<Box<dyn SecretStore>, Box<dyn Error + Send + Sync>> {
+        secrets::from_name(&self.secret_store, self.secret_command.as_deref())
+    }
+}
+
+/// Percent-encodes everything but letters, digits and `-._~`
+pub(crate) fn url_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
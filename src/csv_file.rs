@@ -0,0 +1,276 @@
+use csv::StringRecord;
+use std::error::Error;
+
+/// A portfolio or ledger CSV held as text rows, so edits keep every column
+/// the user added, the order of the rows and the `#` notes above the header
+pub struct CsvFile {
+    /// Comment lines before the header, newlines included
+    notes: String,
+    headers: StringRecord,
+    rows: Vec<StringRecord>,
+}
+
+impl CsvFile {
+    /// An empty positions file with the given columns
+    pub fn new(headers: &[&str]) -> CsvFile {
+        CsvFile {
+            notes: String::new(),
+            headers: StringRecord::from(headers.to_vec()),
+            rows: Vec::new(),
+        }
+    }
+
+    /// Parses CSV text; the first row that is not a `#` comment names the
+    /// columns
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::csv_file::CsvFile;
+    ///
+    /// let text = b"# My portfolio\nticker,buy_price,shares\n# sold soon\nAAPL.US,100,10\n";
+    /// let mut file = CsvFile::parse(text).unwrap();
+    /// file.push(&[("ticker", "PKN".to_string())]);
+    /// assert_eq!(
+    ///     file.to_bytes().unwrap(),
+    ///     b"# My portfolio\nticker,buy_price,shares\nAAPL.US,100,10\nPKN,,\n"
+    /// );
+    /// ```
+    pub fn parse(contents: &[u8]) -> Result<CsvFile, Box<dyn Error + Send + Sync>> {
+        let text = String::from_utf8_lossy(contents);
+        let notes: String = text
+            .split_inclusive('\n')
+            .take_while(|line| line.starts_with('#'))
+            .collect();
+        let mut reader = csv::ReaderBuilder::new()
+            .comment(Some(b'#'))
+            .from_reader(contents);
+        let headers = reader.headers()?.clone();
+        let rows = reader.records().collect::<Result<_, _>>()?;
+        Ok(CsvFile {
+            notes,
+            headers,
+            rows,
+        })
+    }
+
+    /// True for a `date,type,ticker,...` transactions ledger
+    pub fn is_ledger(&self) -> bool {
+        self.column("type").is_some()
+    }
+
+    /// Index of a column by case-insensitive name
+    pub fn column(&self, name: &str) -> Option<usize> {
+        self.headers
+            .iter()
+            .position(|header| header.trim().eq_ignore_ascii_case(name))
+    }
+
+    /// Appends a row, leaving columns not given blank and dropping values for
+    /// columns the file does not have
+    pub fn push(&mut self, fields: &[(&str, String)]) {
+        let mut row = vec![String::new(); self.headers.len()];
+        for (name, value) in fields {
+            if let Some(index) = self.column(name) {
+                row[index] = value.clone();
+            }
+        }
+        self.rows.push(StringRecord::from(row));
+    }
+
+    /// Adds shares bought at `price` to the first open lot of `ticker`,
+    /// averaging the buy price; false when there is no such lot
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::csv_file::CsvFile;
+    ///
+    /// let mut file = CsvFile::parse(b"ticker,buy_price,shares\nAAPL.US,100,10\n").unwrap();
+    /// assert!(file.merge_buy("aapl.us", 10.0, 200.0));
+    /// assert!(!file.merge_buy("MSFT.US", 1.0, 400.0));
+    /// assert_eq!(file.to_bytes().unwrap(), b"ticker,buy_price,shares\nAAPL.US,150,20\n");
+    /// ```
+    pub fn merge_buy(&mut self, ticker: &str, shares: f64, price: f64) -> bool {
+        let (Some(price_column), Some(shares_column)) =
+            (self.column("buy_price"), self.column("shares"))
+        else {
+            return false;
+        };
+        let Some(&index) = self.open_lots(ticker).first() else {
+            return false;
+        };
+        let row = &mut self.rows[index];
+        let number = |index: usize| {
+            row.get(index)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+        };
+        let (Some(old_price), Some(old_shares)) = (number(price_column), number(shares_column))
+        else {
+            return false;
+        };
+        let total = old_shares + shares;
+        let average = (old_price * old_shares + price * shares) / total;
+        *row = with(
+            row,
+            &[
+                (price_column, decimal(average)),
+                (shares_column, decimal(total)),
+            ],
+        );
+        true
+    }
+
+    /// Sells `shares` of `ticker` from its open lots, oldest row first, and
+    /// returns the profit made against their buy prices
+    ///
+    /// With `sell_price` and `sell_date` columns the sold shares become sold
+    /// lots, splitting a lot that is sold in part; without them the lots
+    /// just shrink and lots sold in full are dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::csv_file::CsvFile;
+    ///
+    /// let text = b"ticker,buy_price,shares,sell_price,sell_date\nAAPL.US,100,10,,\n";
+    /// let mut file = CsvFile::parse(text).unwrap();
+    /// assert_eq!(file.sell("AAPL.US", 4.0, 150.0, "2024-05-01").unwrap(), 200.0);
+    /// assert!(file.sell("AAPL.US", 7.0, 150.0, "2024-05-01").is_err());
+    /// assert_eq!(
+    ///     String::from_utf8(file.to_bytes().unwrap()).unwrap(),
+    ///     "ticker,buy_price,shares,sell_price,sell_date\nAAPL.US,100,6,,\nAAPL.US,100,4,150,2024-05-01\n"
+    /// );
+    /// ```
+    pub fn sell(
+        &mut self,
+        ticker: &str,
+        shares: f64,
+        price: f64,
+        date: &str,
+    ) -> Result<f64, Box<dyn Error + Send + Sync>> {
+        let (Some(price_column), Some(shares_column)) =
+            (self.column("buy_price"), self.column("shares"))
+        else {
+            return Err("the file has no buy_price and shares columns".into());
+        };
+        let sold_columns = self.column("sell_price").zip(self.column("sell_date"));
+        let open = self.open_lots(ticker);
+        let number = |row: &StringRecord, index: usize| {
+            row.get(index)
+                .and_then(|value| value.trim().parse::<f64>().ok())
+                .unwrap_or(0.0)
+        };
+        let held: f64 = open
+            .iter()
+            .map(|index| number(&self.rows[*index], shares_column))
+            .sum();
+        // Allow for rounding in fractional share counts
+        if shares > held + 1e-9 {
+            return Err(format!(
+                "selling {} shares of {} but only {} held",
+                decimal(shares),
+                ticker,
+                decimal(held)
+            )
+            .into());
+        }
+
+        let mut remaining = shares;
+        let mut profit = 0.0;
+        let mut rows = Vec::new();
+        for (index, row) in self.rows.iter().enumerate() {
+            if remaining <= 1e-9 || !open.contains(&index) {
+                rows.push(row.clone());
+                continue;
+            }
+            let lot = number(row, shares_column);
+            let taken = lot.min(remaining);
+            remaining -= taken;
+            profit += (price - number(row, price_column)) * taken;
+            let left = lot - taken;
+            if left > 1e-9 {
+                rows.push(with(row, &[(shares_column, decimal(left))]));
+            }
+            if let Some((sell_price, sell_date)) = sold_columns {
+                rows.push(with(
+                    row,
+                    &[
+                        (shares_column, decimal(taken)),
+                        (sell_price, decimal(price)),
+                        (sell_date, date.to_string()),
+                    ],
+                ));
+            }
+        }
+        self.rows = rows;
+        Ok(profit)
+    }
+
+    /// Drops every open lot of `ticker`, returning how many rows went
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::csv_file::CsvFile;
+    ///
+    /// let mut file = CsvFile::parse(b"ticker,buy_price,shares\nAAPL.US,100,10\nPKN,50,3\n").unwrap();
+    /// assert_eq!(file.remove("aapl.us"), 1);
+    /// assert_eq!(file.to_bytes().unwrap(), b"ticker,buy_price,shares\nPKN,50,3\n");
+    /// ```
+    pub fn remove(&mut self, ticker: &str) -> usize {
+        let open = self.open_lots(ticker);
+        let mut index = 0;
+        self.rows.retain(|_| {
+            index += 1;
+            !open.contains(&(index - 1))
+        });
+        open.len()
+    }
+
+    /// Indices of the rows of `ticker` that have not been sold
+    fn open_lots(&self, ticker: &str) -> Vec<usize> {
+        let Some(ticker_column) = self.column("ticker") else {
+            return Vec::new();
+        };
+        let sell_column = self.column("sell_price");
+        self.rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| {
+                row.get(ticker_column)
+                    .is_some_and(|value| value.trim().eq_ignore_ascii_case(ticker))
+                    && sell_column
+                        .is_none_or(|index| row.get(index).unwrap_or("").trim().is_empty())
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// The file as CSV text
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let mut writer = csv::Writer::from_writer(self.notes.as_bytes().to_vec());
+        writer.write_record(&self.headers)?;
+        for row in &self.rows {
+            writer.write_record(row)?;
+        }
+        Ok(writer.into_inner().map_err(|e| e.to_string())?)
+    }
+}
+
+/// A copy of `row` with the given columns replaced
+fn with(row: &StringRecord, values: &[(usize, String)]) -> StringRecord {
+    let mut fields: Vec<String> = row.iter().map(str::to_string).collect();
+    for (index, value) in values {
+        fields[*index] = value.clone();
+    }
+    StringRecord::from(fields)
+}
+
+/// A number without trailing zeros, to at most eight decimals
+pub fn decimal(value: f64) -> String {
+    format!("{:.8}", value)
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
@@ -0,0 +1,134 @@
+use crate::date::Date;
+use std::f64::consts::TAU;
+
+/// A sample portfolio in the CSV format, with two accounts, two currencies,
+/// cash, a watched ticker and a sold lot
+pub const PORTFOLIO: &str = "\
+ticker,buy_price,shares,currency,account,tags,apy,date,sell_price,sell_date
+AAPL.US,172.40,15,USD,Brokerage,tech,,2024-02-12,,
+MSFT.US,338.10,8,USD,Brokerage,tech,,2023-11-03,,
+NVDA.US,96.50,20,USD,Brokerage,tech,,2024-08-05,,
+KO.US,61.20,30,USD,IKE,dividend,,2024-05-20,,
+PKN,68.90,40,PLN,IKE,energy,,2024-09-16,,
+CDR,141.00,10,PLN,IKE,games,,2025-01-08,,
+CASH,1,2500,USD,Brokerage,,4.2,2025-06-01,,
+TSLA.US,0,0,USD,,,,,,
+KO.US,58.00,10,USD,IKE,dividend,,2023-06-01,63.40,2024-11-15
+";
+
+/// Units of each currency per US dollar, roughly, for exchange rates
+const PER_DOLLAR: &[(&str, f64)] = &[
+    ("USD", 1.0),
+    ("EUR", 0.92),
+    ("GBP", 0.79),
+    ("PLN", 3.95),
+    ("CHF", 0.88),
+    ("JPY", 150.0),
+    ("HKD", 7.8),
+    ("CAD", 1.37),
+];
+
+/// Typical prices of the sample tickers; others get one from their name
+const PRICES: &[(&str, f64)] = &[
+    ("AAPL.US", 215.0),
+    ("MSFT.US", 410.0),
+    ("NVDA.US", 120.0),
+    ("KO.US", 64.0),
+    ("TSLA.US", 240.0),
+    ("PKN", 62.0),
+    ("CDR", 150.0),
+    ("^SPX", 5600.0),
+];
+
+/// 64-bit FNV-1a, stable across builds so demo prices are reproducible
+fn hash(text: &str) -> u64 {
+    text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Pseudo-random number in `[-1, 1)` for a ticker on a day
+fn noise(seed: u64, day: i64) -> f64 {
+    // SplitMix64 finalizer
+    let mut z = seed.wrapping_add((day as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+}
+
+/// Synthetic price of `ticker` at a fractional day since the epoch: slow
+/// cycles around a typical price plus a little daily noise, so any day
+/// always gets the same close
+fn price_on(ticker: &str, day: f64) -> f64 {
+    let ticker = ticker.to_uppercase();
+    if let Some(rate) = exchange_rate(&ticker) {
+        return rate * (1.0 + 0.01 * (TAU * day / 97.0).sin());
+    }
+    let seed = hash(&ticker);
+    let typical = PRICES
+        .iter()
+        .find(|(known, _)| *known == ticker)
+        .map(|(_, price)| *price)
+        .unwrap_or(10.0 + (seed % 490) as f64);
+    let phase = (seed % 1000) as f64 / 1000.0 * TAU;
+    let trend = 0.15 * (TAU * day / 173.0 + phase).sin() + 0.06 * (TAU * day / 41.0 - phase).sin();
+    let today = noise(seed, day.floor() as i64);
+    let yesterday = noise(seed, day.floor() as i64 - 1);
+    // Blend into the day's noise as the day goes on, so intraday prices move
+    let jitter = yesterday + (today - yesterday) * day.fract();
+    let price = typical * (trend + 0.015 * jitter).exp();
+    (price * 100.0).round() / 100.0
+}
+
+/// Rate of a currency pair ticker such as `USDPLN`
+fn exchange_rate(ticker: &str) -> Option<f64> {
+    if ticker.len() != 6 {
+        return None;
+    }
+    let per_dollar = |code: &str| {
+        PER_DOLLAR
+            .iter()
+            .find(|(known, _)| *known == code)
+            .map(|(_, units)| *units)
+    };
+    Some(per_dollar(&ticker[3..])? / per_dollar(&ticker[..3])?)
+}
+
+/// The latest synthetic price of `ticker` at a Unix time
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::demo;
+///
+/// let price = demo::latest_price("AAPL.US", 1_750_000_000);
+/// assert_eq!(price, demo::latest_price("AAPL.US", 1_750_000_000));
+/// assert!(price > 100.0 && price < 400.0);
+/// assert!((demo::latest_price("USDPLN", 1_750_000_000) - 3.95).abs() < 0.1);
+/// ```
+pub fn latest_price(ticker: &str, unix: i64) -> f64 {
+    price_on(ticker, unix as f64 / 86_400.0)
+}
+
+/// Synthetic daily closes of `ticker` from `from` through `to`, weekdays
+/// only, oldest first
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::date::Date;
+/// use xbar_stocks::demo;
+///
+/// let from = Date::parse("2025-03-03").unwrap();
+/// let closes = demo::daily_closes("MSFT.US", from, from.add_days(13));
+/// assert_eq!(closes.len(), 10);
+/// assert_eq!(closes[0].0, from);
+/// ```
+pub fn daily_closes(ticker: &str, from: Date, to: Date) -> Vec<(Date, f64)> {
+    (from.days()..=to.days())
+        .map(Date::from_days)
+        .filter(|date| !date.is_weekend())
+        .map(|date| (date, price_on(ticker, date.days() as f64 + 0.9)))
+        .collect()
+}
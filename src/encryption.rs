@@ -0,0 +1,195 @@
+use crate::secrets::SecretStore;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Supported file encryption tools, chosen by file extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encryption {
+    /// `.age` files; the secret store holds the age identity (`AGE-SECRET-KEY-...`)
+    Age,
+    /// `.gpg` files encrypted symmetrically; the secret store holds the passphrase
+    Gpg,
+}
+
+impl Encryption {
+    /// Detects encryption from the file extension
+    pub fn for_path(path: &Path) -> Option<Encryption> {
+        match path.extension()?.to_str()? {
+            "age" => Some(Encryption::Age),
+            "gpg" => Some(Encryption::Gpg),
+            _ => None,
+        }
+    }
+
+    /// Secret store key holding the secret for this tool
+    pub fn secret_key(&self) -> &'static str {
+        match self {
+            Encryption::Age => "age",
+            Encryption::Gpg => "gpg",
+        }
+    }
+}
+
+/// Reads a file, transparently decrypting `.age` and `.gpg` files
+pub fn read_file(
+    path: &Path,
+    secrets: &dyn SecretStore,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let Some(encryption) = Encryption::for_path(path) else {
+        return Ok(fs::read(path)?);
+    };
+    if !path.exists() {
+        return Err(format!("{} does not exist", path.display()).into());
+    }
+
+    let secret = secret_for(encryption, secrets)?;
+    let path_str = path.to_string_lossy();
+    let mut command = match encryption {
+        // age reads the identity file from standard input with `-i -`
+        Encryption::Age => {
+            let mut command = Command::new("age");
+            command.args(["--decrypt", "-i", "-", &path_str]);
+            command
+        }
+        Encryption::Gpg => {
+            let mut command = Command::new("gpg");
+            command.args([
+                "--batch",
+                "--quiet",
+                "--pinentry-mode",
+                "loopback",
+                "--passphrase-fd",
+                "0",
+                "--decrypt",
+                &path_str,
+            ]);
+            command
+        }
+    };
+    run_with_secret(&mut command, &secret, None)
+}
+
+/// Writes a file, encrypting it first when the path ends in `.age` or `.gpg`
+///
+/// age files are encrypted to the recipient derived from the stored identity.
+pub fn write_file(
+    path: &Path,
+    contents: &[u8],
+    secrets: &dyn SecretStore,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    // Replace the symlink's target rather than the link, which a rename
+    // would swap for a plain file; a new file has nothing to resolve
+    let path = &fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    // Replace atomically so a failed write never leaves a truncated file
+    // behind; the temp file keeps the whole name (`data.csv.tmp`) so it
+    // cannot clash with a sibling such as `data.tmp`
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let Some(encryption) = Encryption::for_path(path) else {
+        fs::write(&tmp, contents)?;
+        fs::rename(tmp, path)?;
+        return Ok(());
+    };
+
+    let secret = secret_for(encryption, secrets)?;
+    let encrypted = match encryption {
+        Encryption::Age => {
+            let recipient = run_with_secret(Command::new("age-keygen").arg("-y"), &secret, None)?;
+            let recipient = String::from_utf8(recipient)?;
+            Command::new("age")
+                .args(["--encrypt", "-r", recipient.trim()])
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|e| format!("Failed to run age: {}", e))
+                .and_then(|child| feed(child, contents))?
+        }
+        Encryption::Gpg => {
+            // The passphrase goes through fd 0 on its own line, followed by the plaintext
+            run_with_secret(
+                Command::new("gpg").args([
+                    "--batch",
+                    "--quiet",
+                    "--yes",
+                    "--pinentry-mode",
+                    "loopback",
+                    "--passphrase-fd",
+                    "0",
+                    "--symmetric",
+                    "--output",
+                    "-",
+                ]),
+                &secret,
+                Some(contents),
+            )?
+        }
+    };
+
+    fs::write(&tmp, encrypted)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Fetches the decryption secret, failing clearly when none is stored
+fn secret_for(
+    encryption: Encryption,
+    secrets: &dyn SecretStore,
+) -> Result<String, Box<dyn Error + Send + Sync>> {
+    secrets.get(encryption.secret_key())?.ok_or_else(|| {
+        format!(
+            "No '{}' secret stored for decrypting the portfolio",
+            encryption.secret_key()
+        )
+        .into()
+    })
+}
+
+/// Runs a command with the secret (and optional payload) on stdin, returning stdout
+fn run_with_secret(
+    command: &mut Command,
+    secret: &str,
+    payload: Option<&[u8]>,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run {}: {}", program, e))?;
+
+    let mut input = format!("{}\n", secret).into_bytes();
+    if let Some(payload) = payload {
+        input.extend_from_slice(payload);
+    }
+    Ok(feed(child, &input)?)
+}
+
+/// Writes `input` to a child's stdin and collects its stdout, failing on a non-zero exit
+fn feed(mut child: std::process::Child, input: &[u8]) -> Result<Vec<u8>, String> {
+    let stdin = child.stdin.take();
+    // Writing from another thread while stdout is read keeps a child that
+    // answers before reading all of a large payload from blocking both sides
+    let (written, output) = std::thread::scope(|scope| {
+        let writer = scope.spawn(move || match stdin {
+            // Dropped at the end, closing the pipe
+            Some(mut stdin) => stdin.write_all(input),
+            None => Ok(()),
+        });
+        let output = child.wait_with_output();
+        (writer.join(), output)
+    });
+    let output = output.map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    written
+        .map_err(|_| "Writing to the child process panicked".to_string())?
+        .map_err(|e| e.to_string())?;
+    Ok(output.stdout)
+}
@@ -0,0 +1,151 @@
+use crate::cache::QuoteCache;
+use crate::date::unix_now;
+use crate::fetch_latest_price;
+use std::collections::HashMap;
+
+/// Rates are refreshed at most this often; currencies move slowly enough
+/// for portfolio totals
+const MAX_AGE_SECS: i64 = 3600;
+
+/// Stooq symbol of the rate converting `from` into `to`
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::fx::pair_ticker;
+///
+/// assert_eq!(pair_ticker("eur", "USD"), "EURUSD");
+/// ```
+pub fn pair_ticker(from: &str, to: &str) -> String {
+    format!("{}{}", from.to_uppercase(), to.to_uppercase())
+}
+
+/// Symbol written before amounts in a currency, falling back to the code
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::fx::symbol;
+///
+/// assert_eq!(symbol("EUR"), "€");
+/// assert_eq!(symbol("PLN"), "PLN ");
+/// ```
+pub fn symbol(currency: &str) -> String {
+    match currency.to_uppercase().as_str() {
+        "USD" => "$".to_string(),
+        "EUR" => "€".to_string(),
+        "GBP" => "£".to_string(),
+        "JPY" => "¥".to_string(),
+        other => format!("{} ", other),
+    }
+}
+
+/// Exchange rates into a base currency
+#[derive(Debug, Clone)]
+pub struct FxRates {
+    base: String,
+    rates: HashMap<String, f64>,
+}
+
+impl FxRates {
+    /// Rates where only the base currency is known
+    pub fn new(base: &str) -> FxRates {
+        FxRates {
+            base: base.to_uppercase(),
+            rates: HashMap::new(),
+        }
+    }
+
+    /// The base currency code
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// Sets the rate of `currency` into the base currency
+    pub fn with_rate(mut self, currency: &str, rate: f64) -> FxRates {
+        self.rates.insert(currency.to_uppercase(), rate);
+        self
+    }
+
+    /// Symbol written before converted amounts, the base currency's
+    ///
+    /// # Example
+    ///
+    /// Reporting in PLN, as with `--currency PLN`, changes both the amount
+    /// and its label:
+    ///
+    /// ```
+    /// use xbar_stocks::fx::FxRates;
+    ///
+    /// let fx = FxRates::new("pln").with_rate("USD", 4.0);
+    /// assert_eq!(fx.convert(25.0, "usd"), Some(100.0));
+    /// assert_eq!(fx.symbol(), "PLN ");
+    /// assert_eq!(FxRates::new("USD").symbol(), "$");
+    /// ```
+    pub fn symbol(&self) -> String {
+        symbol(&self.base)
+    }
+
+    /// Units of the base currency per unit of `currency`
+    pub fn rate(&self, currency: &str) -> Option<f64> {
+        let currency = currency.to_uppercase();
+        if currency == self.base {
+            return Some(1.0);
+        }
+        self.rates.get(&currency).copied()
+    }
+
+    /// Converts an amount into the base currency
+    pub fn convert(&self, amount: f64, currency: &str) -> Option<f64> {
+        self.rate(currency).map(|rate| amount * rate)
+    }
+
+    /// Loads the rates for `currencies` from the quote cache, fetching those
+    /// older than an hour unless `cached_only` is set
+    ///
+    /// A failed fetch falls back to the cached rate however old; currencies
+    /// with neither are left out, so [`FxRates::rate`] returns `None`.
+    pub fn load<'a>(
+        base: &str,
+        currencies: impl IntoIterator<Item = &'a str>,
+        cached_only: bool,
+    ) -> FxRates {
+        let mut fx = FxRates::new(base);
+        let mut cache = QuoteCache::default_location();
+        let now = unix_now();
+        let mut fetched_any = false;
+
+        for currency in currencies {
+            let currency = currency.to_uppercase();
+            if currency == fx.base || fx.rates.contains_key(&currency) {
+                continue;
+            }
+            let ticker = pair_ticker(&currency, &fx.base);
+            let cached = cache.get(&ticker).cloned();
+            let fresh = cached
+                .as_ref()
+                .is_some_and(|quote| now - quote.fetched_at < MAX_AGE_SECS);
+            let rate = if fresh || cached_only {
+                log::debug!("{}: rate from the quote cache", ticker);
+                cached.map(|quote| quote.price)
+            } else {
+                match fetch_latest_price(&ticker) {
+                    Ok(rate) => {
+                        cache.insert(&ticker, rate, now);
+                        fetched_any = true;
+                        Some(rate)
+                    }
+                    Err(_) => cached.map(|quote| quote.price),
+                }
+            };
+            if let Some(rate) = rate {
+                fx.rates.insert(currency, rate);
+            }
+        }
+
+        if fetched_any && let Err(e) = cache.save() {
+            eprintln!("Error saving quote cache: {}", e);
+        }
+        fx
+    }
+}
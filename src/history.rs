@@ -0,0 +1,228 @@
+use crate::data_dir;
+use crate::date::Date;
+use crate::fetch_daily_closes;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// Ticker used for the portfolio-wide row of a snapshot
+pub const TOTAL_TICKER: &str = "TOTAL";
+
+/// One row of an end-of-day portfolio snapshot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub date: Date,
+    pub ticker: String,
+    pub shares: f64,
+    /// Invested amount (buy price times shares)
+    pub cost: f64,
+    /// Price per share, empty for the total row and failed fetches
+    pub price: Option<f64>,
+    /// Market value, falling back to cost when the price is unknown
+    pub value: f64,
+}
+
+/// On-disk store of historical daily closes and portfolio snapshots
+///
+/// Closes live under `~/.stocks/history/closes/<ticker>.csv` with a
+/// `date,close` header and snapshots in `~/.stocks/history/snapshots.csv`,
+/// so both can be inspected or edited by hand.
+pub struct HistoryStore {
+    dir: PathBuf,
+}
+
+impl HistoryStore {
+    /// Creates a store rooted at the given directory
+    pub fn new(dir: PathBuf) -> HistoryStore {
+        HistoryStore { dir }
+    }
+
+    /// The default store location, `~/.stocks/history`
+    pub fn default_location() -> HistoryStore {
+        HistoryStore::new(data_dir().join("history"))
+    }
+
+    fn closes_path(&self, ticker: &str) -> PathBuf {
+        let name = ticker.to_lowercase().replace(['/', '\\'], "_");
+        self.dir.join("closes").join(format!("{}.csv", name))
+    }
+
+    /// Loads cached closes for a ticker, oldest first (empty if none are stored)
+    pub fn load_closes(
+        &self,
+        ticker: &str,
+    ) -> Result<Vec<(Date, f64)>, Box<dyn Error + Send + Sync>> {
+        let path = self.closes_path(ticker);
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut closes = Vec::new();
+        for record in reader.records() {
+            let record = record?;
+            let date = record.get(0).and_then(Date::parse);
+            let close = record.get(1).and_then(|c| c.parse::<f64>().ok());
+            if let (Some(date), Some(close)) = (date, close) {
+                closes.push((date, close));
+            }
+        }
+        closes.sort_by_key(|(date, _)| *date);
+        Ok(closes)
+    }
+
+    /// Replaces the cached closes for a ticker
+    pub fn save_closes(
+        &self,
+        ticker: &str,
+        closes: &[(Date, f64)],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let path = self.closes_path(ticker);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut writer = csv::Writer::from_path(path)?;
+        writer.write_record(["date", "close"])?;
+        for (date, close) in closes {
+            writer.write_record([date.to_string(), close.to_string()])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Tickers with cached closes, sorted
+    ///
+    /// File names are lowercased on save, so tickers come back uppercase.
+    pub fn close_tickers(&self) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let dir = self.dir.join("closes");
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut tickers = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "csv")
+                && let Some(stem) = path.file_stem().and_then(|stem| stem.to_str())
+            {
+                tickers.push(stem.to_uppercase());
+            }
+        }
+        tickers.sort();
+        Ok(tickers)
+    }
+
+    /// Time since the cached closes for a ticker were last written
+    pub fn closes_age(&self, ticker: &str) -> Option<Duration> {
+        let modified = fs::metadata(self.closes_path(ticker))
+            .ok()?
+            .modified()
+            .ok()?;
+        SystemTime::now().duration_since(modified).ok()
+    }
+
+    /// True when the cached closes are missing, older than `max_age`, or do
+    /// not reach back to `from`
+    pub fn needs_refresh(&self, ticker: &str, from: Date, max_age: Duration) -> bool {
+        let fresh = self.closes_age(ticker).is_some_and(|age| age <= max_age);
+        // Allow a week of slack for weekends, holidays and recent listings
+        let covers = self
+            .load_closes(ticker)
+            .ok()
+            .and_then(|cached| cached.first().map(|(first, _)| *first))
+            .is_some_and(|first| first.days() <= from.days() + 7);
+        !(fresh && covers)
+    }
+
+    /// Returns closes since `from`, fetching and caching them when the
+    /// cache is missing, older than `max_age`, or does not reach back far enough
+    pub fn closes(
+        &self,
+        ticker: &str,
+        from: Date,
+        max_age: Duration,
+    ) -> Result<Vec<(Date, f64)>, Box<dyn Error + Send + Sync>> {
+        let cached = self.load_closes(ticker)?;
+        if !self.needs_refresh(ticker, from, max_age) {
+            return Ok(cached
+                .into_iter()
+                .filter(|(date, _)| *date >= from)
+                .collect());
+        }
+
+        let fetched = fetch_daily_closes(ticker, from)?;
+        let merged = merge_closes(cached, fetched);
+        self.save_closes(ticker, &merged)?;
+        Ok(merged
+            .into_iter()
+            .filter(|(date, _)| *date >= from)
+            .collect())
+    }
+}
+
+impl HistoryStore {
+    fn snapshots_path(&self) -> PathBuf {
+        self.dir.join("snapshots.csv")
+    }
+
+    /// Loads every recorded snapshot row, oldest first
+    pub fn load_snapshots(&self) -> Result<Vec<Snapshot>, Box<dyn Error + Send + Sync>> {
+        let path = self.snapshots_path();
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut reader = csv::Reader::from_path(path)?;
+        let mut snapshots = Vec::new();
+        for result in reader.deserialize() {
+            let snapshot: Snapshot = result?;
+            snapshots.push(snapshot);
+        }
+        snapshots.sort_by_key(|snapshot| snapshot.date);
+        Ok(snapshots)
+    }
+
+    /// Records the snapshot rows for `date`, replacing any earlier rows for that day
+    pub fn record_snapshot(
+        &self,
+        date: Date,
+        rows: &[Snapshot],
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut snapshots = self.load_snapshots()?;
+        snapshots.retain(|snapshot| snapshot.date != date);
+        snapshots.extend(rows.iter().cloned());
+
+        let path = self.snapshots_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Write to a temporary file first so a crash never truncates the history
+        let tmp = path.with_extension("csv.tmp");
+        let mut writer = csv::Writer::from_path(&tmp)?;
+        for snapshot in &snapshots {
+            writer.serialize(snapshot)?;
+        }
+        writer.flush()?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Merges two close series, preferring values from `newer` on the same date
+fn merge_closes(older: Vec<(Date, f64)>, newer: Vec<(Date, f64)>) -> Vec<(Date, f64)> {
+    let mut merged: std::collections::BTreeMap<Date, f64> = older.into_iter().collect();
+    merged.extend(newer);
+    merged.into_iter().collect()
+}
+
+/// Returns the last close on or before `date`
+pub fn close_on_or_before(closes: &[(Date, f64)], date: Date) -> Option<f64> {
+    closes
+        .iter()
+        .rev()
+        .find(|(day, _)| *day <= date)
+        .map(|(_, close)| *close)
+}
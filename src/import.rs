@@ -0,0 +1,447 @@
+pub mod degiro;
+pub mod fidelity;
+pub mod ibkr;
+pub mod mbank;
+pub mod ofx;
+pub mod qif;
+pub mod revolut;
+pub mod schwab;
+pub mod trading212;
+pub mod xtb;
+
+use crate::data_dir;
+use crate::date::Date;
+use crate::transactions::{Kind, Transaction};
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Brokers and file formats whose exports can be imported
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Broker {
+    /// Interactive Brokers activity statement or Flex query CSV
+    Ibkr,
+    /// Degiro `Transactions.csv`
+    Degiro,
+    /// Revolut trading account statement
+    Revolut,
+    /// Trading212 history export
+    Trading212,
+    /// Charles Schwab positions export
+    Schwab,
+    /// Fidelity `Portfolio_Positions` export
+    Fidelity,
+    /// XTB cash operations history
+    Xtb,
+    /// mBank eMakler transaction history
+    Mbank,
+    /// Quicken Interchange Format investment account
+    Qif,
+    /// OFX or QFX investment statement
+    Ofx,
+}
+
+/// A holding read from a positions export
+#[derive(Debug, Clone, PartialEq)]
+pub struct Holding {
+    pub ticker: String,
+    pub shares: f64,
+    /// Average cost per share; 1 for cash, whose shares are the balance
+    pub buy_price: f64,
+}
+
+/// What an export holds: a transaction history or a snapshot of positions
+#[derive(Debug, Clone)]
+pub enum Import {
+    /// Ledger rows, oldest first
+    Transactions(Vec<Transaction>),
+    Holdings(Vec<Holding>),
+}
+
+impl Holding {
+    /// A cash balance, one share per unit of currency
+    pub fn cash(value: f64) -> Holding {
+        Holding {
+            ticker: "CASH".to_string(),
+            shares: value,
+            buy_price: 1.0,
+        }
+    }
+}
+
+impl Broker {
+    /// Parses a broker name as given on the command line
+    pub fn parse(name: &str) -> Option<Broker> {
+        match name.to_lowercase().as_str() {
+            "ibkr" | "interactive-brokers" => Some(Broker::Ibkr),
+            "degiro" => Some(Broker::Degiro),
+            "revolut" => Some(Broker::Revolut),
+            "trading212" | "t212" => Some(Broker::Trading212),
+            "schwab" => Some(Broker::Schwab),
+            "fidelity" => Some(Broker::Fidelity),
+            "xtb" => Some(Broker::Xtb),
+            "mbank" | "emakler" => Some(Broker::Mbank),
+            "qif" => Some(Broker::Qif),
+            "ofx" | "qfx" => Some(Broker::Ofx),
+            _ => None,
+        }
+    }
+
+    /// Reads an export
+    pub fn import(&self, contents: &[u8]) -> Result<Import, Box<dyn Error + Send + Sync>> {
+        let mut transactions = match self {
+            Broker::Ibkr => ibkr::parse(contents)?,
+            Broker::Degiro => degiro::parse(contents)?,
+            Broker::Revolut => revolut::parse(contents)?,
+            Broker::Trading212 => trading212::parse(contents)?,
+            Broker::Xtb => xtb::parse(contents)?,
+            Broker::Mbank => mbank::parse(contents)?,
+            Broker::Qif => qif::parse(contents)?,
+            Broker::Ofx => ofx::parse(contents)?,
+            Broker::Schwab => return Ok(Import::Holdings(schwab::parse(contents)?)),
+            Broker::Fidelity => return Ok(Import::Holdings(fidelity::parse(contents)?)),
+        };
+        transactions.sort_by_key(|transaction| transaction.date);
+        Ok(Import::Transactions(transactions))
+    }
+}
+
+/// Writes transactions as a ledger CSV that
+/// [`transactions::parse`](crate::transactions::parse) reads back
+pub fn write_ledger(
+    transactions: &[Transaction],
+    writer: impl Write,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record([
+        "date",
+        "type",
+        "ticker",
+        "shares",
+        "price",
+        "fee",
+        "amount",
+        "currency",
+        "reinvested",
+    ])?;
+    let optional = |value: Option<f64>| value.map(decimal).unwrap_or_default();
+    for transaction in transactions {
+        let (kind, shares, price) = match transaction.kind {
+            Kind::Buy => ("buy", Some(transaction.shares), Some(transaction.price)),
+            Kind::Sell => ("sell", Some(transaction.shares), Some(transaction.price)),
+            // A reinvested dividend keeps the shares it bought when known
+            Kind::Dividend if transaction.shares > 0.0 => (
+                "dividend",
+                Some(transaction.shares),
+                Some(transaction.price),
+            ),
+            Kind::Dividend => ("dividend", None, None),
+        };
+        writer.write_record([
+            transaction.date.to_string(),
+            kind.to_string(),
+            transaction.ticker.clone(),
+            optional(shares),
+            optional(price),
+            optional(transaction.fee),
+            optional(transaction.amount),
+            transaction.currency.clone().unwrap_or_default(),
+            transaction.reinvested.to_string(),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes holdings as a `ticker,buy_price,shares` portfolio CSV
+pub fn write_positions(
+    holdings: &[Holding],
+    writer: impl Write,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["ticker", "buy_price", "shares"])?;
+    for holding in holdings {
+        writer.write_record([
+            holding.ticker.clone(),
+            decimal(holding.buy_price),
+            decimal(holding.shares),
+        ])?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// A number rounded to drop the float noise of converted fees and averages
+fn decimal(value: f64) -> String {
+    format!("{:.8}", value)
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+/// Default ISIN table location, `~/.stocks/isins.csv`
+pub fn isins_path() -> PathBuf {
+    data_dir().join("isins.csv")
+}
+
+/// Loads an `isin,ticker` table; a missing file means no mappings
+pub fn load_isins(path: &Path) -> Result<HashMap<String, String>, Box<dyn Error + Send + Sync>> {
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let contents = fs::read(path)?;
+    let mut reader = csv::Reader::from_reader(contents.as_slice());
+    let mut isins = HashMap::new();
+    for record in reader.records() {
+        let record = record?;
+        if let (Some(isin), Some(ticker)) = (record.get(0), record.get(1)) {
+            isins.insert(isin.trim().to_uppercase(), ticker.trim().to_string());
+        }
+    }
+    Ok(isins)
+}
+
+/// Replaces ISINs, and any other broker symbol the table lists, with
+/// tickers from the table, returning the ISINs that have no mapping
+pub fn apply_symbols(
+    transactions: &mut [Transaction],
+    isins: &HashMap<String, String>,
+) -> Vec<String> {
+    let mut unmapped = Vec::new();
+    for transaction in transactions {
+        match isins.get(&transaction.ticker.to_uppercase()) {
+            Some(ticker) => transaction.ticker = ticker.clone(),
+            None if is_isin(&transaction.ticker) && !unmapped.contains(&transaction.ticker) => {
+                unmapped.push(transaction.ticker.clone())
+            }
+            None => {}
+        }
+    }
+    unmapped
+}
+
+/// True for a 12-character ISIN such as `US0378331005`
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::is_isin;
+///
+/// assert!(is_isin("US0378331005"));
+/// assert!(!is_isin("AAPL.US"));
+/// ```
+pub fn is_isin(text: &str) -> bool {
+    text.len() == 12
+        && text.is_ascii()
+        && text[..2].chars().all(|c| c.is_ascii_alphabetic())
+        && text[2..].chars().all(|c| c.is_ascii_alphanumeric())
+        && text[11..].chars().all(|c| c.is_ascii_digit())
+}
+
+/// Stooq symbol for a broker symbol, from its listing exchange when known
+/// and otherwise its trading currency
+///
+/// Warsaw listings have no suffix on stooq; symbols the broker writes with a
+/// space, dot or slash for the share class use a dash.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::stooq_symbol;
+///
+/// assert_eq!(stooq_symbol("BRK B", "USD", None), "BRK-B.US");
+/// assert_eq!(stooq_symbol("VUSA", "EUR", Some("LSE")), "VUSA.UK");
+/// assert_eq!(stooq_symbol("PKN", "PLN", None), "PKN");
+/// ```
+pub fn stooq_symbol(symbol: &str, currency: &str, exchange: Option<&str>) -> String {
+    let symbol = symbol.trim().to_uppercase().replace([' ', '.', '/'], "-");
+    let by_exchange = exchange.and_then(|exchange| match exchange.trim().to_uppercase().as_str() {
+        "NASDAQ" | "NYSE" | "ARCA" | "AMEX" | "BATS" | "NYSEARCA" | "NMS" => Some("US"),
+        "LSE" | "LSEETF" => Some("UK"),
+        "IBIS" | "IBIS2" | "XETRA" | "FWB" | "GETTEX" => Some("DE"),
+        "TSEJ" | "TSE" => Some("JP"),
+        "SEHK" => Some("HK"),
+        "WSE" | "GPW" | "WWA-GPW" | "WWA-NC" => Some(""),
+        _ => None,
+    });
+    let suffix = by_exchange.unwrap_or(match currency.trim().to_uppercase().as_str() {
+        "USD" => "US",
+        "GBP" => "UK",
+        "EUR" => "DE",
+        "JPY" => "JP",
+        "HKD" => "HK",
+        _ => "",
+    });
+    if suffix.is_empty() {
+        symbol
+    } else {
+        format!("{}.{}", symbol, suffix)
+    }
+}
+
+/// Stooq symbol for a US broker's symbol, without the markers some brokers
+/// append (Fidelity's `**` on money-market funds)
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::clean_symbol;
+///
+/// assert_eq!(clean_symbol(" brk/b* "), "BRK-B.US");
+/// ```
+pub fn clean_symbol(symbol: &str) -> String {
+    stooq_symbol(symbol.trim().trim_end_matches('*'), "USD", None)
+}
+
+/// A CSV reader over an export separated by `;` or `,`, whichever the
+/// header line uses
+pub(crate) fn delimited(contents: &[u8]) -> csv::Reader<&[u8]> {
+    let header = contents.split(|byte| *byte == b'\n').next().unwrap_or(&[]);
+    let delimiter = if header.contains(&b';') { b';' } else { b',' };
+    csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .from_reader(contents)
+}
+
+/// Header of an exported table, looked up by column name
+pub(crate) struct Columns(Vec<String>);
+
+impl Columns {
+    pub(crate) fn new<'a>(names: impl IntoIterator<Item = &'a str>) -> Columns {
+        Columns(
+            names
+                .into_iter()
+                .map(|name| name.trim().to_lowercase())
+                .collect(),
+        )
+    }
+
+    /// The field under the first of `names` the header has
+    pub(crate) fn get<'a>(&self, record: &'a StringRecord, names: &[&str]) -> Option<&'a str> {
+        let index = names
+            .iter()
+            .find_map(|name| self.0.iter().position(|column| column == name))?;
+        record.get(index).map(str::trim)
+    }
+
+    /// Like [`Columns::get`] but fails naming the column
+    pub(crate) fn require<'a>(
+        &self,
+        record: &'a StringRecord,
+        names: &[&str],
+    ) -> Result<&'a str, Box<dyn Error + Send + Sync>> {
+        self.get(record, names)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| format!("missing {}", names[0]).into())
+    }
+}
+
+/// Parses a number in either the English or the continental style
+///
+/// Whichever of `.` and `,` comes last is the decimal separator. Without a
+/// `.`, commas group thousands when there are several or when one is
+/// followed by exactly three digits, as in US exports; otherwise a lone `,`
+/// is decimal.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::localized_number;
+///
+/// assert_eq!(localized_number("1.234,5").unwrap(), 1234.5);
+/// assert_eq!(localized_number("-150,25").unwrap(), -150.25);
+/// assert_eq!(localized_number("1,234.5").unwrap(), 1234.5);
+/// assert_eq!(localized_number("1,500").unwrap(), 1500.0);
+/// assert_eq!(localized_number("1,234,567").unwrap(), 1234567.0);
+/// assert_eq!(localized_number("0,5").unwrap(), 0.5);
+/// ```
+pub fn localized_number(text: &str) -> Result<f64, Box<dyn Error + Send + Sync>> {
+    let cleaned: String = text
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '\'')
+        .collect();
+    let normalized = match (cleaned.rfind(','), cleaned.rfind('.')) {
+        (Some(comma), Some(dot)) if comma > dot => cleaned.replace('.', "").replace(',', "."),
+        (Some(_), Some(_)) => cleaned.replace(',', ""),
+        (Some(comma), None) => {
+            let grouped = cleaned.matches(',').count() > 1
+                || cleaned[comma + 1..].len() == 3
+                    && cleaned[comma + 1..].chars().all(|c| c.is_ascii_digit());
+            if grouped {
+                cleaned.replace(',', "")
+            } else {
+                cleaned.replace(',', ".")
+            }
+        }
+        _ => cleaned,
+    };
+    normalized
+        .parse()
+        .map_err(|_| format!("invalid number '{}'", text).into())
+}
+
+/// Parses a number from a European export, where a lone `,` is always the
+/// decimal separator; numbers with a `.` are read as [`localized_number`]
+/// does
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::comma_decimal_number;
+///
+/// assert_eq!(comma_decimal_number("42,150").unwrap(), 42.15);
+/// assert_eq!(comma_decimal_number("1.234,5").unwrap(), 1234.5);
+/// assert_eq!(comma_decimal_number("1,234.5").unwrap(), 1234.5);
+/// assert_eq!(comma_decimal_number("12.5").unwrap(), 12.5);
+/// ```
+pub fn comma_decimal_number(text: &str) -> Result<f64, Box<dyn Error + Send + Sync>> {
+    if text.contains('.') {
+        return localized_number(text);
+    }
+    localized_number(&text.replace(',', "."))
+        .map_err(|_| format!("invalid number '{}'", text).into())
+}
+
+/// An amount with its currency code or symbol stripped, as in `$1,500.00`
+/// or `USD 150.50`
+pub(crate) fn money(text: &str) -> Result<f64, Box<dyn Error + Send + Sync>> {
+    let number: String = text
+        .chars()
+        .filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',' | '-'))
+        .collect();
+    localized_number(&number).map_err(|_| format!("invalid amount '{}'", text).into())
+}
+
+/// Parses a European `DD-MM-YYYY` date (or with `.` or `/`), ignoring a
+/// trailing time; ISO dates are accepted too
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::day_first_date;
+///
+/// let date = day_first_date("02.01.2024 15:31:05").unwrap();
+/// assert_eq!(date.to_string(), "2024-01-02");
+/// ```
+pub fn day_first_date(text: &str) -> Result<Date, Box<dyn Error + Send + Sync>> {
+    let day = text.split_whitespace().next().unwrap_or("");
+    let parts: Vec<&str> = day.split(['-', '/', '.']).collect();
+    let (year, month, day) = match parts.as_slice() {
+        [year, month, day] if year.len() == 4 => (year, month, day),
+        [day, month, year] => (year, month, day),
+        _ => return Err(format!("invalid date '{}'", text).into()),
+    };
+    let date = || Date::new(year.parse().ok()?, month.parse().ok()?, day.parse().ok()?);
+    date().ok_or_else(|| format!("invalid date '{}'", text).into())
+}
+
+/// Parses a number written with `,` thousands separators
+pub(crate) fn number(text: &str) -> Result<f64, Box<dyn Error + Send + Sync>> {
+    text.replace(',', "")
+        .parse()
+        .map_err(|_| format!("invalid number '{}'", text).into())
+}
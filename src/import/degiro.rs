@@ -0,0 +1,119 @@
+use super::{comma_decimal_number, day_first_date};
+use crate::transactions::{Kind, Transaction};
+use std::error::Error;
+
+/// Parses Degiro's `Transactions.csv` in any of its interface languages
+///
+/// The leading columns are the same in every version of the export (date,
+/// time, product, ISIN, exchange, venue, quantity, price and its currency);
+/// the fee and exchange rate columns move around and are found by their
+/// header. Degiro exports no ticker, so rows carry the ISIN as the ticker
+/// until mapped with [`super::apply_symbols`]. Fees charged in the account
+/// currency are converted at the row's exchange rate.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::degiro;
+/// use xbar_stocks::transactions::Kind;
+///
+/// let export = br#"Date,Time,Product,ISIN,Reference exchange,Venue,Quantity,Price,,Local value,,Value,,Exchange rate,Transaction and/or third party fees,,Total,,Order ID
+/// 04-03-2024,15:31,APPLE INC. - COMMON ST,US0378331005,NDQ,XNAS,5,"170,12",USD,"-850,60",USD,"-783,72",EUR,"1,0853",-1,EUR,"-784,72",EUR,3c9e1a52-7d4f-4e0b-9a61-0f2a8d6b1c11
+/// 15-05-2024,09:04,ASML HOLDING,NL0010273215,EAM,XAMS,-2,"872,40",EUR,"1744,80",EUR,"1744,80",EUR,,"-4,90",EUR,"1739,90",EUR,5b7d2e91-0c3a-4f16-8e2d-3a9b4c5d6e7f
+/// "#;
+/// let transactions = degiro::parse(export).unwrap();
+/// assert_eq!(transactions.len(), 2);
+/// let apple = &transactions[0];
+/// assert_eq!((apple.kind, apple.ticker.as_str()), (Kind::Buy, "US0378331005"));
+/// assert_eq!((apple.shares, apple.price), (5.0, 170.12));
+/// assert_eq!(apple.date.to_string(), "2024-03-04");
+/// assert_eq!(apple.currency.as_deref(), Some("USD"));
+/// // The EUR fee in dollars
+/// assert!((apple.fee.unwrap() - 1.0853).abs() < 1e-9);
+/// let asml = &transactions[1];
+/// assert_eq!((asml.kind, asml.shares, asml.price), (Kind::Sell, 2.0, 872.4));
+/// assert_eq!(asml.fee, Some(4.9));
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents);
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .collect();
+    let find = |prefixes: &[&str]| {
+        headers
+            .iter()
+            .position(|name| prefixes.iter().any(|prefix| name.starts_with(prefix)))
+    };
+    let fee_column = find(&[
+        "transaction",
+        "transactiekosten",
+        "transaktionskosten",
+        "frais",
+        "costes",
+        "commissioni",
+    ]);
+    let rate_column = find(&[
+        "exchange rate",
+        "wisselkoers",
+        "wechselkurs",
+        "taux de change",
+        "tipo de cambio",
+        "tasso di cambio",
+    ]);
+
+    let mut transactions = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let field = |i: usize| record.get(i).unwrap_or("").trim();
+        let row = || -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
+            let quantity = comma_decimal_number(field(6))?;
+            if quantity == 0.0 {
+                return Ok(None);
+            }
+            let currency = field(8);
+            let mut fee = fee_column
+                .map(&field)
+                .filter(|fee| !fee.is_empty())
+                .map(comma_decimal_number)
+                .transpose()?
+                .map(f64::abs);
+            // The fee's currency sits in the unnamed column after it
+            let fee_currency = fee_column.map(|column| field(column + 1)).unwrap_or("");
+            if let (Some(amount), Some(rate)) = (fee, rate_column.map(&field))
+                && !fee_currency.is_empty()
+                && fee_currency != currency
+                && !rate.is_empty()
+            {
+                fee = Some(amount * comma_decimal_number(rate)?);
+            }
+            let isin = field(3);
+            if isin.is_empty() {
+                return Err("missing ISIN".into());
+            }
+            Ok(Some(Transaction {
+                date: day_first_date(field(0))?,
+                kind: if quantity < 0.0 {
+                    Kind::Sell
+                } else {
+                    Kind::Buy
+                },
+                ticker: isin.to_string(),
+                shares: quantity.abs(),
+                price: comma_decimal_number(field(7))?,
+                fee,
+                amount: None,
+                reinvested: false,
+                currency: (!currency.is_empty()).then(|| currency.to_string()),
+            }))
+        };
+        // Line numbers count the header
+        if let Some(transaction) = row().map_err(|e| format!("line {}: {}", index + 2, e))? {
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
@@ -0,0 +1,72 @@
+use super::{Columns, Holding, clean_symbol, money, number};
+use std::error::Error;
+
+/// Parses a Fidelity `Portfolio_Positions` export
+///
+/// Money-market funds, which Fidelity marks with trailing asterisks (e.g.
+/// `SPAXX**`), become `CASH` balances. Pending activity, options and the
+/// disclaimer text at the end of the file are skipped.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::{Holding, fidelity};
+///
+/// let export = br#"Account Number,Account Name,Symbol,Description,Quantity,Last Price,Last Price Change,Current Value,Today's Gain/Loss Dollar,Today's Gain/Loss Percent,Total Gain/Loss Dollar,Total Gain/Loss Percent,Percent Of Account,Cost Basis Total,Average Cost Basis,Type
+/// Z12345678,Individual,SPAXX**,HELD IN MONEY MARKET,,,,"$1,523.45",,,,,35.12%,,,Cash,
+/// Z12345678,Individual,AAPL,APPLE INC,10,$227.52,+$1.20,"$2,275.20",+$12.00,+0.53%,+$420.20,+22.65%,52.45%,"$1,855.00",$185.50,Cash,
+/// Z12345678,Individual,BRK/B,BERKSHIRE HATHAWAY INC CL B,1,$470.01,-$0.35,$470.01,-$0.35,-0.07%,--,--,10.83%,--,--,Cash,
+/// Z12345678,Individual,Pending Activity,,,,,-$25.00,,,,,,,,,
+///
+/// "The data and information in this spreadsheet is provided to you solely for your use."
+/// "Date downloaded 06/03/2024 4:05 PM ET"
+/// "#;
+/// let holdings = fidelity::parse(export).unwrap();
+/// assert_eq!(
+///     holdings,
+///     vec![
+///         Holding::cash(1523.45),
+///         Holding { ticker: "AAPL.US".into(), shares: 10.0, buy_price: 185.5 },
+///         // No cost basis, so the last price
+///         Holding { ticker: "BRK-B.US".into(), shares: 1.0, buy_price: 470.01 },
+///     ]
+/// );
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Holding>, Box<dyn Error + Send + Sync>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents);
+    let columns = Columns::new(reader.headers()?.iter());
+
+    let mut holdings = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let row = || -> Result<Option<Holding>, Box<dyn Error + Send + Sync>> {
+            let symbol = columns.get(&record, &["symbol"]).unwrap_or("");
+            // Option symbols start with a dash, e.g. -AAPL240119C150
+            if symbol.is_empty() || symbol == "Pending Activity" || symbol.starts_with('-') {
+                return Ok(None);
+            }
+            if symbol.ends_with('*') {
+                return Ok(Some(Holding::cash(money(
+                    columns.require(&record, &["current value"])?,
+                )?)));
+            }
+            let shares = number(columns.require(&record, &["quantity"])?)?;
+            let buy_price = match columns.get(&record, &["average cost basis"]) {
+                Some(cost) if money(cost).is_ok() => money(cost)?,
+                _ => money(columns.require(&record, &["last price"])?)?,
+            };
+            Ok(Some(Holding {
+                ticker: clean_symbol(symbol),
+                shares,
+                buy_price,
+            }))
+        };
+        // Line numbers count the header
+        if let Some(holding) = row().map_err(|e| format!("line {}: {}", index + 2, e))? {
+            holdings.push(holding);
+        }
+    }
+    Ok(holdings)
+}
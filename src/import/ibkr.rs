@@ -0,0 +1,242 @@
+use super::{Columns, number, stooq_symbol};
+use crate::date::Date;
+use crate::transactions::{Kind, Transaction};
+use csv::StringRecord;
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Parses an Interactive Brokers export: either an activity statement, where
+/// every row starts with its section name, or a single-table Flex query
+///
+/// Stock trades and cash dividends are read; other asset classes, fees
+/// charged outside trades and withholding tax are left out.
+///
+/// # Example
+///
+/// A trimmed activity statement; the listing exchange comes from the
+/// instrument section at the end.
+///
+/// ```
+/// use xbar_stocks::import::ibkr;
+/// use xbar_stocks::transactions::Kind;
+///
+/// let statement = br#"Statement,Header,Field Name,Field Value
+/// Statement,Data,Period,"January 1, 2024 - June 30, 2024"
+/// Trades,Header,DataDiscriminator,Asset Category,Currency,Symbol,Date/Time,Quantity,T. Price,C. Price,Proceeds,Comm/Fee,Basis,Realized P/L,MTM P/L,Code
+/// Trades,Data,Order,Stocks,USD,AAPL,"2024-01-02, 10:15:00",10,185.5,185.64,-1855,-1,1856,0,1.4,O
+/// Trades,Data,Order,Stocks,USD,AAPL,"2024-06-03, 15:02:11",-4,194.1,194.03,776.4,-1.02,-742.4,32.98,-0.28,C
+/// Trades,SubTotal,,Stocks,USD,AAPL,,6,,,-1078.6,-2.02,1113.6,32.98,1.12,
+/// Trades,Data,Order,Equity and Index Options,USD,AAPL 21JUN24 200 C,"2024-02-05, 11:00:00",1,3.1,3.05,-310,-0.65,310.65,0,-5,O
+/// Dividends,Header,Currency,Date,Description,Amount
+/// Dividends,Data,USD,2024-05-16,AAPL(US0378331005) Cash Dividend USD 0.25 per Share (Ordinary Dividend),2.5
+/// Dividends,Data,Total,,,2.5
+/// Financial Instrument Information,Header,Asset Category,Symbol,Description,Conid,Security ID,Listing Exch,Multiplier,Type,Code
+/// Financial Instrument Information,Data,Stocks,AAPL,APPLE INC,265598,US0378331005,NASDAQ,1,COMMON,
+/// "#;
+/// let transactions = ibkr::parse(statement).unwrap();
+/// assert_eq!(transactions.len(), 3);
+/// let buy = &transactions[0];
+/// assert_eq!((buy.kind, buy.ticker.as_str()), (Kind::Buy, "AAPL.US"));
+/// assert_eq!((buy.shares, buy.price, buy.fee), (10.0, 185.5, Some(1.0)));
+/// assert_eq!(buy.date.to_string(), "2024-01-02");
+/// let sell = &transactions[1];
+/// assert_eq!((sell.kind, sell.shares, sell.fee), (Kind::Sell, 4.0, Some(1.02)));
+/// let dividend = &transactions[2];
+/// assert_eq!((dividend.kind, dividend.ticker.as_str()), (Kind::Dividend, "AAPL.US"));
+/// assert_eq!(dividend.amount, Some(2.5));
+///
+/// let flex = b"ClientAccountID,AssetClass,Symbol,ListingExchange,CurrencyPrimary,TradeDate,Quantity,TradePrice,IBCommission,Buy/Sell
+/// U1234567,STK,VUSA,LSEETF,GBP,20240312,15,78.42,-1.7,BUY
+/// U1234567,OPT,SPY   240621C00500000,CBOE,USD,20240312,1,4.2,-0.65,BUY
+/// ";
+/// let transactions = ibkr::parse(flex).unwrap();
+/// assert_eq!(transactions.len(), 1);
+/// assert_eq!(transactions[0].ticker, "VUSA.UK");
+/// assert_eq!(transactions[0].currency.as_deref(), Some("GBP"));
+/// assert_eq!((transactions[0].shares, transactions[0].fee), (15.0, Some(1.7)));
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let records = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents)
+        .records()
+        .collect::<Result<Vec<_>, _>>()?;
+    match records.first() {
+        Some(first) if first.get(1) == Some("Header") => activity_statement(&records),
+        Some(_) => flex_query(&records),
+        None => Err("the export is empty".into()),
+    }
+}
+
+fn activity_statement(
+    records: &[StringRecord],
+) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let mut headers: HashMap<&str, Columns> = HashMap::new();
+    let mut exchanges: HashMap<String, String> = HashMap::new();
+    let mut transactions = Vec::new();
+
+    for (index, record) in records.iter().enumerate() {
+        let section = record.get(0).unwrap_or("");
+        // Drop the section and row kind so fields line up with the header
+        let fields: StringRecord = record.iter().skip(2).collect();
+        match record.get(1) {
+            Some("Header") => {
+                headers.insert(section, Columns::new(fields.iter()));
+                continue;
+            }
+            Some("Data") => {}
+            _ => continue,
+        }
+        let Some(columns) = headers.get(section) else {
+            continue;
+        };
+        let transaction = match section {
+            "Financial Instrument Information" => {
+                if let (Some(symbol), Some(exchange)) = (
+                    columns.get(&fields, &["symbol"]),
+                    columns.get(&fields, &["listing exch"]),
+                ) {
+                    exchanges.insert(symbol.to_string(), exchange.to_string());
+                }
+                continue;
+            }
+            "Trades" => {
+                // Subtotal and total rows repeat the orders' sums
+                let order = columns
+                    .get(&fields, &["datadiscriminator"])
+                    .is_none_or(|kind| kind == "Order");
+                let stock = columns.get(&fields, &["asset category"]) == Some("Stocks");
+                if !order || !stock {
+                    continue;
+                }
+                trade(columns, &fields, &["date/time"])
+            }
+            "Dividends" => {
+                if columns
+                    .get(&fields, &["currency"])
+                    .is_some_and(|currency| currency.starts_with("Total"))
+                {
+                    continue;
+                }
+                dividend(columns, &fields, &["date"])
+            }
+            _ => continue,
+        };
+        transactions.push(transaction.map_err(|e| format!("line {}: {}", index + 1, e))?);
+    }
+
+    // Instruments are listed after the trades, so symbols are mapped last
+    for transaction in &mut transactions {
+        let currency = transaction.currency.as_deref().unwrap_or("");
+        let exchange = exchanges.get(&transaction.ticker).map(String::as_str);
+        transaction.ticker = stooq_symbol(&transaction.ticker, currency, exchange);
+    }
+    Ok(transactions)
+}
+
+fn flex_query(records: &[StringRecord]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let columns = Columns::new(records[0].iter());
+    let mut transactions = Vec::new();
+    for (index, record) in records.iter().enumerate().skip(1) {
+        if let Some(class) = columns.get(record, &["assetclass", "asset class"])
+            && class != "STK"
+        {
+            continue;
+        }
+        let transaction = match columns.get(record, &["type"]) {
+            Some("Dividends") => dividend(&columns, record, &["settledate", "datetime", "date"]),
+            // Other cash transactions such as withholding tax or interest
+            Some(_) if columns.get(record, &["quantity"]).is_none() => continue,
+            _ => trade(&columns, record, &["tradedate", "datetime", "date/time"]),
+        };
+        let mut transaction = transaction.map_err(|e| format!("line {}: {}", index + 1, e))?;
+        let currency = transaction.currency.clone().unwrap_or_default();
+        let exchange = columns.get(record, &["listingexchange", "exchange"]);
+        transaction.ticker = stooq_symbol(&transaction.ticker, &currency, exchange);
+        transactions.push(transaction);
+    }
+    Ok(transactions)
+}
+
+/// A buy or sell from a trade row; IBKR signs the quantity by direction and
+/// the commission as a cost
+fn trade(
+    columns: &Columns,
+    record: &StringRecord,
+    date_columns: &[&str],
+) -> Result<Transaction, Box<dyn Error + Send + Sync>> {
+    let quantity = number(columns.require(record, &["quantity"])?)?;
+    let kind = match columns.get(record, &["buy/sell"]) {
+        Some("SELL") => Kind::Sell,
+        Some("BUY") => Kind::Buy,
+        _ if quantity < 0.0 => Kind::Sell,
+        _ => Kind::Buy,
+    };
+    let fee = columns
+        .get(record, &["comm/fee", "ibcommission", "commission"])
+        .filter(|fee| !fee.is_empty())
+        .map(number)
+        .transpose()?
+        .map(f64::abs);
+    Ok(Transaction {
+        date: date(columns.require(record, date_columns)?)?,
+        kind,
+        ticker: columns.require(record, &["symbol"])?.to_string(),
+        shares: quantity.abs(),
+        price: number(columns.require(record, &["t. price", "tradeprice", "price"])?)?,
+        fee,
+        amount: None,
+        reinvested: false,
+        currency: Some(
+            columns
+                .require(record, &["currency", "currencyprimary"])?
+                .to_string(),
+        ),
+    })
+}
+
+/// A cash dividend; activity statements name the stock only in the
+/// description, as in `AAPL(US0378331005) Cash Dividend ...`
+fn dividend(
+    columns: &Columns,
+    record: &StringRecord,
+    date_columns: &[&str],
+) -> Result<Transaction, Box<dyn Error + Send + Sync>> {
+    let ticker = match columns.get(record, &["symbol"]) {
+        Some(symbol) if !symbol.is_empty() => symbol,
+        _ => {
+            let description = columns.require(record, &["description"])?;
+            description.split(['(', ' ']).next().unwrap_or(description)
+        }
+    };
+    Ok(Transaction {
+        date: date(columns.require(record, date_columns)?)?,
+        kind: Kind::Dividend,
+        ticker: ticker.to_string(),
+        shares: 0.0,
+        price: 0.0,
+        fee: None,
+        amount: Some(number(columns.require(record, &["amount"])?)?),
+        reinvested: false,
+        currency: Some(
+            columns
+                .require(record, &["currency", "currencyprimary"])?
+                .to_string(),
+        ),
+    })
+}
+
+/// Date of `2024-01-02, 10:15:00`, `20240102;101500` and similar stamps
+fn date(text: &str) -> Result<Date, Box<dyn Error + Send + Sync>> {
+    let day = text.split([',', ';', ' ']).next().unwrap_or(text);
+    let parsed = match day.len() {
+        8 if day.is_ascii() => Date::new(
+            day[..4].parse().unwrap_or(0),
+            day[4..6].parse().unwrap_or(0),
+            day[6..].parse().unwrap_or(0),
+        ),
+        _ => Date::parse(day),
+    };
+    parsed.ok_or_else(|| format!("invalid date '{}'", text).into())
+}
@@ -0,0 +1,154 @@
+use super::{Columns, comma_decimal_number, day_first_date, delimited, stooq_symbol};
+use crate::transactions::{Kind, Transaction};
+use std::error::Error;
+
+/// GPW short names, as mBank lists instruments, for their stooq tickers
+const SHORT_NAMES: &[(&str, &str)] = &[
+    ("ALIOR", "ALR"),
+    ("ALLEGRO", "ALE"),
+    ("AMREST", "EAT"),
+    ("ASSECOPOL", "ACP"),
+    ("BUDIMEX", "BDX"),
+    ("CCC", "CCC"),
+    ("CDPROJEKT", "CDR"),
+    ("CYFRPLSAT", "CPS"),
+    ("DINOPL", "DNP"),
+    ("ENEA", "ENA"),
+    ("GPW", "GPW"),
+    ("HANDLOWY", "BHW"),
+    ("INGBSK", "ING"),
+    ("JSW", "JSW"),
+    ("KETY", "KTY"),
+    ("KGHM", "KGH"),
+    ("KRUK", "KRU"),
+    ("LPP", "LPP"),
+    ("MBANK", "MBK"),
+    ("MILLENNIUM", "MIL"),
+    ("ORANGEPL", "OPL"),
+    ("PEKAO", "PEO"),
+    ("PEPCO", "PCO"),
+    ("PGE", "PGE"),
+    ("PKNORLEN", "PKN"),
+    ("PKOBP", "PKO"),
+    ("PZU", "PZU"),
+    ("SANPL", "SPL"),
+    ("TAURONPE", "TPE"),
+    ("TEXT", "TXT"),
+    ("XTB", "XTB"),
+    ("ZABKA", "ZAB"),
+];
+
+/// Parses an mBank eMakler transaction history (`Historia transakcji`)
+///
+/// mBank saves the file in Windows-1250 with `;` separators, and may put a
+/// title above the table. Instruments are named by their GPW short name,
+/// mapped here for the larger companies; others keep the name and can be
+/// mapped through [`super::isins_path`]. Foreign trades take the stooq
+/// suffix of their exchange.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::mbank;
+/// use xbar_stocks::transactions::Kind;
+///
+/// let export = "Historia transakcji
+///
+/// Czas transakcji;Walor;Giełda;K/S;Liczba;Kurs;Waluta;Prowizja;Waluta prowizji;Wartość;Waluta
+/// 03.01.2024 09:15:22;PKNORLEN;WWA-GPW;K;20;62,50;PLN;3,75;PLN;1 250,00;PLN
+/// 12.03.2024 15:40:05;APPLE;NASDAQ;K;3;172,10;USD;3,00;USD;516,30;USD
+/// 04.06.2024 10:02:41;PKNORLEN;WWA-GPW;S;5;66,02;PLN;3,00;PLN;330,10;PLN
+/// ";
+/// let transactions = mbank::parse(export.as_bytes()).unwrap();
+/// assert_eq!(transactions.len(), 3);
+/// let orlen = &transactions[0];
+/// assert_eq!((orlen.kind, orlen.ticker.as_str()), (Kind::Buy, "PKN"));
+/// assert_eq!((orlen.shares, orlen.price, orlen.fee), (20.0, 62.5, Some(3.75)));
+/// assert_eq!(orlen.date.to_string(), "2024-01-03");
+/// assert_eq!(transactions[1].ticker, "APPLE.US");
+/// assert_eq!((transactions[2].kind, transactions[2].price), (Kind::Sell, 66.02));
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let text = match std::str::from_utf8(contents) {
+        Ok(text) => text.to_string(),
+        Err(_) => windows_1250(contents),
+    };
+    let start = text
+        .lines()
+        .position(|line| line.to_lowercase().contains("czas transakcji"))
+        .ok_or("no transactions table (expected a 'Czas transakcji' column)")?;
+    let table = text.lines().skip(start).collect::<Vec<_>>().join("\n");
+    let mut reader = delimited(table.as_bytes());
+    let columns = Columns::new(reader.headers()?.iter());
+
+    let mut transactions = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let row = || -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
+            let kind = match columns.get(&record, &["k/s"]) {
+                Some("K") => Kind::Buy,
+                Some("S") => Kind::Sell,
+                _ => return Ok(None),
+            };
+            let name = columns.require(&record, &["walor"])?.to_uppercase();
+            let currency = columns.require(&record, &["waluta"])?;
+            let exchange = columns.get(&record, &["giełda"]).unwrap_or("");
+            let ticker = match SHORT_NAMES.iter().find(|(short, _)| *short == name) {
+                Some((_, ticker)) => ticker.to_string(),
+                None => stooq_symbol(&name, currency, Some(exchange)),
+            };
+            let fee = columns
+                .get(&record, &["prowizja"])
+                .filter(|fee| !fee.is_empty())
+                .map(comma_decimal_number)
+                .transpose()?;
+            Ok(Some(Transaction {
+                date: day_first_date(columns.require(&record, &["czas transakcji"])?)?,
+                kind,
+                ticker,
+                shares: comma_decimal_number(columns.require(&record, &["liczba"])?)?,
+                price: comma_decimal_number(columns.require(&record, &["kurs"])?)?,
+                fee,
+                amount: None,
+                reinvested: false,
+                currency: Some(currency.to_string()),
+            }))
+        };
+        // Line numbers count the title and header
+        let line = start + index + 2;
+        if let Some(transaction) = row().map_err(|e| format!("line {}: {}", line, e))? {
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
+
+/// Decodes Windows-1250 text; only Polish letters are mapped, any other
+/// byte above ASCII becomes `?`
+fn windows_1250(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| match byte {
+            0x00..=0x7F => *byte as char,
+            0xA5 => 'Ą',
+            0xB9 => 'ą',
+            0xC6 => 'Ć',
+            0xE6 => 'ć',
+            0xCA => 'Ę',
+            0xEA => 'ę',
+            0xA3 => 'Ł',
+            0xB3 => 'ł',
+            0xD1 => 'Ń',
+            0xF1 => 'ń',
+            0xD3 => 'Ó',
+            0xF3 => 'ó',
+            0x8C => 'Ś',
+            0x9C => 'ś',
+            0x8F => 'Ź',
+            0x9F => 'ź',
+            0xAF => 'Ż',
+            0xBF => 'ż',
+            _ => '?',
+        })
+        .collect()
+}
@@ -0,0 +1,198 @@
+use super::stooq_symbol;
+use crate::date::Date;
+use crate::transactions::{Kind, Transaction};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Investment transaction aggregates and how each is read
+const AGGREGATES: &[(&str, Kind)] = &[
+    ("BUYSTOCK", Kind::Buy),
+    ("BUYMF", Kind::Buy),
+    ("BUYOTHER", Kind::Buy),
+    ("SELLSTOCK", Kind::Sell),
+    ("SELLMF", Kind::Sell),
+    ("SELLOTHER", Kind::Sell),
+    ("INCOME", Kind::Dividend),
+    ("REINVEST", Kind::Dividend),
+];
+
+/// Parses the investment statement of an OFX or QFX file, in either the
+/// SGML (1.x) or XML (2.x) form
+///
+/// Securities are identified by CUSIP or ISIN and mapped to the tickers of
+/// the statement's security list. Income other than dividends (interest,
+/// capital gains distributions) is skipped.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::ofx;
+/// use xbar_stocks::transactions::Kind;
+///
+/// let statement = b"OFXHEADER:100
+/// DATA:OFXSGML
+/// VERSION:102
+///
+/// <OFX>
+/// <INVSTMTMSGSRSV1><INVSTMTTRNRS><INVSTMTRS>
+/// <DTASOF>20240603
+/// <CURDEF>USD
+/// <INVACCTFROM><BROKERID>fidelity.com<ACCTID>Z12345678</INVACCTFROM>
+/// <INVTRANLIST><DTSTART>20240101<DTEND>20240603
+/// <BUYSTOCK><INVBUY>
+/// <INVTRAN><FITID>1001<DTTRADE>20240102103000.000[-5:EST]</INVTRAN>
+/// <SECID><UNIQUEID>037833100<UNIQUEIDTYPE>CUSIP</SECID>
+/// <UNITS>10<UNITPRICE>185.50<COMMISSION>0.50<FEES>0.02<TOTAL>-1855.52
+/// <SUBACCTSEC>CASH<SUBACCTFUND>CASH
+/// </INVBUY><BUYTYPE>BUY</BUYSTOCK>
+/// <INCOME>
+/// <INVTRAN><FITID>1002<DTTRADE>20240516</INVTRAN>
+/// <SECID><UNIQUEID>037833100<UNIQUEIDTYPE>CUSIP</SECID>
+/// <INCOMETYPE>DIV<TOTAL>2.50<SUBACCTSEC>CASH<SUBACCTFUND>CASH
+/// </INCOME>
+/// <INCOME>
+/// <INVTRAN><FITID>1003<DTTRADE>20240531</INVTRAN>
+/// <SECID><UNIQUEID>31617H102<UNIQUEIDTYPE>CUSIP</SECID>
+/// <INCOMETYPE>INTEREST<TOTAL>4.12<SUBACCTSEC>CASH<SUBACCTFUND>CASH
+/// </INCOME>
+/// </INVTRANLIST>
+/// </INVSTMTRS></INVSTMTTRNRS></INVSTMTMSGSRSV1>
+/// <SECLISTMSGSRSV1><SECLIST>
+/// <STOCKINFO><SECINFO><SECID><UNIQUEID>037833100<UNIQUEIDTYPE>CUSIP</SECID>
+/// <SECNAME>APPLE INC<TICKER>AAPL</SECINFO></STOCKINFO>
+/// </SECLIST></SECLISTMSGSRSV1>
+/// </OFX>
+/// ";
+/// let transactions = ofx::parse(statement).unwrap();
+/// assert_eq!(transactions.len(), 2);
+/// let buy = &transactions[0];
+/// assert_eq!((buy.kind, buy.ticker.as_str()), (Kind::Buy, "AAPL.US"));
+/// assert_eq!((buy.shares, buy.price), (10.0, 185.5));
+/// assert!((buy.fee.unwrap() - 0.52).abs() < 1e-9);
+/// assert_eq!(buy.date.to_string(), "2024-01-02");
+/// let dividend = &transactions[1];
+/// assert_eq!((dividend.kind, dividend.amount), (Kind::Dividend, Some(2.5)));
+/// assert_eq!(dividend.currency.as_deref(), Some("USD"));
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let text = String::from_utf8_lossy(contents);
+    let mut default_currency = String::new();
+    let mut tickers: HashMap<String, String> = HashMap::new();
+    let mut security: HashMap<String, String> = HashMap::new();
+    let mut current: Option<(Kind, bool, HashMap<String, String>)> = None;
+    let mut rows = Vec::new();
+
+    for (tag, value) in elements(&text) {
+        if let Some(closed) = tag.strip_prefix('/') {
+            if closed == "SECINFO" {
+                if let (Some(id), Some(ticker)) = (security.get("UNIQUEID"), security.get("TICKER"))
+                {
+                    tickers.insert(id.clone(), ticker.clone());
+                }
+                security.clear();
+            } else if AGGREGATES.iter().any(|(name, _)| *name == closed)
+                && let Some(row) = current.take()
+            {
+                rows.push(row);
+            }
+            continue;
+        }
+        if let Some((_, kind)) = AGGREGATES.iter().find(|(name, _)| *name == tag) {
+            current = Some((*kind, tag == "REINVEST", HashMap::new()));
+        } else if tag == "CURDEF" {
+            default_currency = value.to_string();
+        } else if let Some((_, _, fields)) = &mut current {
+            fields.insert(tag.to_string(), value.to_string());
+        } else {
+            security.insert(tag.to_string(), value.to_string());
+        }
+    }
+
+    let mut transactions = Vec::new();
+    for (kind, reinvested, fields) in rows {
+        let field = |name: &str| {
+            fields
+                .get(name)
+                .map(String::as_str)
+                .filter(|v| !v.is_empty())
+        };
+        let number = |name: &str| -> Result<Option<f64>, Box<dyn Error + Send + Sync>> {
+            field(name)
+                .map(|value| {
+                    value
+                        .parse::<f64>()
+                        .map_err(|_| format!("invalid {} '{}'", name, value).into())
+                })
+                .transpose()
+        };
+        if kind == Kind::Dividend && field("INCOMETYPE").is_some_and(|income| income != "DIV") {
+            continue;
+        }
+        let id = field("UNIQUEID").ok_or("transaction without a security")?;
+        let currency = field("CURSYM").unwrap_or(&default_currency).to_string();
+        let ticker = match tickers.get(id) {
+            Some(ticker) => stooq_symbol(ticker, &currency, None),
+            None => id.to_string(),
+        };
+        let stamp = field("DTTRADE").ok_or("transaction without a trade date")?;
+        let date = stamp
+            .get(..8)
+            .filter(|day| day.is_ascii())
+            .and_then(|day| {
+                Date::new(
+                    day[..4].parse().ok()?,
+                    day[4..6].parse().ok()?,
+                    day[6..].parse().ok()?,
+                )
+            })
+            .ok_or_else(|| format!("invalid date '{}'", stamp))?;
+        let fee = match (number("COMMISSION")?, number("FEES")?) {
+            (None, None) => None,
+            (commission, fees) => Some(commission.unwrap_or(0.0) + fees.unwrap_or(0.0)),
+        };
+        let (shares, price) = match (kind, number("UNITS")?, number("UNITPRICE")?) {
+            (Kind::Buy | Kind::Sell, Some(units), Some(price)) => (units.abs(), price),
+            (Kind::Buy | Kind::Sell, _, _) => {
+                return Err(format!("{}: trade without units and price", id).into());
+            }
+            (_, Some(units), Some(price)) if reinvested => (units.abs(), price),
+            _ => (0.0, 0.0),
+        };
+        transactions.push(Transaction {
+            date,
+            kind,
+            ticker,
+            shares,
+            price,
+            fee,
+            amount: if kind == Kind::Dividend {
+                number("TOTAL")?.map(f64::abs)
+            } else {
+                None
+            },
+            reinvested,
+            currency: (!currency.is_empty()).then_some(currency),
+        });
+    }
+    Ok(transactions)
+}
+
+/// Tags with the text that follows them, closing tags included as `/NAME`;
+/// SGML leaves elements unclosed, which this reads the same as XML
+fn elements(text: &str) -> Vec<(&str, &str)> {
+    let mut elements = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let Some(end) = rest[start..].find('>') else {
+            break;
+        };
+        let tag = &rest[start + 1..start + end];
+        rest = &rest[start + end + 1..];
+        let value = rest[..rest.find('<').unwrap_or(rest.len())].trim();
+        // Skip the XML declaration and OFX processing instruction
+        if !tag.starts_with('?') {
+            elements.push((tag.trim(), value));
+        }
+    }
+    elements
+}
@@ -0,0 +1,179 @@
+use super::{localized_number, stooq_symbol};
+use crate::date::Date;
+use crate::transactions::{Kind, Transaction};
+use std::collections::HashMap;
+use std::error::Error;
+
+/// Parses the investment accounts of a QIF file, as exported by Quicken and
+/// Microsoft Money
+///
+/// Securities are named in transactions; a `!Type:Security` list, when
+/// present, gives their symbols, which are taken as US listings. Cash
+/// dividends, reinvested dividends, buys and sells are read, including the
+/// `X` variants that move the cash to another account.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::qif;
+/// use xbar_stocks::transactions::Kind;
+///
+/// let export = b"!Option:AutoSwitch
+/// !Type:Security
+/// NApple Inc
+/// SAAPL
+/// TStock
+/// ^
+/// !Clear:AutoSwitch
+/// !Account
+/// NBrokerage
+/// TInvst
+/// ^
+/// !Type:Invst
+/// D1/ 2'24
+/// NBuy
+/// YApple Inc
+/// I185.50
+/// Q10
+/// U1,856.00
+/// T1,856.00
+/// O1.00
+/// ^
+/// D5/16'24
+/// NDiv
+/// YApple Inc
+/// U2.50
+/// T2.50
+/// ^
+/// D6/ 3'24
+/// NReinvDiv
+/// YApple Inc
+/// I194.00
+/// Q0.0129
+/// U2.50
+/// T2.50
+/// ^
+/// ";
+/// let transactions = qif::parse(export).unwrap();
+/// assert_eq!(transactions.len(), 3);
+/// let buy = &transactions[0];
+/// assert_eq!((buy.kind, buy.ticker.as_str()), (Kind::Buy, "AAPL.US"));
+/// assert_eq!((buy.shares, buy.price, buy.fee), (10.0, 185.5, Some(1.0)));
+/// assert_eq!(buy.date.to_string(), "2024-01-02");
+/// assert_eq!((transactions[1].kind, transactions[1].amount), (Kind::Dividend, Some(2.5)));
+/// let reinvested = &transactions[2];
+/// assert!(reinvested.reinvested);
+/// assert_eq!((reinvested.shares, reinvested.price), (0.0129, 194.0));
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let text = String::from_utf8_lossy(contents);
+    let mut section = String::new();
+    let mut symbols: HashMap<String, String> = HashMap::new();
+    let mut entries: Vec<(usize, HashMap<char, String>)> = Vec::new();
+    let mut fields: HashMap<char, String> = HashMap::new();
+    let mut first_line = 0;
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim_end();
+        if let Some(kind) = line.strip_prefix('!') {
+            // Options such as !Option:AutoSwitch do not start a section
+            if kind.starts_with("Type:") {
+                section = kind.to_lowercase();
+            }
+            continue;
+        }
+        let mut chars = line.chars();
+        let Some(code) = chars.next() else {
+            continue;
+        };
+        if code != '^' {
+            if fields.is_empty() {
+                first_line = index + 1;
+            }
+            fields.insert(code, chars.as_str().trim().to_string());
+            continue;
+        }
+        let entry = std::mem::take(&mut fields);
+        match section.as_str() {
+            "type:security" => {
+                if let (Some(name), Some(symbol)) = (entry.get(&'N'), entry.get(&'S')) {
+                    symbols.insert(name.clone(), symbol.clone());
+                }
+            }
+            "type:invst" => entries.push((first_line, entry)),
+            _ => {}
+        }
+    }
+
+    let mut transactions = Vec::new();
+    for (line, entry) in entries {
+        let transaction =
+            transaction(&entry, &symbols).map_err(|e| format!("line {}: {}", line, e))?;
+        transactions.extend(transaction);
+    }
+    Ok(transactions)
+}
+
+fn transaction(
+    entry: &HashMap<char, String>,
+    symbols: &HashMap<String, String>,
+) -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
+    let action = entry.get(&'N').map(|action| action.to_lowercase());
+    let (kind, reinvested) = match action.as_deref() {
+        Some("buy" | "buyx" | "shrsin") => (Kind::Buy, false),
+        Some("sell" | "sellx" | "shrsout") => (Kind::Sell, false),
+        Some("div" | "divx") => (Kind::Dividend, false),
+        Some("reinvdiv") => (Kind::Dividend, true),
+        _ => return Ok(None),
+    };
+    let field = |code: char| entry.get(&code).filter(|value| !value.is_empty());
+    let number = |code: char| field(code).map(|value| localized_number(value)).transpose();
+    let security = field('Y').ok_or("missing security")?;
+    let ticker = match symbols.get(security) {
+        Some(symbol) => stooq_symbol(symbol, "USD", None),
+        None => security.clone(),
+    };
+    let date = date(field('D').ok_or("missing date")?)?;
+    let amount = number('T')?.or(number('U')?).map(f64::abs);
+    let (shares, price) = match (kind, number('Q')?, number('I')?) {
+        (Kind::Buy | Kind::Sell, Some(shares), Some(price)) => (shares.abs(), price),
+        (Kind::Buy | Kind::Sell, _, _) => return Err("expected shares and price".into()),
+        (_, Some(shares), Some(price)) if reinvested => (shares.abs(), price),
+        _ => (0.0, 0.0),
+    };
+    Ok(Some(Transaction {
+        date,
+        kind,
+        ticker,
+        shares,
+        price,
+        fee: number('O')?,
+        amount: if kind == Kind::Dividend { amount } else { None },
+        reinvested,
+        currency: None,
+    }))
+}
+
+/// QIF dates are month first, with the year after `/` or, in Quicken's
+/// style for this century, after `'`: `1/2/1998`, `01/02'24`, `1/2/98`
+fn date(text: &str) -> Result<Date, Box<dyn Error + Send + Sync>> {
+    let invalid = || format!("invalid date '{}'", text);
+    let mut parts = text.split(['/', '\'', '-', '.']).map(str::trim);
+    let month = parts
+        .next()
+        .and_then(|part| part.parse().ok())
+        .ok_or_else(invalid)?;
+    let day = parts
+        .next()
+        .and_then(|part| part.parse().ok())
+        .ok_or_else(invalid)?;
+    let year_text = parts.next().ok_or_else(invalid)?;
+    let year: i32 = year_text.parse().map_err(|_| invalid())?;
+    let year = match year_text.len() {
+        4 => year,
+        _ if text.contains('\'') => 2000 + year,
+        _ if year < 70 => 2000 + year,
+        _ => 1900 + year,
+    };
+    Date::new(year, month, day).ok_or_else(|| invalid().into())
+}
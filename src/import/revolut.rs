@@ -0,0 +1,97 @@
+use super::{Columns, localized_number, money, stooq_symbol};
+use crate::date::Date;
+use crate::transactions::{Kind, Transaction};
+use std::error::Error;
+
+/// Parses a Revolut trading account statement
+///
+/// Market and limit buys and sells and dividends are read; top-ups, custody
+/// fees and corporate actions such as splits are skipped. Revolut writes
+/// amounts with their currency, as in `USD 150.50` or `$150.50`.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::revolut;
+/// use xbar_stocks::transactions::Kind;
+///
+/// let statement = br#"Date,Ticker,Type,Quantity,Price per share,Total Amount,Currency,FX Rate
+/// 2024-01-02T09:12:44.518Z,,CASH TOP-UP,,,"USD 2,000",USD,1.27
+/// 2024-01-03T15:31:05.123Z,AAPL,BUY - MARKET,10,USD 184.25,"USD 1,842.50",USD,1.27
+/// 2024-03-01T04:10:00.000Z,,CUSTODY FEE,,,USD -0.12,USD,1.26
+/// 2024-05-17T11:20:31.004Z,AAPL,DIVIDEND,,,USD 2.13,USD,1.25
+/// "#;
+/// let transactions = revolut::parse(statement).unwrap();
+/// assert_eq!(transactions.len(), 2);
+/// let buy = &transactions[0];
+/// assert_eq!((buy.kind, buy.ticker.as_str()), (Kind::Buy, "AAPL.US"));
+/// assert_eq!((buy.shares, buy.price), (10.0, 184.25));
+/// assert_eq!(buy.date.to_string(), "2024-01-03");
+/// let dividend = &transactions[1];
+/// assert_eq!((dividend.kind, dividend.amount), (Kind::Dividend, Some(2.13)));
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents);
+    let columns = Columns::new(reader.headers()?.iter());
+
+    let mut transactions = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let row = || -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
+            let kind = columns.require(&record, &["type"])?.to_uppercase();
+            let kind = if kind.starts_with("BUY") {
+                Kind::Buy
+            } else if kind.starts_with("SELL") {
+                Kind::Sell
+            } else if kind.starts_with("DIVIDEND") {
+                Kind::Dividend
+            } else {
+                return Ok(None);
+            };
+            let currency = columns.require(&record, &["currency"])?;
+            let ticker = stooq_symbol(columns.require(&record, &["ticker"])?, currency, None);
+            let date = date(columns.require(&record, &["date"])?)?;
+            let total = money(columns.require(&record, &["total amount"])?)?.abs();
+            let transaction = if kind == Kind::Dividend {
+                Transaction {
+                    date,
+                    kind,
+                    ticker,
+                    shares: 0.0,
+                    price: 0.0,
+                    fee: None,
+                    amount: Some(total),
+                    reinvested: false,
+                    currency: Some(currency.to_string()),
+                }
+            } else {
+                Transaction {
+                    date,
+                    kind,
+                    ticker,
+                    shares: localized_number(columns.require(&record, &["quantity"])?)?.abs(),
+                    price: money(columns.require(&record, &["price per share"])?)?,
+                    fee: None,
+                    amount: None,
+                    reinvested: false,
+                    currency: Some(currency.to_string()),
+                }
+            };
+            Ok(Some(transaction))
+        };
+        // Line numbers count the header
+        if let Some(transaction) = row().map_err(|e| format!("line {}: {}", index + 2, e))? {
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
+
+/// Dates are ISO timestamps such as `2024-01-02T15:31:05.123Z`
+fn date(text: &str) -> Result<Date, Box<dyn Error + Send + Sync>> {
+    text.get(..10)
+        .and_then(Date::parse)
+        .ok_or_else(|| format!("invalid date '{}'", text).into())
+}
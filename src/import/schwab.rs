@@ -0,0 +1,85 @@
+use super::{Columns, Holding, clean_symbol, money, number};
+use std::error::Error;
+
+/// Parses a Charles Schwab positions export
+///
+/// The table follows a title line naming the account. The cash row and
+/// money-market funds become one `CASH` balance each; the account total is
+/// skipped. Cost per share comes from the total cost basis, falling back to
+/// the current price where Schwab shows none (`--` or `N/A`); a cost basis
+/// that does not parse is an error rather than silently replaced.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::{Holding, schwab};
+///
+/// let export = br#""Positions for account Individual ...123 as of 09:41 AM ET, 2024/06/03"
+/// "Symbol","Description","Quantity","Price","Price Change $","Price Change %","Market Value","Day Change $","Day Change %","Cost Basis","Gain $","Gain %","Reinvest Dividends?","Capital Gains?","% Of Account","Security Type",
+/// "AAPL","APPLE INC","10","$194.03","$1.78","0.93%","$1,940.30","$17.80","0.93%","$1,855.00","$85.30","4.6%","No","N/A","60.1%","Equity",
+/// "SWVXX","SCHWAB VALUE ADVANTAGE MONEY INV","500","$1.00","$0.00","0%","$500.00","$0.00","0%","$500.00","$0.00","0%","No","N/A","15.5%","Money Market",
+/// "VTI","VANGUARD TOTAL STOCK MARKET ETF","3","$262.38","$0.62","0.24%","$787.14","$1.86","0.24%","--","--","--","No","N/A","24.4%","ETFs & Closed End Funds",
+/// "Cash & Cash Investments","--","--","--","--","--","$12.50","$0.00","0%","--","--","--","--","--","0.4%","Cash and Money Market",
+/// "Account Total","--","--","--","--","--","$3,239.94","$19.66","0.61%","$2,355.00","$85.30","3.6%","--","--","--","--",
+/// "#;
+/// let holdings = schwab::parse(export).unwrap();
+/// assert_eq!(
+///     holdings,
+///     vec![
+///         Holding { ticker: "AAPL.US".into(), shares: 10.0, buy_price: 185.5 },
+///         Holding::cash(500.0),
+///         Holding { ticker: "VTI.US".into(), shares: 3.0, buy_price: 262.38 },
+///         Holding::cash(12.5),
+///     ]
+/// );
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Holding>, Box<dyn Error + Send + Sync>> {
+    let records = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(contents)
+        .records()
+        .collect::<Result<Vec<_>, _>>()?;
+    let start = records
+        .iter()
+        .position(|record| record.get(0).map(str::trim) == Some("Symbol"))
+        .ok_or("no positions table (expected a Symbol column)")?;
+    let columns = Columns::new(records[start].iter());
+
+    let mut holdings = Vec::new();
+    for (index, record) in records.iter().enumerate().skip(start + 1) {
+        let row = || -> Result<Option<Holding>, Box<dyn Error + Send + Sync>> {
+            let symbol = columns.get(record, &["symbol"]).unwrap_or("");
+            let kind = columns
+                .get(record, &["security type", "asset type"])
+                .unwrap_or("")
+                .to_lowercase();
+            if symbol.is_empty() || symbol == "Account Total" {
+                return Ok(None);
+            }
+            if symbol.starts_with("Cash") || kind.contains("money market") {
+                let value = columns.require(record, &["market value", "mkt val (market value)"])?;
+                return Ok(Some(Holding::cash(money(value)?)));
+            }
+            let shares = number(columns.require(record, &["quantity", "qty (quantity)"])?)?;
+            let cost = columns
+                .get(record, &["cost basis"])
+                .filter(|cost| !matches!(*cost, "" | "--" | "N/A"))
+                .map(money)
+                .transpose()?;
+            let buy_price = match cost {
+                Some(cost) => cost / shares,
+                None => money(columns.require(record, &["price"])?)?,
+            };
+            Ok(Some(Holding {
+                ticker: clean_symbol(symbol),
+                shares,
+                buy_price,
+            }))
+        };
+        if let Some(holding) = row().map_err(|e| format!("line {}: {}", index + 1, e))? {
+            holdings.push(holding);
+        }
+    }
+    Ok(holdings)
+}
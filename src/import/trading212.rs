@@ -0,0 +1,125 @@
+use super::{Columns, localized_number, stooq_symbol};
+use crate::date::Date;
+use crate::transactions::{Kind, Transaction};
+use std::error::Error;
+
+/// Parses a Trading212 history export
+///
+/// Buys, sells and dividends are read; deposits, interest and the like are
+/// skipped. Fees and taxes are charged in the account currency and converted
+/// to the instrument's at the row's exchange rate, and prices quoted in pence
+/// (GBX) become pounds; the rate of a GBX row is already in pounds.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::trading212;
+/// use xbar_stocks::transactions::Kind;
+///
+/// let export = b"Action,Time,ISIN,Ticker,Name,No. of shares,Price / share,Currency (Price / share),Exchange rate,Result,Currency (Result),Total,Currency (Total),Withholding tax,Currency (Withholding tax),Stamp duty reserve tax,Currency (Stamp duty reserve tax),Currency conversion fee,Currency (Currency conversion fee)
+/// Deposit,2024-01-02 09:00:00,,,,,,,,,,1000.00,GBP,,,,,,
+/// Market buy,2024-01-03 14:30:05,GB00B10RZP78,ULVR,Unilever,10,3800.0,GBX,1.00000,,GBP,381.90,GBP,,,1.90,GBP,,
+/// Market buy,2024-01-04 15:01:10,US0378331005,AAPL,Apple,2,184.50,USD,1.27000,,GBP,290.99,GBP,,,,,0.43,GBP
+/// Dividend (Dividend),2024-05-16 10:00:00,US0378331005,AAPL,Apple,2,0.24,USD,Not available,,GBP,0.32,GBP,0.07,USD,,,,
+/// ";
+/// let transactions = trading212::parse(export).unwrap();
+/// assert_eq!(transactions.len(), 3);
+/// let unilever = &transactions[0];
+/// assert_eq!((unilever.kind, unilever.ticker.as_str()), (Kind::Buy, "ULVR.UK"));
+/// // Pence become pounds
+/// assert_eq!((unilever.shares, unilever.price), (10.0, 38.0));
+/// assert_eq!(unilever.fee, Some(1.9));
+/// let apple = &transactions[1];
+/// assert_eq!((apple.ticker.as_str(), apple.price), ("AAPL.US", 184.5));
+/// // The pound conversion fee in dollars
+/// assert!((apple.fee.unwrap() - 0.5461).abs() < 1e-9);
+/// let dividend = &transactions[2];
+/// assert_eq!((dividend.kind, dividend.amount), (Kind::Dividend, Some(0.48)));
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(contents);
+    let columns = Columns::new(reader.headers()?.iter());
+    // Every charge except withholding tax, whose column layout varies with
+    // the taxes the account has paid
+    let fee_columns: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| {
+            (name.contains("fee") || name.contains("tax"))
+                && !name.starts_with("currency (")
+                && !name.starts_with("withholding")
+        })
+        .collect();
+
+    let mut transactions = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let row = || -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
+            let action = columns.require(&record, &["action"])?.to_lowercase();
+            let kind = if action.ends_with("buy") {
+                Kind::Buy
+            } else if action.ends_with("sell") {
+                Kind::Sell
+            } else if action.starts_with("dividend") {
+                Kind::Dividend
+            } else {
+                return Ok(None);
+            };
+            let mut currency = columns
+                .require(&record, &["currency (price / share)"])?
+                .to_string();
+            let mut price = localized_number(columns.require(&record, &["price / share"])?)?;
+            let rate = match columns.get(&record, &["exchange rate"]) {
+                Some(rate) if !rate.is_empty() && rate != "Not available" => {
+                    localized_number(rate)?
+                }
+                _ => 1.0,
+            };
+            if currency == "GBX" {
+                currency = "GBP".to_string();
+                price /= 100.0;
+            }
+            let shares = localized_number(columns.require(&record, &["no. of shares"])?)?;
+            let mut fee = 0.0;
+            for column in &fee_columns {
+                if let Some(value) = columns
+                    .get(&record, &[column])
+                    .filter(|value| !value.is_empty())
+                {
+                    fee += localized_number(value)?.abs() * rate;
+                }
+            }
+            let ticker = columns.require(&record, &["ticker"])?;
+            let (shares, price, amount) = match kind {
+                // The dividend row gives the shares held and the payout per share
+                Kind::Dividend => (0.0, 0.0, Some(shares * price)),
+                _ => (shares, price, None),
+            };
+            Ok(Some(Transaction {
+                date: date(columns.require(&record, &["time"])?)?,
+                kind,
+                ticker: stooq_symbol(ticker, &currency, None),
+                shares,
+                price,
+                fee: (fee > 0.0).then_some(fee),
+                amount,
+                reinvested: false,
+                currency: Some(currency),
+            }))
+        };
+        // Line numbers count the header
+        if let Some(transaction) = row().map_err(|e| format!("line {}: {}", index + 2, e))? {
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
+
+fn date(text: &str) -> Result<Date, Box<dyn Error + Send + Sync>> {
+    text.get(..10)
+        .and_then(Date::parse)
+        .ok_or_else(|| format!("invalid date '{}'", text).into())
+}
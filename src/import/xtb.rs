@@ -0,0 +1,125 @@
+use super::{Columns, comma_decimal_number, day_first_date, delimited};
+use crate::transactions::{Kind, Transaction};
+use std::error::Error;
+
+/// Parses the cash operations sheet of an XTB history export, saved as CSV
+///
+/// Trades are read from their comments, such as `OPEN BUY 10 @ 150.50` or
+/// `OPEN BUY 2/10 @ 150.50` for a partial fill. XTB symbols carry a market
+/// suffix that matches stooq's except for Warsaw (`PKN.PL` is `PKN`), and
+/// the suffix also gives the quote currency. Dividends are paid in the
+/// account currency, which the export does not name, so they are left
+/// without one.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::import::xtb;
+/// use xbar_stocks::transactions::Kind;
+///
+/// let sheet = "ID;Type;Time;Comment;Symbol;Amount
+/// 512345678;Deposit;02.01.2024 09:12:44;Blik deposit;;5000
+/// 512345901;Stocks/ETF purchase;03.01.2024 10:15:01;OPEN BUY 10 @ 150.50;PKN.PL;-1505
+/// 512346120;Stocks/ETF purchase;05.02.2024 15:40:12;OPEN BUY 2/5 @ 184.25;AAPL.US;-368,5
+/// 512349877;Stocks/ETF sale;06.06.2024 11:02:33;CLOSE BUY 4 @ 162.10;PKN.PL;648,4
+/// 512351002;Divident;17.05.2024 08:00:00;AAPL.US USD 0.2400/ SHR;AAPL.US;1,92
+/// ";
+/// let transactions = xtb::parse(sheet.as_bytes()).unwrap();
+/// assert_eq!(transactions.len(), 4);
+/// let orlen = &transactions[0];
+/// assert_eq!((orlen.kind, orlen.ticker.as_str()), (Kind::Buy, "PKN"));
+/// assert_eq!((orlen.shares, orlen.price), (10.0, 150.5));
+/// assert_eq!(orlen.currency.as_deref(), Some("PLN"));
+/// assert_eq!(orlen.date.to_string(), "2024-01-03");
+/// // Only the filled part of the order
+/// assert_eq!((transactions[1].ticker.as_str(), transactions[1].shares), ("AAPL.US", 2.0));
+/// assert_eq!((transactions[2].kind, transactions[2].shares), (Kind::Sell, 4.0));
+/// let dividend = &transactions[3];
+/// assert_eq!((dividend.kind, dividend.amount), (Kind::Dividend, Some(1.92)));
+/// ```
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let mut reader = delimited(contents);
+    let columns = Columns::new(reader.headers()?.iter());
+
+    let mut transactions = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let row = || -> Result<Option<Transaction>, Box<dyn Error + Send + Sync>> {
+            let kind = columns.get(&record, &["type"]).unwrap_or("").to_lowercase();
+            let symbol = columns.get(&record, &["symbol"]).unwrap_or("");
+            let (ticker, currency) = ticker(symbol);
+            let date = day_first_date(columns.require(&record, &["time"])?)?;
+            if kind.starts_with("divident") || kind.starts_with("dividend") {
+                return Ok(Some(Transaction {
+                    date,
+                    kind: Kind::Dividend,
+                    ticker,
+                    shares: 0.0,
+                    price: 0.0,
+                    fee: None,
+                    amount: Some(comma_decimal_number(
+                        columns.require(&record, &["amount"])?,
+                    )?),
+                    reinvested: false,
+                    currency: None,
+                }));
+            }
+            let kind = if kind.contains("purchase") {
+                Kind::Buy
+            } else if kind.contains("sale") {
+                Kind::Sell
+            } else {
+                return Ok(None);
+            };
+            let comment = columns.require(&record, &["comment"])?;
+            let (quantity, price) = comment
+                .split_once('@')
+                .ok_or_else(|| format!("unrecognised trade comment '{}'", comment))?;
+            let quantity = quantity.split_whitespace().last().unwrap_or("");
+            // A partial fill reads "filled/ordered"
+            let quantity = quantity.split('/').next().unwrap_or(quantity);
+            Ok(Some(Transaction {
+                date,
+                kind,
+                ticker,
+                shares: comma_decimal_number(quantity)?,
+                price: comma_decimal_number(price.trim())?,
+                fee: None,
+                amount: None,
+                reinvested: false,
+                currency,
+            }))
+        };
+        // Line numbers count the header
+        if let Some(transaction) = row().map_err(|e| format!("line {}: {}", index + 2, e))? {
+            transactions.push(transaction);
+        }
+    }
+    Ok(transactions)
+}
+
+/// Stooq ticker and quote currency of an XTB symbol
+fn ticker(symbol: &str) -> (String, Option<String>) {
+    let symbol = symbol.trim().to_uppercase();
+    let Some((base, market)) = symbol.rsplit_once('.') else {
+        return (symbol, None);
+    };
+    let currency = match market {
+        "PL" => "PLN",
+        "US" => "USD",
+        "UK" => "GBP",
+        "DE" | "FR" | "NL" | "ES" | "IT" | "PT" | "BE" | "FI" | "IE" => "EUR",
+        "CH" => "CHF",
+        "DK" => "DKK",
+        "SE" => "SEK",
+        "NO" => "NOK",
+        "CZ" => "CZK",
+        _ => return (symbol.clone(), None),
+    };
+    let ticker = if market == "PL" {
+        base.to_string()
+    } else {
+        symbol.clone()
+    };
+    (ticker, Some(currency.to_string()))
+}
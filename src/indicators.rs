@@ -0,0 +1,149 @@
+use crate::date::Date;
+
+/// Simple moving average of the last `period` values
+pub fn sma(values: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || values.len() < period {
+        return None;
+    }
+    let window = &values[values.len() - period..];
+    Some(window.iter().sum::<f64>() / period as f64)
+}
+
+/// Crossing of the 50-day over the 200-day moving average
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cross {
+    /// SMA50 crossed above SMA200
+    Golden,
+    /// SMA50 crossed below SMA200
+    Death,
+}
+
+/// SMA50/SMA200 levels and any cross within the last `lookback` sessions
+#[derive(Debug, Clone, Copy)]
+pub struct MovingAverages {
+    pub sma50: f64,
+    pub sma200: f64,
+    pub recent_cross: Option<Cross>,
+}
+
+/// Computes SMA50/SMA200 from daily closes, oldest first
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::indicators::{moving_averages, Cross};
+///
+/// // A long decline followed by a sharp rally
+/// let mut closes: Vec<f64> = (0..200).map(|i| 300.0 - i as f64).collect();
+/// closes.extend((0..60).map(|i| 100.0 + 10.0 * i as f64));
+/// let averages = moving_averages(&closes, 60).unwrap();
+/// assert!(averages.sma50 > averages.sma200);
+/// assert_eq!(averages.recent_cross, Some(Cross::Golden));
+/// ```
+pub fn moving_averages(closes: &[f64], lookback: usize) -> Option<MovingAverages> {
+    let sma50 = sma(closes, 50)?;
+    let sma200 = sma(closes, 200)?;
+
+    // Walk back through earlier sessions looking for the spread to change sign
+    let above = sma50 > sma200;
+    let mut recent_cross = None;
+    for back in 1..=lookback.min(closes.len() - 200) {
+        let earlier = &closes[..closes.len() - back];
+        let (Some(fast), Some(slow)) = (sma(earlier, 50), sma(earlier, 200)) else {
+            break;
+        };
+        if (fast > slow) != above {
+            recent_cross = Some(if above { Cross::Golden } else { Cross::Death });
+            break;
+        }
+    }
+
+    Some(MovingAverages {
+        sma50,
+        sma200,
+        recent_cross,
+    })
+}
+
+/// Relative Strength Index over `period` sessions using Wilder's smoothing
+///
+/// Needs at least `period + 1` closes, oldest first.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::indicators::rsi;
+///
+/// let rising: Vec<f64> = (0..30).map(|i| 100.0 + i as f64).collect();
+/// assert_eq!(rsi(&rising, 14), Some(100.0));
+/// ```
+pub fn rsi(closes: &[f64], period: usize) -> Option<f64> {
+    if period == 0 || closes.len() <= period {
+        return None;
+    }
+
+    let changes: Vec<f64> = closes.windows(2).map(|pair| pair[1] - pair[0]).collect();
+    let (seed, rest) = changes.split_at(period);
+    let mut gain = seed.iter().filter(|c| **c > 0.0).sum::<f64>() / period as f64;
+    let mut loss = -seed.iter().filter(|c| **c < 0.0).sum::<f64>() / period as f64;
+    for change in rest {
+        gain = (gain * (period - 1) as f64 + change.max(0.0)) / period as f64;
+        loss = (loss * (period - 1) as f64 + (-change).max(0.0)) / period as f64;
+    }
+
+    if loss == 0.0 {
+        return Some(100.0);
+    }
+    Some(100.0 - 100.0 / (1.0 + gain / loss))
+}
+
+/// Daily log returns keyed by the later date, from closes oldest first
+pub fn daily_returns(closes: &[(Date, f64)]) -> Vec<(Date, f64)> {
+    closes
+        .windows(2)
+        .filter(|pair| pair[0].1 > 0.0 && pair[1].1 > 0.0)
+        .map(|pair| (pair[1].0, (pair[1].1 / pair[0].1).ln()))
+        .collect()
+}
+
+/// Annualized volatility in percent from daily returns (252 sessions a year)
+pub fn volatility(returns: &[(Date, f64)]) -> Option<f64> {
+    let values: Vec<f64> = returns.iter().map(|(_, r)| *r).collect();
+    let variance = sample_variance(&values)?;
+    Some(variance.sqrt() * 252f64.sqrt() * 100.0)
+}
+
+/// Beta of a position's returns against a benchmark's, on dates both traded
+pub fn beta(returns: &[(Date, f64)], benchmark_returns: &[(Date, f64)]) -> Option<f64> {
+    let benchmark: std::collections::HashMap<Date, f64> =
+        benchmark_returns.iter().copied().collect();
+    let (own, market): (Vec<f64>, Vec<f64>) = returns
+        .iter()
+        .filter_map(|(date, r)| benchmark.get(date).map(|m| (*r, *m)))
+        .unzip();
+    if own.len() < 20 {
+        return None;
+    }
+
+    let own_mean = own.iter().sum::<f64>() / own.len() as f64;
+    let market_mean = market.iter().sum::<f64>() / market.len() as f64;
+    let covariance = own
+        .iter()
+        .zip(&market)
+        .map(|(o, m)| (o - own_mean) * (m - market_mean))
+        .sum::<f64>()
+        / (own.len() - 1) as f64;
+    let variance = sample_variance(&market)?;
+    if variance == 0.0 {
+        return None;
+    }
+    Some(covariance / variance)
+}
+
+fn sample_variance(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    Some(values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (values.len() - 1) as f64)
+}
@@ -0,0 +1,77 @@
+use std::error::Error;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Keychain service name under which all secrets are stored
+pub const SERVICE: &str = "xbar-stocks";
+
+/// Reads a generic password from the macOS Keychain
+///
+/// Uses the `security` command-line tool, so the first access from a new
+/// binary triggers the usual Keychain permission prompt.
+pub fn find_password(account: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let output = Command::new("security")
+        .args(["find-generic-password", "-s", SERVICE, "-a", account, "-w"])
+        .output()
+        .map_err(|e| format!("Failed to run security: {}", e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "No keychain entry for service '{}' account '{}'",
+            SERVICE, account
+        )
+        .into());
+    }
+
+    let secret = String::from_utf8(output.stdout)?;
+    Ok(secret.trim_end_matches('\n').to_string())
+}
+
+/// Stores (or replaces) a generic password in the macOS Keychain
+///
+/// A trailing `-w` without a value makes `security` prompt for the
+/// password, which is answered on its stdin so the secret never shows up
+/// in the process list.
+pub fn set_password(account: &str, secret: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut child = Command::new("security")
+        .args([
+            "add-generic-password",
+            "-U",
+            "-s",
+            SERVICE,
+            "-a",
+            account,
+            "-w",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Failed to run security: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // Once for the password prompt and once for its confirmation
+        write!(stdin, "{}\n{}\n", secret, secret)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(format!(
+            "Could not store keychain entry '{}': {}",
+            account,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Removes a generic password from the macOS Keychain
+pub fn delete_password(account: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let status = Command::new("security")
+        .args(["delete-generic-password", "-s", SERVICE, "-a", account])
+        .stdout(std::process::Stdio::null())
+        .status()
+        .map_err(|e| format!("Failed to run security: {}", e))?;
+    if !status.success() {
+        return Err(format!("No keychain entry '{}'", account).into());
+    }
+    Ok(())
+}
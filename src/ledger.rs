@@ -0,0 +1,78 @@
+use crate::date::Date;
+
+/// Plain-text accounting dialect for price directives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedgerStyle {
+    /// `2024-05-01 price AAPL 182.31 USD`
+    Beancount,
+    /// `P 2024-05-01 AAPL 182.31 USD`
+    Ledger,
+}
+
+impl LedgerStyle {
+    /// Parses a style name as given on the command line
+    pub fn parse(name: &str) -> Option<LedgerStyle> {
+        match name {
+            "beancount" => Some(LedgerStyle::Beancount),
+            "ledger" | "hledger" => Some(LedgerStyle::Ledger),
+            _ => None,
+        }
+    }
+}
+
+/// Commodity symbol for a stooq ticker: the exchange suffix is dropped and
+/// characters beancount does not allow become underscores
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::ledger::commodity;
+///
+/// assert_eq!(commodity("aapl.us"), "AAPL");
+/// assert_eq!(commodity("^SPX"), "SPX");
+/// ```
+pub fn commodity(ticker: &str) -> String {
+    let upper = ticker.to_uppercase();
+    let base = match upper.rsplit_once('.') {
+        Some((base, "US" | "PL" | "UK" | "DE")) => base,
+        _ => upper.as_str(),
+    };
+    base.trim_start_matches('^')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// One price directive line for the given dialect
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::date::Date;
+/// use xbar_stocks::ledger::{price_directive, LedgerStyle};
+///
+/// let date = Date::parse("2024-05-01").unwrap();
+/// assert_eq!(
+///     price_directive(LedgerStyle::Beancount, date, "AAPL.US", 182.31, "USD"),
+///     "2024-05-01 price AAPL 182.31 USD"
+/// );
+/// ```
+pub fn price_directive(
+    style: LedgerStyle,
+    date: Date,
+    ticker: &str,
+    price: f64,
+    currency: &str,
+) -> String {
+    let symbol = commodity(ticker);
+    match style {
+        LedgerStyle::Beancount => format!("{} price {} {} {}", date, symbol, price, currency),
+        LedgerStyle::Ledger => format!("P {} {} {} {}", date, symbol, price, currency),
+    }
+}
@@ -1,6 +1,251 @@
+pub mod cache;
+pub mod calendar;
+pub mod cash;
+pub mod config;
+pub mod csv_file;
+pub mod date;
+pub mod demo;
+pub mod encryption;
+pub mod fx;
+pub mod history;
+pub mod import;
+pub mod indicators;
+pub mod json;
+pub mod keychain;
+pub mod ledger;
+pub mod live;
+pub mod locale;
+pub mod logging;
+pub mod market;
+pub mod metrics;
+pub mod mqtt;
+pub mod notify;
+pub mod offline;
+pub mod parquet;
+pub mod performance;
+pub mod portfolio;
+pub mod secrets;
+pub mod sheets;
+pub mod sparkline;
+pub mod splits;
+pub mod state;
+pub mod store;
+pub mod symbols;
+pub mod template;
+pub mod transactions;
+pub mod watcher;
+pub mod width;
+
+use date::Date;
 use regex::Regex;
 use std::error::Error;
-use std::time::Duration;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// The data directory, `~/.stocks`, or a scratch directory in demo mode
+pub fn data_dir() -> PathBuf {
+    if is_demo() {
+        return std::env::temp_dir().join("xbar-stocks-demo");
+    }
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    PathBuf::from(home).join(".stocks")
+}
+
+/// Broad category of a failed fetch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FetchErrorKind {
+    /// DNS failure, refused or reset connection
+    Network,
+    /// The request did not complete in time
+    Timeout,
+    /// The server answered with a non-200 status
+    HttpStatus,
+    /// The page loaded but contained no price (unknown ticker or captive portal)
+    NotFound,
+    /// The response could not be decoded or parsed
+    Decode,
+    Other,
+}
+
+impl FetchErrorKind {
+    /// Short machine-friendly name
+    pub fn name(&self) -> &'static str {
+        match self {
+            FetchErrorKind::Network => "network",
+            FetchErrorKind::Timeout => "timeout",
+            FetchErrorKind::HttpStatus => "http_status",
+            FetchErrorKind::NotFound => "not_found",
+            FetchErrorKind::Decode => "decode",
+            FetchErrorKind::Other => "other",
+        }
+    }
+
+    /// Parses a name produced by [`FetchErrorKind::name`]
+    pub fn parse(name: &str) -> Option<FetchErrorKind> {
+        [
+            FetchErrorKind::Network,
+            FetchErrorKind::Timeout,
+            FetchErrorKind::HttpStatus,
+            FetchErrorKind::NotFound,
+            FetchErrorKind::Decode,
+            FetchErrorKind::Other,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+
+    /// True if retrying later may succeed
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            FetchErrorKind::Network | FetchErrorKind::Timeout | FetchErrorKind::HttpStatus
+        )
+    }
+}
+
+/// A fetch failure recorded earlier, e.g. by the daemon, with its kind preserved
+#[derive(Debug, Clone)]
+pub struct FetchError {
+    pub kind: FetchErrorKind,
+    pub message: String,
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for FetchError {}
+
+/// Classifies an error returned by the fetch functions
+pub fn classify_error(error: &(dyn Error + 'static)) -> FetchErrorKind {
+    if let Some(e) = error.downcast_ref::<FetchError>() {
+        return e.kind;
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        return if e.is_timeout() {
+            FetchErrorKind::Timeout
+        } else if e.is_connect() || e.is_request() {
+            FetchErrorKind::Network
+        } else if e.is_status() {
+            FetchErrorKind::HttpStatus
+        } else if e.is_decode() || e.is_body() {
+            FetchErrorKind::Decode
+        } else {
+            FetchErrorKind::Other
+        };
+    }
+    if error.downcast_ref::<std::num::ParseFloatError>().is_some() {
+        return FetchErrorKind::Decode;
+    }
+
+    let message = error.to_string();
+    if message.starts_with("Invalid status code") {
+        FetchErrorKind::HttpStatus
+    } else if message.starts_with("Could not find price")
+        || message.starts_with("No historical data")
+    {
+        FetchErrorKind::NotFound
+    } else if message.starts_with("Failed to decode") {
+        FetchErrorKind::Decode
+    } else {
+        FetchErrorKind::Other
+    }
+}
+
+/// Connect and total timeouts of every request, from `fetch.connect_timeout`
+/// and `fetch.timeout`
+static TIMEOUTS: OnceLock<(Duration, Duration)> = OnceLock::new();
+
+/// Sets the request timeouts once at startup; later calls are ignored
+pub fn set_timeouts(connect: Duration, total: Duration) {
+    let _ = TIMEOUTS.set((connect, total));
+}
+
+/// Set by `--offline`; every request then fails without touching the network
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+/// Makes every later request fail as a network error, so callers fall back
+/// to what they have cached
+pub fn set_offline() {
+    OFFLINE.store(true, Ordering::Relaxed);
+}
+
+/// True once [`set_offline`] has been called
+pub fn is_offline() -> bool {
+    OFFLINE.load(Ordering::Relaxed)
+}
+
+/// Set by `--demo`; prices are made up and nothing touches the network
+static DEMO: AtomicBool = AtomicBool::new(false);
+
+/// Switches to synthetic prices from [`demo`] and keeps the caches, state
+/// and default portfolio in a scratch directory instead of `~/.stocks`
+pub fn set_demo() {
+    DEMO.store(true, Ordering::Relaxed);
+}
+
+/// True once [`set_demo`] has been called
+pub fn is_demo() -> bool {
+    DEMO.load(Ordering::Relaxed)
+}
+
+/// Creates a client with proper headers and timeouts
+pub(crate) fn http_client() -> Result<reqwest::blocking::Client, Box<dyn Error + Send + Sync>> {
+    if is_offline() || is_demo() {
+        let mode = if is_demo() { "demo" } else { "offline" };
+        return Err(Box::new(FetchError {
+            kind: FetchErrorKind::Network,
+            message: format!("{} mode", mode),
+        }));
+    }
+    let (connect, total) =
+        *TIMEOUTS.get_or_init(|| (Duration::from_secs(5), Duration::from_secs(15)));
+    let client = reqwest::blocking::Client::builder()
+        .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
+        .gzip(false) // Disable gzip to avoid decoding issues
+        .connect_timeout(connect)
+        .timeout(total) // Total timeout including reading body
+        .tcp_keepalive(Duration::from_secs(60))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .build()?;
+    Ok(client)
+}
+
+/// Fetches a URL and returns the body, failing on any non-200 status
+fn fetch_body(url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+    let client = http_client()?;
+
+    // Fetch the page content
+    let started = Instant::now();
+    let response = client.get(url).send().inspect_err(|e| {
+        log::debug!(
+            "GET {} failed after {} ms: {}",
+            url,
+            started.elapsed().as_millis(),
+            e
+        )
+    })?;
+    log::debug!(
+        "GET {} HTTP {} in {} ms",
+        url,
+        response.status().as_u16(),
+        started.elapsed().as_millis()
+    );
+    if response.status() != 200 {
+        return Err(format!("Invalid status code HTTP{}", response.status()).into());
+    }
+
+    // Read response as bytes first, then convert to string
+    let bytes = response.bytes()?;
+    let body = String::from_utf8(bytes.to_vec())
+        .map_err(|e| format!("Failed to decode response: {}", e))?;
+    Ok(body)
+}
 
 /// Fetches the latest price for a given stock ticker from Yahoo Finance
 ///
@@ -24,29 +269,13 @@ use std::time::Duration;
 /// println!("Price: {}", price);
 /// ```
 pub fn fetch_latest_price(ticker: &str) -> Result<f64, Box<dyn Error + Send + Sync>> {
+    if is_demo() {
+        return Ok(demo::latest_price(ticker, date::unix_now()));
+    }
     // Construct the Yahoo Finance URL
     let url = format!("https://stooq.pl/q/?s={}", ticker.to_lowercase());
 
-    // Create a client with proper headers and timeouts
-    let client = reqwest::blocking::Client::builder()
-        .user_agent("Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36")
-        .gzip(false) // Disable gzip to avoid decoding issues
-        .connect_timeout(Duration::from_secs(5))
-        .timeout(Duration::from_secs(15)) // Total timeout including reading body
-        .tcp_keepalive(Duration::from_secs(60))
-        .pool_idle_timeout(Duration::from_secs(90))
-        .build()?;
-
-    // Fetch the page content
-    let response = client.get(&url).send()?;
-    if response.status() != 200 {
-        return Err(format!("Invalid status code HTTP{}", response.status()).into());
-    }
-
-    // Read response as bytes first, then convert to string
-    let bytes = response.bytes()?;
-    let body = String::from_utf8(bytes.to_vec())
-        .map_err(|e| format!("Failed to decode response: {}", e))?;
+    let body = fetch_body(&url)?;
 
     let pattern = format!(
         r#"id=aq_{}_c4[^>]+>([0-9]+\.?[0-9]*)</span>"#,
@@ -55,12 +284,64 @@ pub fn fetch_latest_price(ticker: &str) -> Result<f64, Box<dyn Error + Send + Sy
 
     let re_post = Regex::new(&pattern)?;
 
-    if let Some(captures) = re_post.captures(&body) {
-        if let Some(price_match) = captures.get(1) {
-            let price: f64 = price_match.as_str().parse()?;
-            return Ok(price);
-        }
+    if let Some(captures) = re_post.captures(&body)
+        && let Some(price_match) = captures.get(1)
+    {
+        let price: f64 = price_match.as_str().parse()?;
+        return Ok(price);
     }
 
     Err("Could not find price in response".into())
 }
+
+/// Fetches daily closing prices for a ticker from stooq, oldest first
+///
+/// # Arguments
+///
+/// * `ticker` - The stock ticker symbol (e.g., "AAPL.US")
+/// * `from` - The first date to include
+///
+/// # Example
+///
+/// ```no_run
+/// use xbar_stocks::date::Date;
+/// use xbar_stocks::fetch_daily_closes;
+///
+/// let from = Date::parse("2024-01-01").unwrap();
+/// let closes = fetch_daily_closes("AAPL.US", from).unwrap();
+/// println!("{} closes", closes.len());
+/// ```
+pub fn fetch_daily_closes(
+    ticker: &str,
+    from: Date,
+) -> Result<Vec<(Date, f64)>, Box<dyn Error + Send + Sync>> {
+    if is_demo() {
+        return Ok(demo::daily_closes(ticker, from, Date::today()));
+    }
+    let url = format!(
+        "https://stooq.pl/q/d/l/?s={}&i=d&d1={:04}{:02}{:02}",
+        ticker.to_lowercase(),
+        from.year,
+        from.month,
+        from.day
+    );
+    let body = fetch_body(&url)?;
+
+    // Columns are date, open, high, low, close[, volume]; header names are localized
+    let mut reader = csv::Reader::from_reader(body.as_bytes());
+    let mut closes = Vec::new();
+    for record in reader.records() {
+        let record = record?;
+        let date = record.get(0).and_then(Date::parse);
+        let close = record.get(4).and_then(|c| c.parse::<f64>().ok());
+        if let (Some(date), Some(close)) = (date, close) {
+            closes.push((date, close));
+        }
+    }
+
+    if closes.is_empty() {
+        return Err("No historical data in response".into());
+    }
+    closes.sort_by_key(|(date, _)| *date);
+    Ok(closes)
+}
@@ -0,0 +1,113 @@
+use crate::data_dir;
+use crate::json::Json;
+use crate::{FetchError, FetchErrorKind};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The daemon's latest result for one ticker
+#[derive(Debug, Clone)]
+pub struct LiveQuote {
+    pub ticker: String,
+    /// Price on success, the failure otherwise
+    pub result: Result<f64, FetchError>,
+    /// Unix time the price refers to
+    pub as_of: Option<i64>,
+    pub stale: bool,
+}
+
+/// Prices kept fresh by the daemon in `~/.stocks/state.json`, so the xbar
+/// entry point can render without touching the network
+#[derive(Debug, Clone)]
+pub struct LiveState {
+    /// Unix time the daemon last wrote the file
+    pub written_at: i64,
+    /// Whether the daemon's last round found the network down
+    pub offline: bool,
+    pub quotes: Vec<LiveQuote>,
+}
+
+impl LiveState {
+    /// Default location, `~/.stocks/state.json`
+    pub fn default_path() -> PathBuf {
+        data_dir().join("state.json")
+    }
+
+    /// Reads the state file, `None` if it is missing or unreadable
+    pub fn load(path: &Path) -> Option<LiveState> {
+        let doc = Json::parse(&fs::read_to_string(path).ok()?).ok()?;
+        let quotes = doc
+            .get("quotes")?
+            .as_array()?
+            .iter()
+            .filter_map(|quote| {
+                let ticker = quote.get("ticker")?.as_str()?.to_string();
+                let result = match quote.get("price").and_then(Json::as_f64) {
+                    Some(price) => Ok(price),
+                    None => {
+                        let error = quote.get("error")?;
+                        Err(FetchError {
+                            kind: error
+                                .get("kind")
+                                .and_then(Json::as_str)
+                                .and_then(FetchErrorKind::parse)
+                                .unwrap_or(FetchErrorKind::Other),
+                            message: error.get("message")?.as_str()?.to_string(),
+                        })
+                    }
+                };
+                Some(LiveQuote {
+                    ticker,
+                    result,
+                    as_of: quote.get("as_of").and_then(Json::as_f64).map(|t| t as i64),
+                    stale: quote.get("stale").and_then(Json::as_bool).unwrap_or(false),
+                })
+            })
+            .collect();
+        Some(LiveState {
+            written_at: doc.get("written_at")?.as_f64()? as i64,
+            offline: doc.get("offline").and_then(Json::as_bool).unwrap_or(false),
+            quotes,
+        })
+    }
+
+    /// Writes the state file atomically so readers never see a partial file
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let quotes = self
+            .quotes
+            .iter()
+            .map(|quote| {
+                let (price, error) = match &quote.result {
+                    Ok(price) => (Json::from(*price), Json::Null),
+                    Err(e) => (
+                        Json::Null,
+                        Json::object([
+                            ("kind", Json::from(e.kind.name())),
+                            ("message", Json::from(e.message.as_str())),
+                        ]),
+                    ),
+                };
+                Json::object([
+                    ("ticker", Json::from(quote.ticker.as_str())),
+                    ("price", price),
+                    ("error", error),
+                    ("as_of", Json::from(quote.as_of)),
+                    ("stale", Json::from(quote.stale)),
+                ])
+            })
+            .collect();
+        let doc = Json::object([
+            ("written_at", Json::from(self.written_at)),
+            ("offline", Json::from(self.offline)),
+            ("quotes", Json::Array(quotes)),
+        ]);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, format!("{}\n", doc))?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use std::env;
+
+/// How numbers and amounts are written: separators and where the currency
+/// symbol goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Locale {
+    /// Groups thousands, e.g. `,` in `1,234`
+    pub thousands: char,
+    /// Separates decimals, e.g. `,` in `12,50`
+    pub decimal: char,
+    /// The currency symbol follows the amount (`1.234 €`) instead of
+    /// preceding it (`$1,234`)
+    pub symbol_after: bool,
+}
+
+impl Locale {
+    /// The tool's own style, `$1 234.56`, used for the `C` locale
+    pub const DEFAULT: Locale = Locale {
+        thousands: ' ',
+        decimal: '.',
+        symbol_after: false,
+    };
+
+    /// Conventions of a locale name such as `de_DE.UTF-8` or `fr`; `C` and
+    /// `POSIX` give [`Locale::DEFAULT`], unknown languages `None`
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::locale::Locale;
+    ///
+    /// let german = Locale::from_name("de_DE.UTF-8").unwrap();
+    /// assert_eq!(german.money("€", 1234.5, 2), "1.234,50 €");
+    /// assert_eq!(Locale::from_name("en_US").unwrap().money("$", 1234.0, 0), "$1,234");
+    /// assert_eq!(Locale::from_name("xx"), None);
+    /// ```
+    pub fn from_name(name: &str) -> Option<Locale> {
+        let name = name.split(['.', '@']).next().unwrap_or(name);
+        let (language, region) = name.split_once(['_', '-']).unwrap_or((name, ""));
+        let locale = |thousands, decimal, symbol_after| Locale {
+            thousands,
+            decimal,
+            symbol_after,
+        };
+        Some(match (language, region) {
+            ("C" | "POSIX", _) => Locale::DEFAULT,
+            ("de" | "it", "CH") => locale('\'', '.', false),
+            ("nl", _) => locale('.', ',', false),
+            ("de" | "it" | "es" | "pt" | "da" | "tr" | "el" | "id" | "ro" | "hr" | "sl", _) => {
+                locale('.', ',', true)
+            }
+            (
+                "fr" | "pl" | "cs" | "sk" | "ru" | "uk" | "sv" | "nb" | "nn" | "no" | "fi" | "hu"
+                | "bg" | "lt" | "lv" | "et",
+                _,
+            ) => locale(' ', ',', true),
+            ("en" | "ja" | "zh" | "ko" | "he" | "th" | "ms" | "hi", _) => locale(',', '.', false),
+            _ => return None,
+        })
+    }
+
+    /// The locale from `LC_ALL`, `LC_MONETARY` or `LANG`, falling back to
+    /// [`Locale::DEFAULT`] when none is set or known
+    pub fn system() -> Locale {
+        ["LC_ALL", "LC_MONETARY", "LANG"]
+            .iter()
+            .filter_map(|key| env::var(key).ok())
+            .find(|value| !value.is_empty())
+            .and_then(|name| Locale::from_name(&name))
+            .unwrap_or(Locale::DEFAULT)
+    }
+
+    /// A number with grouped thousands and `decimals` places
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xbar_stocks::locale::Locale;
+    ///
+    /// assert_eq!(Locale::DEFAULT.number(-1234567.891, 2), "-1 234 567.89");
+    /// ```
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        let text = format!("{:.*}", decimals, value.abs());
+        let (integer, fraction) = text.split_once('.').unwrap_or((&text, ""));
+        let mut out = String::new();
+        if value < 0.0
+            && text
+                .bytes()
+                .any(|byte| byte.is_ascii_digit() && byte != b'0')
+        {
+            out.push('-');
+        }
+        for (i, digit) in integer.chars().enumerate() {
+            if i > 0 && (integer.len() - i).is_multiple_of(3) {
+                out.push(self.thousands);
+            }
+            out.push(digit);
+        }
+        if !fraction.is_empty() {
+            out.push(self.decimal);
+            out.push_str(fraction);
+        }
+        out
+    }
+
+    /// An amount with its currency symbol, such as `$1 234` or `1.234 €`;
+    /// symbols that are codes (`PLN `) keep their space either way
+    pub fn money(&self, symbol: &str, amount: f64, decimals: usize) -> String {
+        let number = self.number(amount, decimals);
+        if self.symbol_after {
+            format!("{} {}", number, symbol.trim_end())
+        } else {
+            format!("{}{}", symbol, number)
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::data_dir;
+use crate::date::{Date, unix_now};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Size at which the log file is rotated
+const MAX_BYTES: u64 = 1024 * 1024;
+/// Rotated files kept next to the current one, `stocks.log.1` the newest
+const KEEP: usize = 3;
+
+/// The log file, `~/.stocks/logs/stocks.log`
+pub fn default_path() -> PathBuf {
+    data_dir().join("logs").join("stocks.log")
+}
+
+/// Level for this crate from a `RUST_LOG` value: a bare level applies to
+/// everything, a `xbar_stocks=level` directive overrides it
+///
+/// # Example
+///
+/// ```
+/// use log::LevelFilter;
+/// use xbar_stocks::logging::parse_filter;
+///
+/// assert_eq!(parse_filter("debug"), Some(LevelFilter::Debug));
+/// assert_eq!(parse_filter("warn,xbar_stocks=trace"), Some(LevelFilter::Trace));
+/// assert_eq!(parse_filter("reqwest=debug"), None);
+/// assert_eq!(parse_filter("loud"), None);
+/// ```
+pub fn parse_filter(value: &str) -> Option<LevelFilter> {
+    let mut level = None;
+    for directive in value.split(',').map(str::trim) {
+        match directive.split_once('=') {
+            Some((target, filter)) if target == "xbar_stocks" || target == "xbar-stocks" => {
+                return filter.parse().ok();
+            }
+            Some(_) => {}
+            None => level = directive.parse().ok().or(level),
+        }
+    }
+    level
+}
+
+/// Writes records to stderr, where xbar leaves them out of the menu, and
+/// to a size-rotated file
+struct Logger {
+    level: LevelFilter,
+    stderr: bool,
+    file: Option<Mutex<LogFile>>,
+}
+
+struct LogFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl LogFile {
+    fn open(path: PathBuf) -> std::io::Result<LogFile> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(LogFile { path, file })
+    }
+
+    /// Shifts `stocks.log` to `stocks.log.1` and so on once it is too big,
+    /// dropping the oldest
+    fn rotate(&mut self) -> std::io::Result<()> {
+        if self.file.metadata()?.len() < MAX_BYTES {
+            return Ok(());
+        }
+        let numbered = |n: usize| PathBuf::from(format!("{}.{}", self.path.display(), n));
+        for n in (1..KEEP).rev() {
+            let _ = fs::rename(numbered(n), numbered(n + 1));
+        }
+        fs::rename(&self.path, numbered(1))?;
+        *self = LogFile::open(self.path.clone())?;
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level && metadata.target().starts_with("xbar_stocks")
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let now = unix_now();
+        let secs = now.rem_euclid(86_400);
+        let line = format!(
+            "{}T{:02}:{:02}:{:02}Z {:<5} {}",
+            Date::from_days(now.div_euclid(86_400)),
+            secs / 3600,
+            secs % 3600 / 60,
+            secs % 60,
+            record.level(),
+            record.args()
+        );
+        if self.stderr {
+            eprintln!("{}", line);
+        }
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.rotate();
+            let _ = writeln!(file.file, "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file
+            && let Ok(mut file) = file.lock()
+        {
+            let _ = file.file.flush();
+        }
+    }
+}
+
+/// Installs the logger: records up to `level` go to stderr when `stderr`
+/// is set and to the file at `file` when given
+pub fn init(level: LevelFilter, stderr: bool, file: Option<PathBuf>) -> std::io::Result<()> {
+    let file = file.map(LogFile::open).transpose()?.map(Mutex::new);
+    let logger = Logger {
+        level,
+        stderr,
+        file,
+    };
+    // The logger lives for the rest of the process
+    if log::set_logger(Box::leak(Box::new(logger))).is_ok() {
+        log::set_max_level(level);
+    }
+    Ok(())
+}
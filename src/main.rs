@@ -1,227 +1,4417 @@
+mod render;
+mod wizard;
+
+use log::{LevelFilter, debug, info, warn};
 use rayon::prelude::*;
+use render::{
+    Group, I3blocksRenderer, JsonRenderer, Line, MarkdownRenderer, PolybarRenderer, Quote,
+    Renderer, Report, Row, TemplateRenderer, TerminalRenderer, WaybarRenderer, XbarRenderer,
+};
 use serde::Deserialize;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::fs::File;
-use std::path::PathBuf;
-use xbar_stocks::fetch_latest_price;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use xbar_stocks::cache::QuoteCache;
+use xbar_stocks::cash;
+use xbar_stocks::config::{
+    Config, HeaderMode, PROVIDERS, Palette, Sort, SortKey, TrendIcon, Value as ConfigValue,
+};
+use xbar_stocks::csv_file::{self, CsvFile};
+use xbar_stocks::date::{Date, local_clock, unix_now};
+use xbar_stocks::demo;
+use xbar_stocks::encryption;
+use xbar_stocks::fx::{self, FxRates};
+use xbar_stocks::history::{HistoryStore, Snapshot, TOTAL_TICKER, close_on_or_before};
+use xbar_stocks::import::{self, Broker, Import};
+use xbar_stocks::indicators::{self, Cross, moving_averages, rsi};
+use xbar_stocks::json::Json;
+use xbar_stocks::ledger::{LedgerStyle, price_directive};
+use xbar_stocks::live::{LiveQuote, LiveState};
+use xbar_stocks::locale::Locale;
+use xbar_stocks::logging;
+use xbar_stocks::market::{Exchange, Session};
+use xbar_stocks::metrics::{self, Metrics, PositionMetric};
+use xbar_stocks::mqtt::{self, MqttClient};
+use xbar_stocks::notify;
+use xbar_stocks::offline::OfflineDetector;
+use xbar_stocks::parquet::{self, Column};
+use xbar_stocks::performance::{self, Holding, Period};
+use xbar_stocks::portfolio;
+use xbar_stocks::secrets::{self, SecretStore};
+use xbar_stocks::sheets;
+use xbar_stocks::sparkline::sparkline;
+use xbar_stocks::splits;
+use xbar_stocks::state::{self, State};
+use xbar_stocks::store::{self, Store};
+use xbar_stocks::symbols;
+use xbar_stocks::template::Template;
+use xbar_stocks::transactions::{self, CostBasis, Kind, Transaction};
+use xbar_stocks::watcher::FileWatcher;
+use xbar_stocks::width::{display_width, pad_left, pad_right};
+use xbar_stocks::{
+    FetchError, FetchErrorKind, classify_error, data_dir, fetch_daily_closes, fetch_latest_price,
+};
+
+type FetchResult = Result<f64, Box<dyn std::error::Error + Send + Sync>>;
+
+#[derive(Debug, Clone, Deserialize)]
+struct Position {
+    ticker: String,
+    buy_price: f64,
+    shares: f64,
+    /// Annual percentage yield for cash positions (e.g. 4.5)
+    #[serde(default)]
+    apy: Option<f64>,
+    /// Purchase date, or for cash the date the balance was last updated (YYYY-MM-DD)
+    #[serde(default)]
+    date: Option<String>,
+    /// Ticker to compare this position against (e.g. WIG_BANKI)
+    #[serde(default)]
+    benchmark: Option<String>,
+    /// Price the lot was sold at; sold lots move to the archive
+    #[serde(default)]
+    sell_price: Option<f64>,
+    /// Date the lot was sold (YYYY-MM-DD)
+    #[serde(default)]
+    sell_date: Option<String>,
+    /// Broker fee or commission paid for the lot; folded into `buy_price`
+    /// when the portfolio is loaded
+    #[serde(default)]
+    fee: Option<f64>,
+    /// Currency the prices are quoted in (ISO code), see [`position_currency`]
+    #[serde(default)]
+    currency: Option<String>,
+    /// Account the lot is held in (e.g. IKE, brokerage, 401k)
+    #[serde(default)]
+    account: Option<String>,
+    /// Semicolon-separated tags such as `tech;dividend`
+    #[serde(default)]
+    tags: Option<String>,
+    /// Name of the portfolio file the lot came from when several are merged
+    #[serde(skip)]
+    source: Option<String>,
+}
+
+/// Currency of a position: the `currency` column, else the suffix of a
+/// cash ticker such as `CASH.EUR`, else the base currency
+fn position_currency(position: &Position, base: &str) -> String {
+    if let Some(currency) = &position.currency {
+        return currency.to_uppercase();
+    }
+    match position.ticker.to_uppercase().split_once('.') {
+        Some((_, currency)) if cash::is_cash_ticker(&position.ticker) => currency.to_string(),
+        _ => base.to_uppercase(),
+    }
+}
+
+/// Prices, costs and closes converted into the base currency for totals
+///
+/// Positions without a known rate turn into errors. Closes are converted at
+/// today's rate, which keeps period changes in each position's own terms.
+fn in_base_currency(
+    results: &[(Position, FetchResult)],
+    closes: &Closes,
+    fx: &FxRates,
+) -> (Vec<(Position, FetchResult)>, Closes) {
+    let mut converted_closes = closes.clone();
+    let converted = results
+        .iter()
+        .map(|(position, result)| {
+            let currency = position_currency(position, fx.base());
+            let Some(rate) = fx.rate(&currency) else {
+                let error = format!("No {}/{} exchange rate", currency, fx.base());
+                return (position.clone(), Err(error.into()));
+            };
+            if let Some(series) = converted_closes.get_mut(&position.ticker) {
+                for (_, close) in series.iter_mut() {
+                    *close *= rate;
+                }
+            }
+            let result = match result {
+                Ok(price) => Ok(price * rate),
+                Err(e) => Err(e.to_string().into()),
+            };
+            (convert_lot(position, rate), result)
+        })
+        .collect();
+    (converted, converted_closes)
+}
+
+fn convert_lot(position: &Position, rate: f64) -> Position {
+    Position {
+        buy_price: position.buy_price * rate,
+        sell_price: position.sell_price.map(|price| price * rate),
+        ..position.clone()
+    }
+}
+
+/// Lots converted into the base currency, dropping those without a rate
+fn lots_in_base_currency(lots: &[Position], fx: &FxRates) -> Vec<Position> {
+    lots.iter()
+        .filter_map(|lot| {
+            let rate = fx.rate(&position_currency(lot, fx.base()))?;
+            Some(convert_lot(lot, rate))
+        })
+        .collect()
+}
+
+/// Loads one portfolio file, returning its positions and the rows that were
+/// skipped as invalid
+///
+/// Invalid rows are an error listing all of them unless
+/// `portfolio.skip_invalid_rows` is set.
+fn load_positions_from_csv(
+    file_path: &str,
+    config: &Config,
+    secrets: &dyn SecretStore,
+) -> Result<(Vec<Position>, Vec<String>), Box<dyn std::error::Error>> {
+    // "-" reads the portfolio piped in by another tool, a URL downloads a
+    // (Google) sheet, a SQLite store is read as its ledger, and encrypted
+    // .age/.gpg files are decrypted in memory with the stored secret
+    let contents = if file_path == STDIN_PATH {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents)?;
+        contents
+    } else if sheets::is_url(file_path) {
+        let token = secrets::api_key(secrets, sheets::TOKEN_PROVIDER);
+        sheets::fetch(file_path, token.as_deref()).map_err(|e| e.to_string())?
+    } else if store::is_store(Path::new(file_path)) {
+        Store::open(Path::new(file_path))
+            .and_then(|store| store.ledger_csv())
+            .map_err(|e| e.to_string())?
+    } else {
+        encryption::read_file(Path::new(file_path), secrets).map_err(|e| e.to_string())?
+    };
+    let splits = splits::load(&splits::default_path()).map_err(|e| e.to_string())?;
+    // Structured formats are known by extension, or by their first
+    // character when piped in
+    let structured = Some(Path::new(file_path))
+        .filter(|_| !sheets::is_url(file_path))
+        .and_then(Path::extension)
+        .and_then(|extension| portfolio::Format::from_extension(&extension.to_string_lossy()))
+        .or_else(|| portfolio::Format::sniff(&contents));
+    if transactions::is_ledger(&contents) {
+        let mut ledger = transactions::parse(&contents).map_err(|e| e.to_string())?;
+        // Express every row in today's shares so lots match across splits
+        for transaction in &mut ledger {
+            let factor =
+                splits::adjustment(&splits, &transaction.ticker, Some(transaction.date), None);
+            transaction.shares *= factor;
+            transaction.price /= factor;
+        }
+        let positions =
+            positions_from_ledger(&ledger, config.cost_basis).map_err(|e| e.to_string())?;
+        return Ok((positions, Vec::new()));
+    }
+    let (rows, problems) = match structured {
+        Some(format) => {
+            let entries = format
+                .parse(&String::from_utf8_lossy(&contents))
+                .map_err(|e| e.to_string())?;
+            let mut rows = Vec::new();
+            let mut problems = Vec::new();
+            for (index, entry) in entries.iter().enumerate() {
+                match position_from_entry(entry)
+                    .and_then(|position| validate_position(&position).map_or(Ok(position), Err))
+                {
+                    Ok(position) => rows.push(position),
+                    Err(e) => problems.push(format!("position {}: {}", index + 1, e)),
+                }
+            }
+            (rows, problems)
+        }
+        None => parse_csv_positions(&contents)?,
+    };
+    if !problems.is_empty() && !config.skip_invalid_rows {
+        return Err(problems.join("\n  ").into());
+    }
+    let mut positions = Vec::new();
+
+    for mut position in rows {
+        // Fees raise the break-even price just like a higher purchase price
+        if let Some(fee) = position.fee.take() {
+            position.buy_price += fee / position.shares;
+        }
+        // Quotes are in post-split shares, so lots bought before a split are too
+        let date = |value: &Option<String>| value.as_deref().and_then(Date::parse);
+        let factor = splits::adjustment(
+            &splits,
+            &position.ticker,
+            date(&position.date),
+            date(&position.sell_date),
+        );
+        position.shares *= factor;
+        position.buy_price /= factor;
+        positions.push(position);
+    }
+
+    Ok((positions, problems))
+}
+
+/// Reads the rows of a portfolio CSV, describing each row that cannot be
+/// used by its line, column and value
+fn parse_csv_positions(contents: &[u8]) -> Result<(Vec<Position>, Vec<String>), csv::Error> {
+    // Lines starting with # are notes, like those `init` writes
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_reader(contents);
+    let headers = reader.headers()?.clone();
+    let mut positions = Vec::new();
+    let mut problems = Vec::new();
+    for record in reader.records() {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                problems.push(match e.kind() {
+                    csv::ErrorKind::UnequalLengths {
+                        pos: Some(pos),
+                        expected_len,
+                        len,
+                    } => format!(
+                        "line {}: expected {} columns, found {}",
+                        pos.line(),
+                        expected_len,
+                        len
+                    ),
+                    _ => e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |pos| pos.line());
+        let error = match record.deserialize::<Position>(Some(&headers)) {
+            Ok(position) => match validate_position(&position) {
+                None => {
+                    positions.push(position);
+                    continue;
+                }
+                Some(problem) => problem,
+            },
+            Err(e) => match e.kind() {
+                csv::ErrorKind::Deserialize { err, .. } => {
+                    let problem = match err.kind() {
+                        csv::DeserializeErrorKind::ParseFloat(_)
+                        | csv::DeserializeErrorKind::ParseInt(_) => "expected a number".to_string(),
+                        csv::DeserializeErrorKind::ParseBool(_) => {
+                            "expected true or false".to_string()
+                        }
+                        csv::DeserializeErrorKind::Message(message) => message.clone(),
+                        other => other.to_string(),
+                    };
+                    match err.field().map(|index| index as usize) {
+                        Some(index) => format!(
+                            "column {}: {}, found '{}'",
+                            headers.get(index).unwrap_or("?"),
+                            problem,
+                            record.get(index).unwrap_or("")
+                        ),
+                        None => problem,
+                    }
+                }
+                _ => e.to_string(),
+            },
+        };
+        problems.push(format!("line {}, {}", line, error));
+    }
+    Ok((positions, problems))
+}
+
+/// What is wrong with a parsed row that serde accepts, such as a blank
+/// ticker or a misspelt date
+fn validate_position(position: &Position) -> Option<String> {
+    if position.ticker.trim().is_empty() {
+        return Some("column ticker: empty".to_string());
+    }
+    for (column, value) in [
+        ("buy_price", position.buy_price),
+        ("shares", position.shares),
+    ] {
+        if !value.is_finite() {
+            return Some(format!(
+                "column {}: expected a number, found '{}'",
+                column, value
+            ));
+        }
+    }
+    // The fee is spread over the shares, so a watched ticker cannot have one
+    if position.shares == 0.0 && position.fee.is_some_and(|fee| fee != 0.0) {
+        return Some("column fee: needs shares to spread the fee over".to_string());
+    }
+    for (column, value) in [("date", &position.date), ("sell_date", &position.sell_date)] {
+        if let Some(value) = value
+            && !value.is_empty()
+            && Date::parse(value).is_none()
+        {
+            return Some(format!(
+                "column {}: expected a YYYY-MM-DD date, found '{}'",
+                column, value
+            ));
+        }
+    }
+    None
+}
+
+/// A position from a TOML or YAML portfolio entry, with the same fields as
+/// the CSV columns; `tags` may also be a list
+fn position_from_entry(entry: &portfolio::Entry) -> Result<Position, String> {
+    let mut position = Position {
+        ticker: String::new(),
+        buy_price: f64::NAN,
+        shares: f64::NAN,
+        apy: None,
+        date: None,
+        benchmark: None,
+        sell_price: None,
+        sell_date: None,
+        fee: None,
+        currency: None,
+        account: None,
+        tags: None,
+        source: None,
+    };
+    for (key, value) in entry {
+        let number = || match value {
+            ConfigValue::Number(number) => Ok(*number),
+            _ => Err(format!("{}: expected a number", key)),
+        };
+        let text = || match value {
+            ConfigValue::String(text) => Ok(text.clone()),
+            ConfigValue::Number(number) => Ok(number.to_string()),
+            _ => Err(format!("{}: expected a string", key)),
+        };
+        match key.as_str() {
+            "ticker" => position.ticker = text()?,
+            "buy_price" => position.buy_price = number()?,
+            "shares" => position.shares = number()?,
+            "apy" => position.apy = Some(number()?),
+            "date" => position.date = Some(text()?),
+            "benchmark" => position.benchmark = Some(text()?),
+            "sell_price" => position.sell_price = Some(number()?),
+            "sell_date" => position.sell_date = Some(text()?),
+            "fee" => position.fee = Some(number()?),
+            "currency" => position.currency = Some(text()?),
+            "account" => position.account = Some(text()?),
+            "tags" => {
+                position.tags = Some(match value {
+                    ConfigValue::Array(tags) => tags
+                        .iter()
+                        .map(|tag| match tag {
+                            ConfigValue::String(tag) => Ok(tag.as_str()),
+                            _ => Err("tags: expected strings".to_string()),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?
+                        .join(";"),
+                    _ => text()?,
+                })
+            }
+            "provider" => {
+                let provider = text()?;
+                if !PROVIDERS.contains(&provider.as_str()) {
+                    return Err(format!("provider: unsupported value '{}'", provider));
+                }
+            }
+            _ => return Err(format!("unknown field '{}'", key)),
+        }
+    }
+    if position.ticker.is_empty() {
+        return Err("missing ticker".to_string());
+    }
+    if position.buy_price.is_nan() || position.shares.is_nan() {
+        return Err(format!(
+            "{}: buy_price and shares are required",
+            position.ticker
+        ));
+    }
+    Ok(position)
+}
+
+/// Open lots and sold lots derived from a transactions ledger, in the same
+/// shape as rows of a positions file
+fn positions_from_ledger(
+    ledger: &[Transaction],
+    method: CostBasis,
+) -> Result<Vec<Position>, Box<dyn std::error::Error + Send + Sync>> {
+    // Reinvested dividends buy at the day's close, downloaded if not cached
+    let store = HistoryStore::default_location();
+    let ledger = transactions::reinvest_dividends(ledger, |ticker, date| {
+        let closes = store
+            .closes(ticker, date.add_days(-7), Duration::MAX)
+            .ok()?;
+        closes
+            .iter()
+            .rev()
+            .find(|(day, _)| *day <= date && day.days_until(date) <= 7)
+            .map(|(_, close)| *close)
+    })?;
+    // The ledger gives the currency per row; every lot of a ticker shares it
+    let mut currencies: HashMap<&str, &str> = HashMap::new();
+    for transaction in &ledger {
+        if let Some(currency) = &transaction.currency {
+            currencies.entry(&transaction.ticker).or_insert(currency);
+        }
+    }
+    let currency = |ticker: &str| currencies.get(ticker).map(|currency| currency.to_string());
+    let holdings = transactions::holdings(&ledger, method)?;
+    let open = holdings.open.into_iter().map(|lot| Position {
+        currency: currency(&lot.ticker),
+        ticker: lot.ticker,
+        buy_price: lot.price,
+        shares: lot.shares,
+        apy: None,
+        date: Some(lot.date.to_string()),
+        benchmark: None,
+        sell_price: None,
+        sell_date: None,
+        fee: None,
+        account: None,
+        tags: None,
+        source: None,
+    });
+    let closed = holdings.closed.into_iter().map(|lot| Position {
+        currency: currency(&lot.ticker),
+        ticker: lot.ticker,
+        buy_price: lot.buy_price,
+        shares: lot.shares,
+        apy: None,
+        date: Some(lot.bought.to_string()),
+        benchmark: None,
+        sell_price: Some(lot.sell_price),
+        sell_date: Some(lot.sold.to_string()),
+        fee: None,
+        account: None,
+        tags: None,
+        source: None,
+    });
+    Ok(open.chain(closed).collect())
+}
+
+/// What the dropdown groups positions by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupBy {
+    /// One flat list
+    Flat,
+    Account,
+    /// Portfolio file, when several are merged
+    File,
+    /// Every tag of a position; positions with several tags appear under each
+    Tag,
+}
+
+impl GroupBy {
+    fn parse(name: &str) -> Option<GroupBy> {
+        match name {
+            "none" => Some(GroupBy::Flat),
+            "account" => Some(GroupBy::Account),
+            "file" => Some(GroupBy::File),
+            "tag" => Some(GroupBy::Tag),
+            _ => None,
+        }
+    }
+
+    /// The name `--group-by` takes
+    fn name(&self) -> &'static str {
+        match self {
+            GroupBy::Flat => "none",
+            GroupBy::Account => "account",
+            GroupBy::File => "file",
+            GroupBy::Tag => "tag",
+        }
+    }
+
+    /// Groups a position belongs to; `None` collects the unassigned ones
+    fn keys(&self, position: &Position) -> Vec<Option<String>> {
+        let keys: Vec<Option<String>> = match self {
+            GroupBy::Flat => Vec::new(),
+            GroupBy::Account => vec![position.account.clone()],
+            GroupBy::File => vec![position.source.clone()],
+            GroupBy::Tag => position
+                .tags
+                .iter()
+                .flat_map(|tags| tags.split(';'))
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(|tag| Some(tag.to_string()))
+                .collect(),
+        };
+        if keys.is_empty() { vec![None] } else { keys }
+    }
+
+    /// Heading of the group of positions without an account or tag
+    fn unassigned_label(&self) -> &'static str {
+        match self {
+            GroupBy::Tag => "Untagged",
+            _ => "Other",
+        }
+    }
+}
+
+/// How the portfolio is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    /// xbar menu lines (default)
+    Xbar,
+    /// One JSON document for scripts
+    Json,
+    /// An aligned, colored table for a shell
+    Terminal,
+    /// A Waybar custom module's JSON
+    Waybar,
+    /// An i3blocks block
+    I3blocks,
+    /// A Polybar module line
+    Polybar,
+    /// A Markdown summary and table
+    Markdown,
+    /// The user's template from `template.path`
+    Template,
+}
+
+/// Whether xbar or SwiftBar is running this as a plugin
+fn in_menu_bar() -> bool {
+    // They set these for the plugins they run
+    env::var_os("XBARDarkMode").is_some() || env::var_os("SWIFTBAR").is_some()
+}
+
+impl Format {
+    /// The table when run by hand in a terminal, xbar lines otherwise
+    fn default_for_stdout() -> Format {
+        if io::stdout().is_terminal() && !in_menu_bar() {
+            Format::Terminal
+        } else {
+            Format::Xbar
+        }
+    }
+
+    fn renderer(
+        &self,
+        config: &Config,
+    ) -> Result<Box<dyn Renderer>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(match self {
+            Format::Xbar => Box::new(XbarRenderer {
+                columns: config.columns.clone(),
+                arrows: config.arrows,
+                font: config.font.clone(),
+            }),
+            Format::Json => Box::new(JsonRenderer),
+            Format::Terminal => Box::new(TerminalRenderer {
+                color: env::var_os("NO_COLOR").is_none(),
+            }),
+            Format::Waybar => Box::new(WaybarRenderer),
+            Format::I3blocks => Box::new(I3blocksRenderer),
+            Format::Polybar => Box::new(PolybarRenderer {
+                summary_only: config.polybar_summary_only,
+            }),
+            Format::Markdown => Box::new(MarkdownRenderer),
+            Format::Template => {
+                let path = config
+                    .template
+                    .as_ref()
+                    .ok_or("set template.path in config.toml")?;
+                let text = std::fs::read_to_string(path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?;
+                let template =
+                    Template::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+                Box::new(TemplateRenderer { template })
+            }
+        })
+    }
+}
+
+/// What the binary was asked to do
+enum Command {
+    /// Render the xbar menu (default)
+    Show,
+    /// Record an end-of-day snapshot to the history store
+    Snapshot,
+    /// Store a provider API key in the secret store
+    AuthSet(String),
+    /// Remove a provider API key from the secret store
+    AuthDelete(String),
+    /// Dump stored history as CSV to stdout
+    ExportHistory(ExportOptions),
+    /// Print today's prices as plain-text accounting directives
+    Prices(LedgerStyle),
+    /// Keep refreshing prices into `~/.stocks/state.json` for fast rendering
+    Daemon,
+    /// Record a purchase in the portfolio file or SQLite store
+    Add(Trade),
+    /// Record a sale in the portfolio file or SQLite store
+    Sell(Trade),
+    /// Create the data directory with a sample portfolio and config
+    Init,
+    /// Drop a position from the portfolio file
+    Remove { ticker: String, dry_run: bool },
+    /// Print the consolidated holdings without fetching prices
+    List,
+    /// Print the transactions in the SQLite store
+    Transactions,
+    /// Fetch every ticker once and report the ones the provider does not know
+    Validate,
+    /// Look up symbols by name
+    Search(String),
+    /// Print one ticker's quote, as JSON when set
+    Quote { ticker: String, json: bool },
+    /// Convert a broker export into a ledger CSV on stdout
+    Import(Broker),
+    /// Turn privacy mode on or off, or flip it when `None`
+    Privacy(Option<bool>),
+}
+
+/// A buy or sell given on the command line
+struct Trade {
+    ticker: String,
+    shares: f64,
+    price: f64,
+    date: Date,
+    fee: Option<f64>,
+    /// Print the resulting portfolio instead of saving it
+    dry_run: bool,
+}
+
+const TRADE_USAGE: &str = "Usage: xbar-stocks add|sell <ticker> <shares> <price> [--date YYYY-MM-DD] [--fee amount] [--dry-run] [path/to/data.csv | portfolio.db]";
+const REMOVE_USAGE: &str = "Usage: xbar-stocks remove <ticker> [--dry-run] [path/to/data.csv]";
+
+/// Parses the arguments following `add` or `sell`, returning the trade and
+/// the database path if one was given
+fn parse_trade(args: &[String]) -> (Trade, Option<PathBuf>) {
+    let mut date = Date::today();
+    let mut fee = None;
+    let mut dry_run = false;
+    let mut positional = Vec::new();
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        match arg {
+            "--date" => {
+                date = args
+                    .next()
+                    .and_then(Date::parse)
+                    .unwrap_or_else(|| usage_exit(TRADE_USAGE))
+            }
+            "--fee" => fee = Some(parse_amount(args.next())),
+            "--dry-run" => dry_run = true,
+            _ => positional.push(arg),
+        }
+    }
+    let (ticker, shares, price, path) = match positional.as_slice() {
+        [ticker, shares, price] => (ticker, shares, price, None),
+        [ticker, shares, price, path] => (ticker, shares, price, Some(PathBuf::from(path))),
+        _ => usage_exit(TRADE_USAGE),
+    };
+    let shares = parse_amount(Some(shares));
+    if shares <= 0.0 {
+        usage_exit(TRADE_USAGE);
+    }
+    let trade = Trade {
+        ticker: ticker.to_string(),
+        shares,
+        price: parse_amount(Some(price)),
+        date,
+        fee,
+        dry_run,
+    };
+    (trade, path)
+}
+
+fn parse_amount(arg: Option<&str>) -> f64 {
+    arg.and_then(|value| value.parse::<f64>().ok())
+        .filter(|value| value.is_finite() && *value >= 0.0)
+        .unwrap_or_else(|| usage_exit(TRADE_USAGE))
+}
+
+/// Handles `add`, `sell` and `list` against the SQLite store at `path`
+fn run_store(
+    command: &Command,
+    path: &Path,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let store = Store::open(path)?;
+    let (kind, trade) = match command {
+        Command::Add(trade) => (Kind::Buy, trade),
+        Command::Sell(trade) => (Kind::Sell, trade),
+        Command::Transactions => {
+            print!("{}", store.table()?);
+            return Ok(());
+        }
+        _ => return Ok(()),
+    };
+    let transaction = Transaction {
+        date: trade.date,
+        kind,
+        ticker: trade.ticker.clone(),
+        shares: trade.shares,
+        price: trade.price,
+        fee: trade.fee,
+        amount: None,
+        reinvested: false,
+        currency: None,
+    };
+    let mut ledger = store.transactions()?;
+    let closed_before = transactions::holdings(&ledger, config.cost_basis)?
+        .closed
+        .len();
+    ledger.push(transaction.clone());
+    ledger.sort_by_key(|transaction| transaction.date);
+    // Refuses a sale of more shares than the ledger holds before writing it
+    let holdings = transactions::holdings(&ledger, config.cost_basis)?;
+    if trade.dry_run {
+        import::write_ledger(&ledger, io::stdout().lock())?;
+        eprintln!("Dry run: {} not changed", path.display());
+        return Ok(());
+    }
+    let id = store.insert(&transaction)?;
+    eprintln!(
+        "Recorded {} of {} {} at {} on {} (#{})",
+        if kind == Kind::Buy {
+            "purchase"
+        } else {
+            "sale"
+        },
+        trade.shares,
+        trade.ticker,
+        trade.price,
+        trade.date,
+        id
+    );
+    if kind == Kind::Sell {
+        let realized: f64 = holdings.closed[closed_before..]
+            .iter()
+            .map(|lot| (lot.sell_price - lot.buy_price) * lot.shares)
+            .sum();
+        eprintln!("Realized {:+.2}", realized);
+    }
+    Ok(())
+}
+
+/// Checks that the provider knows `ticker` before it is written anywhere;
+/// when the provider cannot be reached the trade is recorded unverified
+fn verify_ticker(ticker: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    if cash::is_cash_ticker(ticker) {
+        return Ok(());
+    }
+    match fetch_latest_price(ticker) {
+        Ok(_) => Ok(()),
+        Err(e) if classify_error(e.as_ref()) == FetchErrorKind::NotFound => Err(format!(
+            "{} not found at the provider; check the symbol, e.g. AAPL.US",
+            ticker
+        )
+        .into()),
+        Err(e) => {
+            eprintln!("Could not verify {} ({}); adding it anyway", ticker, e);
+            Ok(())
+        }
+    }
+}
+
+/// Handles `add` for a CSV portfolio or ledger: merges the purchase into the
+/// open lot of the same ticker when the file keeps one row per ticker,
+/// otherwise appends it, and replaces the file atomically
+fn add_to_file(
+    trade: &Trade,
+    path: &Path,
+    secrets: &dyn SecretStore,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let structured = path
+        .extension()
+        .and_then(|extension| portfolio::Format::from_extension(&extension.to_string_lossy()));
+    if structured.is_some() || sheets::is_url(&path.to_string_lossy()) {
+        return Err(format!(
+            "add writes CSV portfolios and SQLite stores; edit {} by hand",
+            path.display()
+        )
+        .into());
+    }
+    let ticker = trade.ticker.to_uppercase();
+    verify_ticker(&ticker)?;
+    let mut file = if path.exists() {
+        CsvFile::parse(&encryption::read_file(path, secrets)?)?
+    } else {
+        CsvFile::new(&["ticker", "buy_price", "shares"])
+    };
+    let optional = |value: Option<f64>| value.map(csv_file::decimal).unwrap_or_default();
+    let merged = if file.is_ledger() {
+        file.push(&[
+            ("date", trade.date.to_string()),
+            ("type", "buy".to_string()),
+            ("ticker", ticker.clone()),
+            ("shares", csv_file::decimal(trade.shares)),
+            ("price", csv_file::decimal(trade.price)),
+            ("fee", optional(trade.fee)),
+        ]);
+        false
+    } else if file.column("date").is_none()
+        && trade.fee.is_none()
+        && file.merge_buy(&ticker, trade.shares, trade.price)
+    {
+        true
+    } else {
+        file.push(&[
+            ("ticker", ticker.clone()),
+            ("buy_price", csv_file::decimal(trade.price)),
+            ("shares", csv_file::decimal(trade.shares)),
+            ("date", trade.date.to_string()),
+            ("fee", optional(trade.fee)),
+        ]);
+        false
+    };
+    if !save_file(&file, path, trade.dry_run, secrets)? {
+        return Ok(());
+    }
+    eprintln!(
+        "{} {} {} at {} {} {}",
+        if merged { "Merged" } else { "Added" },
+        trade.shares,
+        ticker,
+        trade.price,
+        if merged { "into" } else { "to" },
+        path.display()
+    );
+    Ok(())
+}
+
+/// Handles `sell` for a CSV portfolio or ledger, recording sold lots when
+/// the portfolio has `sell_price` and `sell_date` columns
+fn sell_from_file(
+    trade: &Trade,
+    path: &Path,
+    config: &Config,
+    secrets: &dyn SecretStore,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let contents = encryption::read_file(path, secrets)?;
+    let mut file = CsvFile::parse(&contents)?;
+    let ticker = trade.ticker.to_uppercase();
+    let realized = if file.is_ledger() {
+        let optional = |value: Option<f64>| value.map(csv_file::decimal).unwrap_or_default();
+        file.push(&[
+            ("date", trade.date.to_string()),
+            ("type", "sell".to_string()),
+            ("ticker", ticker.clone()),
+            ("shares", csv_file::decimal(trade.shares)),
+            ("price", csv_file::decimal(trade.price)),
+            ("fee", optional(trade.fee)),
+        ]);
+        let mut ledger = transactions::parse(&contents)?;
+        let closed_before = transactions::holdings(&ledger, config.cost_basis)?
+            .closed
+            .len();
+        ledger.push(Transaction {
+            date: trade.date,
+            kind: Kind::Sell,
+            ticker: ticker.clone(),
+            shares: trade.shares,
+            price: trade.price,
+            fee: trade.fee,
+            amount: None,
+            reinvested: false,
+            currency: None,
+        });
+        ledger.sort_by_key(|transaction| transaction.date);
+        let holdings = transactions::holdings(&ledger, config.cost_basis)?;
+        holdings.closed[closed_before..]
+            .iter()
+            .map(|lot| (lot.sell_price - lot.buy_price) * lot.shares)
+            .sum()
+    } else {
+        // The fee comes out of the proceeds, as in a ledger
+        let price = trade.price - trade.fee.unwrap_or(0.0) / trade.shares;
+        file.sell(&ticker, trade.shares, price, &trade.date.to_string())?
+    };
+    if !save_file(&file, path, trade.dry_run, secrets)? {
+        return Ok(());
+    }
+    eprintln!(
+        "Sold {} {} at {} from {}; realized {:+.2}",
+        trade.shares,
+        ticker,
+        trade.price,
+        path.display(),
+        realized
+    );
+    if !file.is_ledger() && file.column("sell_price").is_none() {
+        eprintln!("Add sell_price and sell_date columns to keep sold lots in the archive");
+    }
+    Ok(())
+}
+
+/// Handles `remove`, dropping every open lot of a ticker from a CSV portfolio
+fn remove_from_file(
+    ticker: &str,
+    path: &Path,
+    dry_run: bool,
+    secrets: &dyn SecretStore,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut file = if store::is_store(path) {
+        None
+    } else {
+        Some(CsvFile::parse(&encryption::read_file(path, secrets)?)?)
+    }
+    .filter(|file| !file.is_ledger())
+    .ok_or_else(|| {
+        format!(
+            "{} is a ledger; record a sale with `xbar-stocks sell` instead",
+            path.display()
+        )
+    })?;
+    let removed = file.remove(ticker);
+    if removed == 0 {
+        return Err(format!("no open position in {} in {}", ticker, path.display()).into());
+    }
+    if save_file(&file, path, dry_run, secrets)? {
+        eprintln!(
+            "Removed {} lots of {} from {}",
+            removed,
+            ticker,
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Replaces the portfolio file atomically, or for a dry run prints what
+/// would be written; true if the file was written
+fn save_file(
+    file: &CsvFile,
+    path: &Path,
+    dry_run: bool,
+    secrets: &dyn SecretStore,
+) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+    let contents = file.to_bytes()?;
+    if dry_run {
+        io::stdout().write_all(&contents)?;
+        eprintln!("Dry run: {} not changed", path.display());
+        return Ok(false);
+    }
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    encryption::write_file(path, &contents, secrets)?;
+    Ok(true)
+}
+
+/// What `export-history` writes
+struct ExportOptions {
+    /// Daily closes per ticker instead of portfolio snapshots
+    closes: bool,
+    /// A Parquet file instead of CSV
+    parquet: bool,
+    from: Option<Date>,
+    to: Option<Date>,
+}
+
+const EXPORT_USAGE: &str = "Usage: xbar-stocks history [snapshots|closes] [--format csv|parquet] [--from YYYY-MM-DD] [--to YYYY-MM-DD]";
+
+/// Parses the arguments following `history` or `export-history`
+fn parse_export(args: &[String]) -> ExportOptions {
+    let mut options = ExportOptions {
+        closes: false,
+        parquet: false,
+        from: None,
+        to: None,
+    };
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        match arg {
+            "snapshots" => options.closes = false,
+            "closes" => options.closes = true,
+            "--format" => match args.next() {
+                Some("csv") => options.parquet = false,
+                Some("parquet") => options.parquet = true,
+                _ => usage_exit(EXPORT_USAGE),
+            },
+            "--from" => options.from = Some(parse_date_arg(args.next())),
+            "--to" => options.to = Some(parse_date_arg(args.next())),
+            _ => usage_exit(EXPORT_USAGE),
+        }
+    }
+    options
+}
+
+fn parse_date_arg(arg: Option<&str>) -> Date {
+    arg.and_then(Date::parse)
+        .unwrap_or_else(|| usage_exit(EXPORT_USAGE))
+}
+
+fn usage_exit(usage: &str) -> ! {
+    eprintln!("{}", usage);
+    std::process::exit(1);
+}
+
+/// Writes snapshots or closes within the date range as CSV to stdout,
+/// sorted by date and ticker so repeated exports diff cleanly
+fn export_history(options: &ExportOptions) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let store = HistoryStore::default_location();
+    let in_range = |date: Date| {
+        options.from.is_none_or(|from| date >= from) && options.to.is_none_or(|to| date <= to)
+    };
+
+    let columns = if options.closes {
+        let mut rows = Vec::new();
+        for ticker in store.close_tickers()? {
+            for (date, close) in store.load_closes(&ticker)? {
+                if in_range(date) {
+                    rows.push((date, ticker.clone(), close));
+                }
+            }
+        }
+        rows.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+        vec![
+            ("date", Column::Date(rows.iter().map(|row| row.0).collect())),
+            (
+                "ticker",
+                Column::Text(rows.iter().map(|row| row.1.clone()).collect()),
+            ),
+            (
+                "close",
+                Column::Double(rows.iter().map(|row| row.2).collect()),
+            ),
+        ]
+    } else {
+        let mut snapshots = store.load_snapshots()?;
+        snapshots.retain(|snapshot| in_range(snapshot.date));
+        snapshots.sort_by(|a, b| (a.date, &a.ticker).cmp(&(b.date, &b.ticker)));
+        let numbers = |value: fn(&Snapshot) -> f64| snapshots.iter().map(value).collect();
+        vec![
+            (
+                "date",
+                Column::Date(snapshots.iter().map(|s| s.date).collect()),
+            ),
+            (
+                "ticker",
+                Column::Text(snapshots.iter().map(|s| s.ticker.clone()).collect()),
+            ),
+            ("shares", Column::Double(numbers(|s| s.shares))),
+            ("cost", Column::Double(numbers(|s| s.cost))),
+            (
+                "price",
+                Column::OptionalDouble(snapshots.iter().map(|s| s.price).collect()),
+            ),
+            ("value", Column::Double(numbers(|s| s.value))),
+        ]
+    };
+
+    let mut out = io::stdout().lock();
+    if options.parquet {
+        if out.is_terminal() {
+            return Err("Parquet is binary; redirect it into a file".into());
+        }
+        parquet::write(&mut out, &columns)?;
+        return Ok(out.flush()?);
+    }
+    let mut writer = csv::Writer::from_writer(out);
+    writer.write_record(columns.iter().map(|(name, _)| name))?;
+    let rows = columns.first().map_or(0, |(_, column)| column.len());
+    for row in 0..rows {
+        writer.write_record(columns.iter().map(|(_, column)| column.text(row)))?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Converts a broker export to a ledger, or for positions exports a
+/// portfolio CSV, on stdout, ready to be redirected into a portfolio file
+fn run_import(broker: Broker, path: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let contents = if path == Path::new(STDIN_PATH) {
+        let mut contents = Vec::new();
+        io::stdin().read_to_end(&mut contents)?;
+        contents
+    } else {
+        std::fs::read(path)?
+    };
+    let mut transactions = match broker.import(&contents)? {
+        Import::Transactions(transactions) => transactions,
+        Import::Holdings(holdings) => {
+            import::write_positions(&holdings, io::stdout().lock())?;
+            eprintln!("Imported {} positions", holdings.len());
+            return Ok(());
+        }
+    };
+    let unmapped = import::apply_symbols(
+        &mut transactions,
+        &import::load_isins(&import::isins_path())?,
+    );
+    if !unmapped.is_empty() {
+        eprintln!(
+            "No ticker for {}; add isin,ticker rows to {} and import again",
+            unmapped.join(", "),
+            import::isins_path().display()
+        );
+    }
+    import::write_ledger(&transactions, io::stdout().lock())?;
+    eprintln!("Imported {} transactions", transactions.len());
+    Ok(())
+}
+
+/// Parsed command line
+struct Cli {
+    command: Command,
+    format: Format,
+    /// Reprint on this interval until interrupted
+    watch: Option<Duration>,
+    /// Grouping of the dropdown; by default by account when accounts are named
+    group_by: Option<GroupBy>,
+    /// Order of the positions, overriding `display.sort`
+    sort: Option<Sort>,
+    /// Portfolio files or directories of them, merged into one portfolio
+    csv_paths: Vec<PathBuf>,
+}
+
+/// Parses an interval such as "60s", "5m", "1h" or plain seconds
+fn parse_interval(text: &str) -> Option<Duration> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text, "s"),
+    };
+    let number: u64 = number.parse().ok()?;
+    let secs = match unit {
+        "s" => number,
+        "m" => number * 60,
+        "h" => number * 3600,
+        _ => return None,
+    };
+    (secs > 0).then(|| Duration::from_secs(secs))
+}
+
+const RUN_USAGE: &str = "Usage: xbar-stocks [run] [--offline | --refresh] [--currency CODE] [--jobs N] [--portfolio NAME | all] [--verbose] [--strict] [--demo] [--format xbar|json|terminal|waybar|i3blocks|polybar|markdown|template] [--watch 60s|5m|1h] [--group-by account|file|tag|none] [--sort ticker|value|profit|change|day[:asc|:desc]] [path/to/data.csv | -]";
+const REPORT_USAGE: &str = "Usage: xbar-stocks report [--format terminal|markdown|json] [--group-by account|file|tag|none] [--sort ticker|value|profit|change|day[:asc|:desc]] [path/to/data.csv | -]";
+const IMPORT_USAGE: &str = "Usage: xbar-stocks import ibkr|degiro|revolut|trading212|schwab|fidelity|xtb|mbank|qif|ofx <export | ->";
+const LIST_USAGE: &str = "Usage: xbar-stocks list [path/to/data.csv ... | dir | -] | list --transactions [path/to/portfolio.db]";
+const PRICES_USAGE: &str =
+    "Usage: xbar-stocks prices [--format beancount|ledger] [path/to/data.csv | -]";
+const INIT_USAGE: &str = "Usage: xbar-stocks init [path/to/data.csv]";
+const VALIDATE_USAGE: &str = "Usage: xbar-stocks validate [path/to/data.csv ... | dir | -]";
+const QUOTE_USAGE: &str = "Usage: xbar-stocks quote <ticker> [--json]";
+const SEARCH_USAGE: &str = "Usage: xbar-stocks search <name | symbol | ISIN>";
+const SNAPSHOT_USAGE: &str = "Usage: xbar-stocks snapshot [path/to/data.csv]";
+const DAEMON_USAGE: &str = "Usage: xbar-stocks daemon [path/to/data.csv]";
+const PRIVACY_USAGE: &str = "Usage: xbar-stocks privacy [on|off|toggle]";
+const AUTH_USAGE: &str = "Usage: xbar-stocks auth set|delete <provider>";
+
+/// A subcommand as listed by `--help`
+struct Help {
+    name: &'static str,
+    usage: &'static str,
+    about: &'static str,
+}
+
+const COMMANDS: &[Help] = &[
+    Help {
+        name: "init",
+        usage: INIT_USAGE,
+        about: "Create ~/.stocks with a sample portfolio and settings",
+    },
+    Help {
+        name: "run",
+        usage: RUN_USAGE,
+        about: "Fetch prices and print the portfolio for xbar (the default)",
+    },
+    Help {
+        name: "report",
+        usage: REPORT_USAGE,
+        about: "Print the portfolio as a table for reading in a terminal",
+    },
+    Help {
+        name: "add",
+        usage: TRADE_USAGE,
+        about: "Record a purchase in the portfolio file or SQLite store",
+    },
+    Help {
+        name: "sell",
+        usage: TRADE_USAGE,
+        about: "Record a sale, keeping the realized profit where the file can",
+    },
+    Help {
+        name: "remove",
+        usage: REMOVE_USAGE,
+        about: "Drop a position from the portfolio file",
+    },
+    Help {
+        name: "list",
+        usage: LIST_USAGE,
+        about: "Print the holdings and what they cost, without fetching prices",
+    },
+    Help {
+        name: "validate",
+        usage: VALIDATE_USAGE,
+        about: "Check that the provider knows every ticker, suggesting fixes",
+    },
+    Help {
+        name: "quote",
+        usage: QUOTE_USAGE,
+        about: "Print the price and day change of one ticker",
+    },
+    Help {
+        name: "search",
+        usage: SEARCH_USAGE,
+        about: "Find the symbol of a company or fund to add",
+    },
+    Help {
+        name: "import",
+        usage: IMPORT_USAGE,
+        about: "Convert a broker export into a portfolio file on stdout",
+    },
+    Help {
+        name: "history",
+        usage: EXPORT_USAGE,
+        about: "Export stored snapshots or daily closes as CSV or Parquet",
+    },
+    Help {
+        name: "snapshot",
+        usage: SNAPSHOT_USAGE,
+        about: "Record today's values in the history store",
+    },
+    Help {
+        name: "prices",
+        usage: PRICES_USAGE,
+        about: "Print today's prices as beancount or ledger directives",
+    },
+    Help {
+        name: "daemon",
+        usage: DAEMON_USAGE,
+        about: "Keep prices fresh in the background for fast rendering",
+    },
+    Help {
+        name: "privacy",
+        usage: PRIVACY_USAGE,
+        about: "Hide or show amounts in the menu",
+    },
+    Help {
+        name: "auth",
+        usage: AUTH_USAGE,
+        about: "Store or remove a provider API key",
+    },
+];
+
+/// Prints the usage of `command`, or the list of commands when it is
+/// missing or unknown
+fn print_help(command: Option<&str>) {
+    if let Some(help) = COMMANDS.iter().find(|help| Some(help.name) == command) {
+        println!("{}\n\n{}", help.about, help.usage);
+        return;
+    }
+    println!("Stock portfolio tracker for the xbar menu bar\n");
+    println!("Usage: xbar-stocks [command] [options] [path/to/data.csv | -]\n");
+    println!("Commands:");
+    for help in COMMANDS {
+        println!("  {:<10}{}", help.name, help.about);
+    }
+    println!("\nOptions:");
+    println!("  --offline         Never touch the network; price from the quote cache");
+    println!("  --refresh         Fetch every quote again, ignoring cached prices");
+    println!("  --currency CODE   Convert every amount into CODE for this run");
+    println!("  --jobs N          Fetch at most N quotes at the same time");
+    println!("  --portfolio NAME  Use ~/.stocks/NAME.csv and its [portfolios.NAME] settings,");
+    println!("                    or every portfolio with a subtotal each for `all`");
+    println!("  --verbose         Log fetches and cache hits to stderr (or set RUST_LOG)");
+    println!("  --strict          Print nothing and exit non-zero if any quote fails");
+    println!("  --demo            Show a sample portfolio with made-up prices, offline");
+    println!("\nExit status:");
+    println!("  0  every quote fetched");
+    println!("  1  invalid arguments or another error");
+    println!(
+        "  {}  some quotes failed (not when run by xbar, unless --strict)",
+        EXIT_PARTIAL
+    );
+    println!(
+        "  {}  invalid config file, STOCKS_* variable or portfolio file",
+        EXIT_CONFIG
+    );
+    println!("  {}  no quote could be fetched", EXIT_FETCH_FAILED);
+    println!("\nRun `xbar-stocks <command> --help` for the options of a command.");
+}
+
+/// Flags every command accepts, anywhere on the command line
+struct Options {
+    /// Never touch the network; prices come from the quote cache
+    offline: bool,
+    /// Fetch every quote again, ignoring the cache and the daemon
+    refresh: bool,
+    /// Currency to convert the whole report into instead of the base currency
+    currency: Option<String>,
+    /// Quotes fetched at the same time, overriding `fetch.concurrency`
+    jobs: Option<usize>,
+    /// Log fetches, timings and cache hits to stderr
+    verbose: bool,
+    /// Exit without output on any failed quote, whatever the format
+    strict: bool,
+    /// Made-up prices for a sample portfolio, without the network
+    demo: bool,
+}
+
+fn parse_command() -> (Cli, Options) {
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let mut value = |name: &str, expected: &str, valid: fn(&str) -> bool| {
+        let index = args.iter().position(|arg| arg == name)?;
+        match args.get(index + 1) {
+            Some(value) if valid(value) => args.drain(index..=index + 1).nth(1),
+            _ => {
+                eprintln!("{} takes {}", name, expected);
+                std::process::exit(1);
+            }
+        }
+    };
+    let currency = value(
+        "--currency",
+        "a three-letter currency code, e.g. EUR",
+        |code| code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()),
+    )
+    .map(|code| code.to_uppercase());
+    let jobs = value("--jobs", "a number of at least 1", |count| {
+        count.parse::<usize>().is_ok_and(|count| count >= 1)
+    })
+    .and_then(|count| count.parse().ok());
+    let portfolio = value(
+        "--portfolio",
+        "a portfolio name such as work, or all",
+        |name| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        },
+    );
+    if let Some(name) = portfolio {
+        let _ = PORTFOLIO.set(name);
+    }
+    let mut flag = |name: &str| match args.iter().position(|arg| arg == name) {
+        Some(index) => {
+            args.remove(index);
+            true
+        }
+        None => false,
+    };
+    let options = Options {
+        offline: flag("--offline"),
+        refresh: flag("--refresh"),
+        verbose: flag("--verbose"),
+        strict: flag("--strict"),
+        demo: flag("--demo"),
+        currency,
+        jobs,
+    };
+    // Before the default paths are worked out, which then point at the
+    // sample portfolio
+    if options.demo {
+        xbar_stocks::set_demo();
+    }
+    let mut cli = parse_cli(args);
+    // Every portfolio at once gets a subtotal each
+    if PORTFOLIO.get().map(String::as_str) == Some(ALL_PORTFOLIOS) {
+        cli.group_by.get_or_insert(GroupBy::File);
+    }
+    (cli, options)
+}
+
+fn parse_cli(mut args: Vec<String>) -> Cli {
+    if args.first().map(String::as_str) == Some("help") {
+        print_help(args.get(1).map(String::as_str));
+        std::process::exit(0);
+    }
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        print_help(args.first().map(String::as_str));
+        std::process::exit(0);
+    }
+    if args.first().map(String::as_str) == Some("run") {
+        args.remove(0);
+    }
+    if let Some("history" | "export-history") = args.first().map(String::as_str) {
+        let options = parse_export(&args[1..]);
+        return Cli {
+            command: Command::ExportHistory(options),
+            format: Format::Xbar,
+            watch: None,
+            group_by: None,
+            sort: None,
+            csv_paths: get_csv_paths(&[]),
+        };
+    }
+    if args.first().map(String::as_str) == Some("import") {
+        let (broker, path) = match &args[1..] {
+            [broker, path] => (
+                Broker::parse(broker).unwrap_or_else(|| usage_exit(IMPORT_USAGE)),
+                path,
+            ),
+            _ => usage_exit(IMPORT_USAGE),
+        };
+        return Cli {
+            command: Command::Import(broker),
+            format: Format::Xbar,
+            watch: None,
+            group_by: None,
+            sort: None,
+            csv_paths: vec![PathBuf::from(path)],
+        };
+    }
+    if let Some(name @ ("add" | "sell" | "remove" | "list")) = args.first().map(String::as_str) {
+        let (command, path) = match name {
+            "add" => {
+                let (trade, path) = parse_trade(&args[1..]);
+                (Command::Add(trade), path)
+            }
+            "sell" => {
+                let (trade, path) = parse_trade(&args[1..]);
+                (Command::Sell(trade), path)
+            }
+            "remove" => {
+                let dry_run = args.iter().any(|arg| arg == "--dry-run");
+                let positional: Vec<&String> =
+                    args[1..].iter().filter(|arg| *arg != "--dry-run").collect();
+                match positional.as_slice() {
+                    [ticker] => (
+                        Command::Remove {
+                            ticker: ticker.to_uppercase(),
+                            dry_run,
+                        },
+                        None,
+                    ),
+                    [ticker, path] => (
+                        Command::Remove {
+                            ticker: ticker.to_uppercase(),
+                            dry_run,
+                        },
+                        Some(PathBuf::from(path)),
+                    ),
+                    _ => usage_exit(REMOVE_USAGE),
+                }
+            }
+            _ => match &args[1..] {
+                [flag] if flag == "--transactions" => (Command::Transactions, None),
+                [flag, path] if flag == "--transactions" => {
+                    (Command::Transactions, Some(PathBuf::from(path)))
+                }
+                paths
+                    if paths
+                        .iter()
+                        .all(|path| !path.starts_with("--") || path == "-") =>
+                {
+                    return Cli {
+                        command: Command::List,
+                        format: Format::Terminal,
+                        watch: None,
+                        group_by: None,
+                        sort: None,
+                        csv_paths: get_csv_paths(paths),
+                    };
+                }
+                _ => usage_exit(LIST_USAGE),
+            },
+        };
+        // Trades go to the portfolio the menu shows by default
+        let path = match command {
+            Command::Transactions => path.unwrap_or_else(store::default_path),
+            _ => path.unwrap_or_else(|| get_csv_paths(&[]).remove(0)),
+        };
+        return Cli {
+            command,
+            format: Format::Xbar,
+            watch: None,
+            group_by: None,
+            sort: None,
+            csv_paths: vec![path],
+        };
+    }
+    if args.first().map(String::as_str) == Some("prices") {
+        args.remove(0);
+        let mut style = LedgerStyle::Beancount;
+        if let Some(index) = args.iter().position(|arg| arg == "--format") {
+            style = args
+                .get(index + 1)
+                .and_then(|name| LedgerStyle::parse(name))
+                .unwrap_or_else(|| usage_exit(PRICES_USAGE));
+            args.drain(index..index + 2);
+        }
+        return Cli {
+            command: Command::Prices(style),
+            format: Format::Xbar,
+            watch: None,
+            group_by: None,
+            sort: None,
+            csv_paths: get_csv_paths(&args),
+        };
+    }
+    // `report` is the table view whether or not stdout is a terminal
+    let report = args.first().map(String::as_str) == Some("report");
+    if report {
+        args.remove(0);
+    }
+    let mut format = if report {
+        Format::Terminal
+    } else {
+        Format::default_for_stdout()
+    };
+    if let Some(index) = args.iter().position(|arg| arg == "--format") {
+        format = match args.get(index + 1).map(String::as_str) {
+            Some("xbar") => Format::Xbar,
+            Some("json") => Format::Json,
+            Some("terminal") => Format::Terminal,
+            Some("waybar") => Format::Waybar,
+            Some("i3blocks") => Format::I3blocks,
+            Some("polybar") => Format::Polybar,
+            Some("markdown") => Format::Markdown,
+            Some("template") => Format::Template,
+            _ => {
+                eprintln!(
+                    "Usage: xbar-stocks --format xbar|json|terminal|waybar|i3blocks|polybar|markdown|template [path/to/data.csv | -]"
+                );
+                std::process::exit(1);
+            }
+        };
+        args.drain(index..index + 2);
+    }
+    let mut watch = None;
+    if let Some(index) = args.iter().position(|arg| arg == "--watch") {
+        watch = Some(
+            args.get(index + 1)
+                .and_then(|interval| parse_interval(interval))
+                .unwrap_or_else(|| {
+                    usage_exit("Usage: xbar-stocks --watch <60s|5m|1h> [path/to/data.csv]")
+                }),
+        );
+        args.drain(index..index + 2);
+    }
+    let mut group_by = None;
+    if let Some(index) = args.iter().position(|arg| arg == "--group-by") {
+        group_by = Some(
+            args.get(index + 1)
+                .and_then(|name| GroupBy::parse(name))
+                .unwrap_or_else(|| {
+                    usage_exit(
+                        "Usage: xbar-stocks --group-by account|file|tag|none [path/to/data.csv]",
+                    )
+                }),
+        );
+        args.drain(index..index + 2);
+    }
+    let mut sort = None;
+    if let Some(index) = args.iter().position(|arg| arg == "--sort") {
+        sort = Some(
+            args.get(index + 1)
+                .and_then(|text| Sort::parse(text))
+                .unwrap_or_else(|| {
+                    usage_exit("Usage: xbar-stocks --sort ticker|value|profit|change|day[:asc|:desc] [path/to/data.csv]")
+                }),
+        );
+        args.drain(index..index + 2);
+    }
+    let command = match args.first().map(String::as_str) {
+        Some("snapshot") => {
+            args.remove(0);
+            Command::Snapshot
+        }
+        Some("daemon") => {
+            args.remove(0);
+            Command::Daemon
+        }
+        Some("quote") => {
+            let json = format == Format::Json || args.iter().any(|arg| arg == "--json");
+            let ticker = match args[1..]
+                .iter()
+                .filter(|arg| *arg != "--json")
+                .collect::<Vec<_>>()
+                .as_slice()
+            {
+                [ticker] if !ticker.starts_with("--") => ticker.to_string(),
+                _ => usage_exit(QUOTE_USAGE),
+            };
+            return Cli {
+                command: Command::Quote {
+                    ticker: ticker.to_uppercase(),
+                    json,
+                },
+                format,
+                watch,
+                group_by,
+                sort,
+                csv_paths: Vec::new(),
+            };
+        }
+        Some("search") => {
+            if args.len() < 2 || args[1].starts_with("--") {
+                usage_exit(SEARCH_USAGE);
+            }
+            return Cli {
+                command: Command::Search(args[1..].join(" ")),
+                format,
+                watch,
+                group_by,
+                sort,
+                csv_paths: Vec::new(),
+            };
+        }
+        Some("init") => {
+            args.remove(0);
+            if args.len() > 1 || args.iter().any(|arg| arg.starts_with("--")) {
+                usage_exit(INIT_USAGE);
+            }
+            Command::Init
+        }
+        Some("validate") => {
+            args.remove(0);
+            if args.iter().any(|arg| arg.starts_with("--")) {
+                usage_exit(VALIDATE_USAGE);
+            }
+            Command::Validate
+        }
+        Some("privacy") => {
+            let setting = match args.get(1).map(String::as_str) {
+                None | Some("toggle") => None,
+                Some("on") => Some(true),
+                Some("off") => Some(false),
+                Some(_) => usage_exit(PRIVACY_USAGE),
+            };
+            return Cli {
+                command: Command::Privacy(setting),
+                format,
+                watch,
+                group_by,
+                sort,
+                csv_paths: Vec::new(),
+            };
+        }
+        Some("auth") => match (args.get(1).map(String::as_str), args.get(2)) {
+            (Some("set"), Some(provider)) => Command::AuthSet(provider.clone()),
+            (Some("delete"), Some(provider)) => Command::AuthDelete(provider.clone()),
+            _ => usage_exit(AUTH_USAGE),
+        },
+        _ => Command::Show,
+    };
+    Cli {
+        command,
+        format,
+        watch,
+        group_by,
+        sort,
+        csv_paths: get_csv_paths(&args),
+    }
+}
+
+/// Handles `privacy [on|off|toggle]`, remembering the choice in the state
+/// file so it outlives the config setting
+fn set_privacy(
+    setting: Option<bool>,
+    config: &Config,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let mut state = State::default_location();
+    let enabled = setting.unwrap_or(!state.privacy.unwrap_or(config.privacy));
+    state.privacy = Some(enabled);
+    state.save()?;
+    eprintln!("Privacy mode {}", if enabled { "on" } else { "off" });
+    Ok(())
+}
+
+/// The dropdown item that flips privacy mode and reruns the plugin; `None`
+/// when the binary's path is unknown
+fn privacy_toggle(privacy: bool) -> Option<Line> {
+    let program = env::current_exe().ok()?;
+    let mut line = Line::new(
+        if privacy {
+            "Show amounts"
+        } else {
+            "Hide amounts"
+        },
+        "white",
+    );
+    line.command = vec![
+        program.to_string_lossy().into_owned(),
+        "privacy".to_string(),
+        "toggle".to_string(),
+    ];
+    line.refresh = true;
+    Some(line)
+}
+
+/// Arguments that rerun the plugin with `--refresh` and every other option
+/// of this run on the same portfolio; empty when it was piped in or the
+/// binary's path is unknown
+fn refresh_command(cli: &Cli, options: &Options) -> Vec<String> {
+    let Ok(program) = env::current_exe() else {
+        return Vec::new();
+    };
+    let csv_paths = &cli.csv_paths;
+    if csv_paths.iter().any(|path| path == Path::new(STDIN_PATH)) {
+        return Vec::new();
+    }
+    let mut command = vec![
+        program.to_string_lossy().into_owned(),
+        "--refresh".to_string(),
+        "--format".to_string(),
+        "xbar".to_string(),
+    ];
+    // The same report as this run, only with fresh quotes
+    let values = [
+        ("--portfolio", PORTFOLIO.get().cloned()),
+        ("--currency", options.currency.clone()),
+        ("--jobs", options.jobs.map(|jobs| jobs.to_string())),
+        (
+            "--group-by",
+            cli.group_by.map(|group_by| group_by.name().to_string()),
+        ),
+        ("--sort", cli.sort.map(|sort| sort.to_string())),
+    ];
+    for (name, value) in values {
+        if let Some(value) = value {
+            command.extend([name.to_string(), value]);
+        }
+    }
+    for (name, set) in [
+        ("--demo", options.demo),
+        ("--strict", options.strict),
+        ("--verbose", options.verbose),
+    ] {
+        if set {
+            command.push(name.to_string());
+        }
+    }
+    // xbar runs the command from its own working directory
+    command.extend(csv_paths.iter().map(|path| {
+        std::path::absolute(path)
+            .unwrap_or_else(|_| path.clone())
+            .to_string_lossy()
+            .into_owned()
+    }));
+    command
+}
+
+/// Handles `auth set|delete <provider>`; the key is read from stdin so it
+/// never appears in shell history or another process's arguments
+fn run_auth(
+    command: &Command,
+    secrets: &dyn SecretStore,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    match command {
+        Command::AuthSet(provider) => {
+            eprint!("API key for {}: ", provider);
+            let mut key = String::new();
+            io::stdin().read_line(&mut key)?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err("No key entered".into());
+            }
+            secrets.set(&secrets::api_key_name(provider), key)?;
+            eprintln!("Stored {} API key", provider);
+        }
+        Command::AuthDelete(provider) => {
+            secrets.delete(&secrets::api_key_name(provider))?;
+            eprintln!("Removed {} API key", provider);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Path argument that reads the portfolio from standard input
+const STDIN_PATH: &str = "-";
+
+fn get_csv_paths(args: &[String]) -> Vec<PathBuf> {
+    // Check command line arguments
+    if !args.is_empty() {
+        return args.iter().map(PathBuf::from).collect();
+    }
+
+    // Then a portfolio named with --portfolio, or all of them
+    if let Some(name) = PORTFOLIO.get() {
+        return named_portfolios(name);
+    }
+
+    // Then STOCKS_CSV, which may list several files like PATH
+    if let Some(paths) = env::var_os("STOCKS_CSV").filter(|paths| !paths.is_empty()) {
+        return env::split_paths(&paths).collect();
+    }
+
+    // Default to ~/.stocks/data.csv, or the SQLite store when only it exists
+    let csv_path = data_dir().join("data.csv");
+    if !csv_path.exists() && store::default_path().exists() {
+        return vec![store::default_path()];
+    }
+    vec![csv_path]
+}
+
+/// Portfolio chosen with `--portfolio`
+static PORTFOLIO: OnceLock<String> = OnceLock::new();
+
+/// `--portfolio` name that renders every portfolio
+const ALL_PORTFOLIOS: &str = "all";
+
+/// `~/.stocks/<name>.csv`, or every CSV file in `~/.stocks` for `all`
+fn named_portfolios(name: &str) -> Vec<PathBuf> {
+    if name != ALL_PORTFOLIOS {
+        return vec![data_dir().join(format!("{}.csv", name))];
+    }
+    let mut files: Vec<PathBuf> = std::fs::read_dir(data_dir())
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|file| file.is_file() && file.extension().is_some_and(|ext| ext == "csv"))
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        files.push(data_dir().join("data.csv"));
+    }
+    files
+}
+
+/// The portfolio files named on the command line, with directories replaced
+/// by the CSV, TOML and YAML files they contain
+fn portfolio_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let mut listed: Vec<PathBuf> = std::fs::read_dir(path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|file| {
+                file.extension().is_some_and(|ext| {
+                    ext == "csv"
+                        || portfolio::Format::from_extension(&ext.to_string_lossy()).is_some()
+                })
+            })
+            .collect();
+        listed.sort();
+        files.extend(listed);
+    }
+    files
+}
+
+/// Loads and merges every portfolio file, labelling lots with their file
+/// when there is more than one; skipped rows are returned with their file
+fn load_positions(
+    paths: &[PathBuf],
+    config: &Config,
+    secrets: &dyn SecretStore,
+) -> Result<(Vec<Position>, Vec<String>), String> {
+    let files = portfolio_files(paths);
+    let mut positions = Vec::new();
+    let mut skipped = Vec::new();
+    for file in &files {
+        let file_str = file.to_str().unwrap_or("data.csv");
+        let (mut lots, problems) = load_positions_from_csv(file_str, config, secrets)
+            .map_err(|e| format!("Error loading positions from {}:\n  {}", file_str, e))?;
+        let name = file.file_name().map(|name| name.to_string_lossy());
+        for problem in problems {
+            skipped.push(format!(
+                "{} {}",
+                name.as_deref().unwrap_or(file_str),
+                problem
+            ));
+        }
+        if files.len() > 1 {
+            let name = file
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned());
+            for lot in &mut lots {
+                lot.source = name.clone();
+            }
+        }
+        positions.extend(lots);
+    }
+    Ok((positions, skipped))
+}
+
+/// Number formatting, set once at startup from `display.locale`
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+fn locale() -> Locale {
+    LOCALE.get().copied().unwrap_or(Locale::DEFAULT)
+}
+
+/// Whole units of an amount with its currency symbol, without the sign:
+/// "$1 234" or "1.234 €"
+fn format_money(symbol: &str, amount: f64) -> String {
+    locale().money(symbol, amount.abs().trunc(), 0)
+}
+
+/// A price with two decimals and its currency symbol
+fn format_price(symbol: &str, price: f64) -> String {
+    locale().money(symbol, price, 2)
+}
+
+/// A share of the portfolio with one decimal, "12.5%"
+fn format_weight(percent: f64) -> String {
+    format!("{}%", locale().number(percent, 1))
+}
+
+/// A percentage with two decimals and its sign, "+5.67%"
+fn format_percent(percent: f64) -> String {
+    let text = locale().number(percent, 2);
+    if percent >= 0.0 {
+        format!("+{}%", text)
+    } else {
+        format!("{}%", text)
+    }
+}
+
+fn consolidate_positions(positions: Vec<Position>) -> Vec<Position> {
+    type Key = (
+        String,
+        Option<u64>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+        Option<String>,
+    );
+    let mut consolidated: HashMap<Key, (f64, f64, Option<String>, Option<String>)> = HashMap::new();
+
+    // Accumulate total cost and total shares per ticker
+    // Cash rows only merge when they accrue at the same rate from the same date,
+    // and lots priced in different currencies, held in different accounts or
+    // listed in different files never merge
+    for position in positions {
+        let date = if position.apy.is_some() {
+            position.date
+        } else {
+            None
+        };
+        let key = (
+            position.ticker,
+            position.apy.map(f64::to_bits),
+            date,
+            position.currency,
+            position.account,
+            position.source,
+        );
+        let entry = consolidated.entry(key).or_insert((0.0, 0.0, None, None));
+        entry.0 += position.buy_price * position.shares; // total cost
+        entry.1 += position.shares; // total shares
+        if entry.2.is_none() {
+            entry.2 = position.benchmark; // first declared benchmark wins
+        }
+        if entry.3.is_none() {
+            entry.3 = position.tags;
+        }
+    }
+
+    // Calculate weighted average buy price for each ticker
+    consolidated
+        .into_iter()
+        .map(
+            |(
+                (ticker, apy, date, currency, account, source),
+                (total_cost, total_shares, benchmark, tags),
+            )| {
+                Position {
+                    ticker,
+                    // Watch-only rows have no shares to average over
+                    buy_price: if total_shares > 0.0 {
+                        total_cost / total_shares
+                    } else {
+                        0.0
+                    },
+                    shares: total_shares,
+                    apy: apy.map(f64::from_bits),
+                    date,
+                    benchmark,
+                    sell_price: None,
+                    sell_date: None,
+                    fee: None,
+                    currency,
+                    account,
+                    tags,
+                    source,
+                }
+            },
+        )
+        .collect()
+}
+
+/// Values a cash position locally, including accrued interest when an APY is set
+fn cash_price(position: &Position, today: Date) -> FetchResult {
+    let Some(apy) = position.apy else {
+        return Ok(position.buy_price);
+    };
+    let since = match &position.date {
+        Some(date) => Date::parse(date).ok_or_else(|| format!("Invalid date '{}'", date))?,
+        None => return Err("Cash with APY requires a date column".into()),
+    };
+    Ok(cash::accrued_price(position.buy_price, apy, since, today))
+}
+
+type Closes = HashMap<String, Vec<(Date, f64)>>;
+
+/// First date of history needed for every period, the 52-week range and
+/// (when enabled) the 200-day moving average, plus a weekend/holiday margin
+fn history_start(today: Date, config: &Config) -> Date {
+    // 200 sessions plus a week of lookback for crosses is roughly 300 calendar days
+    let indicator_days = if config.moving_averages { 300 } else { 0 };
+    Period::ALL
+        .iter()
+        .map(|period| period.start(today))
+        .chain([today.add_days(-365), today.add_days(-indicator_days)])
+        .min()
+        .unwrap_or(today)
+        .add_days(-10)
+}
+
+/// Loads daily closes for the given tickers from the on-disk history cache,
+/// downloading at most `budget` missing or stale series per run (never when
+/// offline) so a large backfill spreads over several refreshes instead of
+/// blocking one. Tickers with no history yet are left out.
+fn fetch_closes(
+    pool: &rayon::ThreadPool,
+    tickers: &[String],
+    from: Date,
+    offline: bool,
+    budget: usize,
+) -> Closes {
+    let store = HistoryStore::default_location();
+    let max_age = Duration::from_secs(6 * 3600);
+
+    // Missing series first, then the stalest ones
+    let mut stale: Vec<(&String, Option<Duration>)> = tickers
+        .iter()
+        .filter(|ticker| store.needs_refresh(ticker, from, max_age))
+        .map(|ticker| (ticker, store.closes_age(ticker)))
+        .collect();
+    stale.sort_by_key(|(_, age)| std::cmp::Reverse(age.unwrap_or(Duration::MAX)));
+    let budget = if offline { 0 } else { budget };
+    let refresh: Vec<&String> = stale
+        .iter()
+        .take(budget)
+        .map(|(ticker, _)| *ticker)
+        .collect();
+
+    pool.install(|| {
+        tickers
+            .par_iter()
+            .filter_map(|ticker| {
+                let closes = if refresh.contains(&ticker) {
+                    let started = Instant::now();
+                    let closes = store.closes(ticker, from, max_age);
+                    match &closes {
+                        Ok(closes) => info!(
+                            "{}: {} closes in {} ms",
+                            ticker,
+                            closes.len(),
+                            started.elapsed().as_millis()
+                        ),
+                        Err(e) => warn!("{}: history fetch failed: {}", ticker, e),
+                    }
+                    closes.ok()
+                } else {
+                    debug!("{}: closes from the history cache", ticker);
+                    store.load_closes(ticker).ok().map(|closes| {
+                        closes
+                            .into_iter()
+                            .filter(|(date, _)| *date >= from)
+                            .collect()
+                    })
+                };
+                closes
+                    .filter(|closes: &Vec<(Date, f64)>| !closes.is_empty())
+                    .map(|closes| (ticker.clone(), closes))
+            })
+            .collect()
+    })
+}
+
+/// Pairs priced positions with their closes, or `None` if any stock lacks history
+fn holdings<'a>(
+    results: &[(Position, FetchResult)],
+    closes: &'a Closes,
+) -> Option<Vec<Holding<'a>>> {
+    // Only positions with a current price take part; cash has no history
+    let mut holdings = Vec::new();
+    for (position, result) in results {
+        let Ok(price) = result else { continue };
+        let closes = match closes.get(&position.ticker) {
+            Some(closes) => Some(closes.as_slice()),
+            None if cash::is_cash_ticker(&position.ticker) => None,
+            // Without history this position would skew every period
+            None => return None,
+        };
+        holdings.push(Holding {
+            shares: position.shares,
+            current_price: *price,
+            closes,
+        });
+    }
+    Some(holdings)
+}
+
+/// Builds the "1W: +1.2%, 1M: -0.4%, YTD: +11.0%" line from daily closes
+fn performance_line(
+    results: &[(Position, FetchResult)],
+    closes: &Closes,
+    today: Date,
+) -> Option<String> {
+    let holdings = holdings(results, closes)?;
+    let parts: Vec<String> = Period::ALL
+        .iter()
+        .filter_map(|period| {
+            performance::period_return(&holdings, period.start(today))
+                .map(|change| format!("{}: {:+.1}%", period.label(), change))
+        })
+        .collect();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(", "))
+    }
+}
+
+/// Formats an amount and percentage as "+$1 234 (+5.67%)"
+fn format_change(symbol: &str, amount: f64, percent: f64) -> String {
+    format!(
+        "{}{} ({})",
+        if amount >= 0.0 { "+" } else { "-" },
+        format_money(symbol, amount),
+        format_percent(percent)
+    )
+}
+
+/// The menu-bar line for the scheduled header mode, falling back to total
+/// P/L when the history needed for the mode is unavailable; `total` is the
+/// total P/L amount and percentage, `value` the portfolio's market value;
+/// amounts are written with the base currency's `symbol`, or left out in
+/// privacy mode when it is `None`
+fn header_line(
+    mode: HeaderMode,
+    results: &[(Position, FetchResult)],
+    closes: &Closes,
+    today: Date,
+    total: (f64, f64),
+    value: f64,
+    symbol: Option<&str>,
+) -> String {
+    // Privacy mode leaves only the percentage
+    let change = |amount: f64, percent: f64| match symbol {
+        Some(symbol) => format_change(symbol, amount, percent),
+        None => format_percent(percent),
+    };
+    let scheduled = match mode {
+        HeaderMode::Total => None,
+        HeaderMode::Today => holdings(results, closes)
+            .and_then(|holdings| performance::period_change(&holdings, today.add_days(-1)))
+            .map(|(amount, percent)| format!("{} today", change(amount, percent))),
+        HeaderMode::Week => holdings(results, closes)
+            .and_then(|holdings| performance::period_change(&holdings, Period::Week.start(today)))
+            .map(|(amount, percent)| format!("{} 1W", change(amount, percent))),
+        HeaderMode::Value => Some(match symbol {
+            Some(symbol) => format_money(symbol, value),
+            None => format_percent(total.1),
+        }),
+        HeaderMode::Percent => Some(format_percent(total.1)),
+    };
+    scheduled.unwrap_or_else(|| change(total.0, total.1))
+}
+
+/// Builds the "vs ^SPX: +3.1pp YTD" summary line comparing the portfolio
+/// against the configured benchmark index
+fn benchmark_line(
+    results: &[(Position, FetchResult)],
+    closes: &Closes,
+    benchmark: &str,
+    today: Date,
+) -> Option<String> {
+    let start = Period::YearToDate.start(today);
+    let portfolio = performance::period_return(&holdings(results, closes)?, start)?;
+    let index = performance::series_return(closes.get(benchmark)?, start)?;
+    Some(format!("vs {}: {:+.1}pp YTD", benchmark, portfolio - index))
+}
+
+/// Per-ticker "vs WIG-BANKI: +3.1pp YTD" notes, using the position's own
+/// benchmark or else the global one from the config
+fn benchmark_notes(
+    results: &[(Position, FetchResult)],
+    closes: &Closes,
+    default_benchmark: Option<&String>,
+    today: Date,
+) -> HashMap<String, String> {
+    let start = Period::YearToDate.start(today);
+    let mut notes = HashMap::new();
+
+    for (position, result) in results {
+        let (Ok(price), Some(benchmark)) =
+            (result, position.benchmark.as_ref().or(default_benchmark))
+        else {
+            continue;
+        };
+        let (Some(own), Some(reference)) = (closes.get(&position.ticker), closes.get(benchmark))
+        else {
+            continue;
+        };
+        if let Some(diff) = performance::relative_return(*price, own, reference, start) {
+            notes.insert(
+                position.ticker.clone(),
+                format!("vs {}: {:+.1}pp YTD", benchmark, diff),
+            );
+        }
+    }
+
+    notes
+}
+
+/// Days before the one-year mark at which a lot gets a long-term badge
+const LONG_TERM_BADGE_DAYS: i64 = 30;
+
+/// Date a lot bought on `bought` qualifies for long-term capital gains
+/// (held for more than one year)
+fn long_term_date(bought: Date) -> Date {
+    bought.add_months(12).add_days(1)
+}
+
+/// Formats a holding period as "45d", "7mo" or "1y 7mo"
+fn format_holding(days: i64) -> String {
+    if days < 60 {
+        return format!("{}d", days.max(0));
+    }
+    let months = days * 12 / 365;
+    match (months / 12, months % 12) {
+        (0, months) => format!("{}mo", months),
+        (years, 0) => format!("{}y", years),
+        (years, months) => format!("{}y {}mo", years, months),
+    }
+}
+
+/// "LT in 23 days" when a dated lot of the position is about to become
+/// long-term, counting down to the earliest such lot
+fn long_term_badge(lots: &[&Position], today: Date) -> Option<String> {
+    lots.iter()
+        .filter_map(|lot| lot.date.as_deref().and_then(Date::parse))
+        .map(|bought| today.days_until(long_term_date(bought)))
+        .filter(|days| (1..=LONG_TERM_BADGE_DAYS).contains(days))
+        .min()
+        .map(|days| format!("LT in {} day{}", days, if days == 1 { "" } else { "s" }))
+}
+
+/// Submenu lines with the holding period of each dated lot, oldest first;
+/// prices are written with the position's currency `symbol`
+fn holding_lines(lots: &[&Position], today: Date, symbol: &str) -> Vec<Line> {
+    let mut dated: Vec<(Date, &Position)> = lots
+        .iter()
+        .filter_map(|lot| Some((Date::parse(lot.date.as_deref()?)?, *lot)))
+        .collect();
+    dated.sort_by_key(|(bought, _)| *bought);
+    dated
+        .into_iter()
+        .map(|(bought, lot)| {
+            let long_term = if today >= long_term_date(bought) {
+                " · LT"
+            } else {
+                ""
+            };
+            Line::new(
+                format!(
+                    "Lot {}: {} @ {}, held {}{}",
+                    bought,
+                    lot.shares,
+                    format_price(symbol, lot.buy_price),
+                    format_holding(bought.days_until(today)),
+                    long_term
+                ),
+                "white",
+            )
+        })
+        .collect()
+}
+
+/// Formats a duration in seconds as "2h 14m" or "14m"
+fn format_countdown(seconds: i64) -> String {
+    let minutes = (seconds.max(0) + 59) / 60;
+    if minutes >= 60 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// Footer with the local time and status of each exchange held, e.g.
+/// "NYSE 07:16 opens in 2h 14m · GPW 13:16 open, closes in 3h 49m"
+fn session_clock_line(exchanges: &[Exchange], now: i64) -> Option<String> {
+    let mut exchanges = exchanges.to_vec();
+    exchanges.sort_by_key(|exchange| exchange.name());
+    exchanges.dedup();
+
+    let parts: Vec<String> = exchanges
+        .iter()
+        .map(|exchange| {
+            let (_, seconds) = exchange.local_time(now);
+            let clock = format!("{:02}:{:02}", seconds / 3600, seconds % 3600 / 60);
+            let status = if let Some(close) = exchange.current_close(now) {
+                format!("open, closes in {}", format_countdown(close - now))
+            } else {
+                match exchange.next_open(now) {
+                    // Far-off opens (weekends, holidays) read better as just closed
+                    Some(open) if open - now < 86_400 => {
+                        format!("opens in {}", format_countdown(open - now))
+                    }
+                    _ => "closed".to_string(),
+                }
+            };
+            format!("{} {} {}", exchange.name(), clock, status)
+        })
+        .collect();
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" · "))
+    }
+}
+
+/// Loads the portfolio lots, printing the error with usage help on failure
+fn load_portfolio(
+    csv_paths: &[PathBuf],
+    config: &Config,
+    secrets: &dyn SecretStore,
+) -> Option<(Vec<Position>, Vec<String>)> {
+    // Load positions from CSV
+    match load_positions(csv_paths, config, secrets) {
+        Ok(loaded) => Some(loaded),
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: {} [snapshot | prices | daemon] [--format xbar|json|terminal|waybar|i3blocks|polybar|markdown|template] [--group-by account|file|tag|none] [--sort ticker|value|profit|change|day[:asc|:desc]] [path/to/data.csv ... | dir | sheet-url | -]",
+                env::args()
+                    .next()
+                    .unwrap_or_else(|| "xbar-stocks".to_string())
+            );
+            eprintln!("Default location: ~/.stocks/data.csv");
+            if csv_paths.iter().any(|path| {
+                path != Path::new(STDIN_PATH)
+                    && !sheets::is_url(&path.to_string_lossy())
+                    && !path.exists()
+            }) {
+                eprintln!("Run `xbar-stocks init` to create it with a sample portfolio.");
+            }
+            None
+        }
+    }
+}
+
+/// Prints the open positions, consolidated as the menu shows them, with
+/// their cost; reads only the portfolio files, so it works offline
+fn list_holdings(csv_paths: &[PathBuf], config: &Config, secrets: &dyn SecretStore) {
+    let Some((lots, skipped_rows)) = load_portfolio(csv_paths, config, secrets) else {
+        std::process::exit(EXIT_CONFIG);
+    };
+    for row in &skipped_rows {
+        eprintln!("Skipped {}", row);
+    }
+    let open_lots = lots
+        .into_iter()
+        .filter(|position| position.sell_price.is_none())
+        .map(|position| Position {
+            source: None,
+            ..position
+        })
+        .collect();
+    let mut positions = consolidate_positions(open_lots);
+    // Watch-only tickers hold nothing
+    positions.retain(|position| position.shares != 0.0);
+    positions.sort_by(|a, b| (&a.ticker, &a.account).cmp(&(&b.ticker, &b.account)));
+
+    let rows: Vec<[String; 5]> = positions
+        .iter()
+        .map(|position| {
+            let symbol = fx::symbol(&position_currency(position, &config.base_currency));
+            [
+                position.ticker.clone(),
+                locale().number(
+                    position.shares,
+                    if position.shares.fract() == 0.0 { 0 } else { 4 },
+                ),
+                format_price(&symbol, position.buy_price),
+                format_money(&symbol, position.buy_price * position.shares),
+                position.account.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let headers = ["Ticker", "Shares", "Avg cost", "Invested", "Account"];
+    let widths: Vec<usize> = (0..headers.len())
+        .map(|column| {
+            rows.iter()
+                .map(|row| display_width(&row[column]))
+                .chain([headers[column].len()])
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |cells: [&str; 5]| {
+        let text = format!(
+            "{}  {}  {}  {}  {}",
+            pad_right(cells[0], widths[0]),
+            pad_left(cells[1], widths[1]),
+            pad_left(cells[2], widths[2]),
+            pad_left(cells[3], widths[3]),
+            cells[4]
+        );
+        println!("{}", text.trim_end());
+    };
+    line(headers);
+    for row in &rows {
+        line([&row[0], &row[1], &row[2], &row[3], &row[4]]);
+    }
+
+    // Without exchange rates the totals stay in each currency
+    let mut invested: BTreeMap<String, f64> = BTreeMap::new();
+    for position in &positions {
+        *invested
+            .entry(position_currency(position, &config.base_currency))
+            .or_default() += position.buy_price * position.shares;
+    }
+    let totals: Vec<String> = invested
+        .iter()
+        .map(|(currency, amount)| format_money(&fx::symbol(currency), *amount))
+        .collect();
+    println!(
+        "\n{} positions, {} invested",
+        positions.len(),
+        totals.join(" + ")
+    );
+}
+
+/// Dated cash flows of every lot plus today's value, for XIRR
+///
+/// Returns `None` if any lot is missing the dates needed to place it in time.
+fn cash_flows(
+    open_lots: &[Position],
+    sold_lots: &[Position],
+    current_value: f64,
+    today: Date,
+) -> Option<Vec<(Date, f64)>> {
+    let date = |value: &Option<String>| value.as_deref().and_then(Date::parse);
+
+    let mut flows = Vec::new();
+    for lot in open_lots {
+        flows.push((date(&lot.date)?, -lot.buy_price * lot.shares));
+    }
+    for lot in sold_lots {
+        let proceeds = lot.sell_price? * lot.shares;
+        flows.push((date(&lot.date)?, -lot.buy_price * lot.shares));
+        flows.push((date(&lot.sell_date)?, proceeds));
+    }
+    flows.push((today, current_value));
+    Some(flows)
+}
+
+/// Builds the "TWR: +12.3% since 2025-01-02, XIRR: +8.4%/yr" line
+///
+/// TWR chains the recorded daily snapshots (plus today's live valuation),
+/// treating changes in invested capital as deposits. XIRR uses the dated lots.
+fn returns_line(
+    open_lots: &[Position],
+    sold_lots: &[Position],
+    total_investment: f64,
+    total_current_value: f64,
+    today: Date,
+) -> Option<String> {
+    let mut parts = Vec::new();
+
+    let snapshots = HistoryStore::default_location()
+        .load_snapshots()
+        .unwrap_or_default();
+    let mut totals: Vec<_> = snapshots
+        .iter()
+        .filter(|snapshot| snapshot.ticker == TOTAL_TICKER && snapshot.date < today)
+        .map(|snapshot| (snapshot.date, snapshot.cost, snapshot.value))
+        .collect();
+    totals.push((today, total_investment, total_current_value));
+    let points: Vec<_> = totals
+        .iter()
+        .map(|(_, cost, value)| (*cost, *value))
+        .collect();
+    if let Some(twr) = performance::time_weighted_return(&points) {
+        parts.push(format!("TWR: {:+.1}% since {}", twr, totals[0].0));
+    }
+
+    if let Some(rate) = cash_flows(open_lots, sold_lots, total_current_value, today)
+        .and_then(|flows| performance::xirr(&flows))
+    {
+        parts.push(format!("XIRR: {:+.1}%/yr", rate));
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(", "))
+    }
+}
+
+/// A watch-only ticker (zero shares): its price and change since the
+/// previous close
+fn watch_line(
+    position: &Position,
+    result: &FetchResult,
+    closes: &Closes,
+    today: Date,
+    currency: &str,
+    config: &Config,
+) -> Line {
+    let price = match result {
+        Ok(price) => *price,
+        Err(e) => return Line::new(format!("{}: Error - {}", position.ticker, e), "darkred"),
+    };
+    let symbol = fx::symbol(currency);
+    let previous = closes
+        .get(&position.ticker)
+        .and_then(|closes| close_on_or_before(closes, today.add_days(-1)));
+    let mut line = match previous {
+        Some(previous) => {
+            let change = (price - previous) / previous * 100.0;
+            Line::new(
+                format!(
+                    "{} {} {} today",
+                    pad_right(&position.ticker, 10),
+                    format_price(&symbol, price),
+                    format_percent(change)
+                ),
+                config.palette.color(change),
+            )
+        }
+        None => Line::new(
+            format!(
+                "{} {}",
+                pad_right(&position.ticker, 10),
+                format_price(&symbol, price)
+            ),
+            "white",
+        ),
+    };
+    line.href = Some(config.quote_link(&position.ticker));
+    line
+}
+
+/// Invested amount and market value per group of the priced positions
+fn group_subtotals(
+    results: &[(Position, FetchResult)],
+    group_by: GroupBy,
+) -> HashMap<Option<String>, (f64, f64)> {
+    let mut subtotals: HashMap<Option<String>, (f64, f64)> = HashMap::new();
+    // Watch-only tickers are listed apart from the groups
+    for (position, result) in results.iter().filter(|(position, _)| position.shares > 0.0) {
+        for key in group_by.keys(position) {
+            let entry = subtotals.entry(key).or_default();
+            if let Ok(price) = result {
+                entry.0 += position.buy_price * position.shares;
+                entry.1 += price * position.shares;
+            }
+        }
+    }
+    subtotals
+}
+
+/// Orders the rows, keeping failed quotes below the priced holdings and cash
+/// last; amounts are compared in the base currency
+fn sort_rows(
+    mut rows: Vec<(Row, Vec<Option<String>>)>,
+    sort: Sort,
+) -> (Vec<Row>, Vec<Vec<Option<String>>>) {
+    let figure = |row: &Row| match sort.key {
+        SortKey::Ticker => 0.0,
+        SortKey::Value => row.value * row.rate,
+        SortKey::ProfitLoss => row.profit_loss * row.rate,
+        SortKey::Change => row.change_percent,
+        // Positions without a previous close go after those with one
+        SortKey::DayChange => row.day_change.unwrap_or(f64::NAN),
+    };
+    rows.sort_by(|(a, _), (b, _)| {
+        let order = match sort.key {
+            SortKey::Ticker => a.ticker.cmp(&b.ticker),
+            _ => figure(a).total_cmp(&figure(b)),
+        };
+        let order = if sort.descending {
+            order.reverse()
+        } else {
+            order
+        };
+        cash::is_cash_ticker(&a.ticker)
+            .cmp(&cash::is_cash_ticker(&b.ticker))
+            .then(a.price.is_err().cmp(&b.price.is_err()))
+            .then(figure(a).is_nan().cmp(&figure(b).is_nan()))
+            .then(order)
+    });
+    rows.into_iter().unzip()
+}
+
+/// Realized profit or loss of sold lots and what they cost
+fn realized_profit_loss(sold: &[Position]) -> (f64, f64) {
+    sold.iter().fold((0.0, 0.0), |(profit_loss, cost), lot| {
+        let lot_cost = lot.buy_price * lot.shares;
+        let proceeds = lot.sell_price.unwrap_or(lot.buy_price) * lot.shares;
+        (profit_loss + proceeds - lot_cost, cost + lot_cost)
+    })
+}
+
+/// Submenu lines for the archive of sold lots, one per ticker with the realized outcome;
+/// `sold` is in the base currency, written with `symbol`
+fn archive_lines(sold: &[Position], symbol: &str, palette: &Palette, privacy: bool) -> Vec<Line> {
+    // Accumulate cost, proceeds and shares per ticker
+    let mut archived: HashMap<&str, (f64, f64, f64)> = HashMap::new();
+    for position in sold {
+        let entry = archived.entry(&position.ticker).or_insert((0.0, 0.0, 0.0));
+        entry.0 += position.buy_price * position.shares;
+        entry.1 += position.sell_price.unwrap_or(position.buy_price) * position.shares;
+        entry.2 += position.shares;
+    }
+
+    let mut archived: Vec<_> = archived.into_iter().collect();
+    archived.sort_by(|a, b| a.0.cmp(b.0));
+    archived
+        .into_iter()
+        .map(|(ticker, (cost, proceeds, shares))| {
+            let realized = proceeds - cost;
+            let change = realized / cost * 100.0;
+            let outcome = if privacy {
+                format_percent(change)
+            } else {
+                format_change(symbol, realized, change)
+            };
+            Line::new(
+                format!(
+                    "{} {} → {} {}",
+                    pad_right(ticker, 10),
+                    format_price(symbol, cost / shares),
+                    format_price(symbol, proceeds / shares),
+                    outcome
+                ),
+                palette.color(change),
+            )
+        })
+        .collect()
+}
+
+/// Per-position prices, their quote times, and whether the network was down
+type PriceResults = (
+    Vec<(Position, FetchResult)>,
+    HashMap<String, QuoteTime>,
+    bool,
+);
+
+/// When a price was valid and whether it is too old to trust
+#[derive(Debug, Clone, Copy)]
+struct QuoteTime {
+    /// Unix time the price refers to: the fetch, or the session close for
+    /// settled prices
+    as_of: i64,
+    stale: bool,
+}
+
+/// Quotes from the daemon's state file and the time it was written, if that
+/// is within `max_age` seconds and the file covers every position; cash is
+/// still valued locally
+fn live_prices(positions: &[Position], today: Date, max_age: i64) -> Option<(PriceResults, i64)> {
+    let state = LiveState::load(&LiveState::default_path())?;
+    if unix_now() - state.written_at > max_age {
+        return None;
+    }
+    let quotes: HashMap<&str, &LiveQuote> = state
+        .quotes
+        .iter()
+        .map(|quote| (quote.ticker.as_str(), quote))
+        .collect();
+
+    let mut results = Vec::new();
+    let mut quote_times = HashMap::new();
+    for position in positions {
+        if cash::is_cash_ticker(&position.ticker) {
+            results.push((position.clone(), cash_price(position, today)));
+            continue;
+        }
+        let quote = quotes.get(position.ticker.as_str())?;
+        if let Some(as_of) = quote.as_of {
+            let stale = quote.stale;
+            quote_times.insert(position.ticker.clone(), QuoteTime { as_of, stale });
+        }
+        let result: FetchResult = quote.result.clone().map_err(Into::into);
+        results.push((position.clone(), result));
+    }
+    Some(((results, quote_times, state.offline), state.written_at))
+}
+
+/// Set by the SIGINT handler; a second Ctrl-C exits immediately
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_interrupt(_: libc::c_int) {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        // SAFETY: _exit is async-signal-safe
+        unsafe { libc::_exit(130) };
+    }
+}
+
+/// Runs `render` every `interval`, clearing the terminal between rounds,
+/// until Ctrl-C; an interrupted wait exits cleanly after restoring the line.
+/// Saving the portfolio file re-renders right away.
+fn run_watch(interval: Duration, csv_paths: &[PathBuf], render: impl Fn()) {
+    // SAFETY: the handler only touches an atomic and calls _exit
+    unsafe {
+        libc::signal(
+            libc::SIGINT,
+            on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+        );
+    }
+    let clear = io::stdout().is_terminal();
+    let mut watcher = FileWatcher::new(csv_paths);
+
+    while !INTERRUPTED.load(Ordering::SeqCst) {
+        if clear {
+            print!("\x1b[2J\x1b[H");
+        }
+        render();
+        let _ = io::stdout().flush();
+
+        wait_for_change(interval, &mut watcher);
+    }
+    println!();
+}
+
+/// Sleeps for `interval` in short steps, returning early on Ctrl-C or when
+/// the watched file changes
+fn wait_for_change(interval: Duration, watcher: &mut FileWatcher) {
+    let started = std::time::Instant::now();
+    while started.elapsed() < interval && !INTERRUPTED.load(Ordering::SeqCst) {
+        if watcher.changed() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// Keeps `~/.stocks/state.json` fresh for the xbar entry point: refetches
+/// prices and backfills history every `daemon.interval` seconds, or as soon
+/// as the portfolio file is saved, logging failures and retrying on the
+/// next round
+fn run_daemon(csv_paths: &[PathBuf], config: &Config, secrets: &dyn SecretStore) -> ! {
+    let path = LiveState::default_path();
+    let stale_after = Duration::from_secs(config.stale_after_minutes * 60);
+    let mut watcher = FileWatcher::new(csv_paths);
+
+    let metrics_body = Arc::new(Mutex::new(String::new()));
+    let mut fetch_errors = BTreeMap::new();
+    if let Some(addr) = &config.metrics_listen
+        && let Err(e) = metrics::serve(addr, Arc::clone(&metrics_body))
+    {
+        eprintln!("Error serving metrics on {}: {}", addr, e);
+        std::process::exit(1);
+    }
+
+    loop {
+        match load_positions(csv_paths, config, secrets) {
+            Ok((lots, skipped)) => {
+                for row in skipped {
+                    eprintln!("Skipped {}", row);
+                }
+                let open_lots = lots
+                    .into_iter()
+                    .filter(|position| position.sell_price.is_none())
+                    .collect();
+                let positions = consolidate_positions(open_lots);
+                let pool = &fetch_pool(config.fetch_concurrency, positions.len());
+                let today = Date::today();
+                let (results, quote_times, offline) =
+                    // Every round refreshes, whatever the cache TTL
+                    fetch_prices(pool, &positions, today, stale_after, Duration::ZERO, false);
+                let closes = fetch_closes(
+                    pool,
+                    &history_tickers(&results, &positions, config),
+                    history_start(today, config),
+                    offline,
+                    config.history_fetches_per_run,
+                );
+                let currencies: Vec<String> = results
+                    .iter()
+                    .map(|(position, _)| position_currency(position, &config.base_currency))
+                    .collect();
+                let fx = FxRates::load(
+                    &config.base_currency,
+                    currencies.iter().map(String::as_str),
+                    offline,
+                );
+                let (valued, valued_closes) = in_base_currency(&results, &closes, &fx);
+                notify_eod(&valued, &valued_closes, config, today);
+                if let Some(broker) = &config.mqtt_broker
+                    && let Err(e) = publish_mqtt(broker, &valued, config, secrets)
+                {
+                    eprintln!("Error publishing to MQTT broker {}: {}", broker, e);
+                }
+                if config.metrics_listen.is_some() {
+                    let metrics = daemon_metrics(&results, &valued, &mut fetch_errors);
+                    if let Ok(mut body) = metrics_body.lock() {
+                        *body = metrics.render();
+                    }
+                }
+
+                let quotes = results
+                    .iter()
+                    .filter(|(position, _)| !cash::is_cash_ticker(&position.ticker))
+                    .map(|(position, result)| {
+                        let time = quote_times.get(&position.ticker);
+                        LiveQuote {
+                            ticker: position.ticker.clone(),
+                            result: result.as_ref().map(|price| *price).map_err(|e| FetchError {
+                                kind: classify_error(e.as_ref()),
+                                message: e.to_string(),
+                            }),
+                            as_of: time.map(|time| time.as_of),
+                            stale: time.is_some_and(|time| time.stale),
+                        }
+                    })
+                    .collect();
+                let state = LiveState {
+                    written_at: unix_now(),
+                    offline,
+                    quotes,
+                };
+                if let Err(e) = state.save(&path) {
+                    eprintln!("Error writing {}: {}", path.display(), e);
+                }
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+        wait_for_change(
+            Duration::from_secs(config.daemon_interval_secs),
+            &mut watcher,
+        );
+    }
+}
+
+/// "Today: +$1 234 (+0.85%) · PKN +3.2%, AAPL.US -2.1%" with the three
+/// biggest movers against the previous close
+fn eod_summary(
+    results: &[(Position, FetchResult)],
+    closes: &Closes,
+    today: Date,
+    symbol: &str,
+) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some((amount, percent)) = holdings(results, closes)
+        .and_then(|holdings| performance::period_change(&holdings, today.add_days(-1)))
+    {
+        parts.push(format!("Today: {}", format_change(symbol, amount, percent)));
+    }
+
+    let mut movers: Vec<(&str, f64)> = results
+        .iter()
+        .filter(|(position, _)| !cash::is_cash_ticker(&position.ticker))
+        .filter_map(|(position, result)| {
+            let price = result.as_ref().ok()?;
+            let previous = close_on_or_before(closes.get(&position.ticker)?, today.add_days(-1))?;
+            Some((position.ticker.as_str(), (price / previous - 1.0) * 100.0))
+        })
+        .collect();
+    movers.sort_by(|a, b| b.1.abs().total_cmp(&a.1.abs()));
+    let movers: Vec<String> = movers
+        .iter()
+        .take(3)
+        .map(|(ticker, change)| format!("{} {:+.1}%", ticker, change))
+        .collect();
+    if !movers.is_empty() {
+        parts.push(movers.join(", "));
+    }
+
+    if parts.is_empty() {
+        None
+    } else {
+        Some(parts.join(" · "))
+    }
+}
+
+/// Sends the end-of-day summary once every held exchange has closed, at
+/// most once per close and only within two hours of it so a daemon started
+/// the next morning stays quiet
+fn notify_eod(results: &[(Position, FetchResult)], closes: &Closes, config: &Config, today: Date) {
+    if !config.eod_notify {
+        return;
+    }
+    let now = unix_now();
+    let exchanges: Vec<Exchange> = results
+        .iter()
+        .filter(|(position, _)| !cash::is_cash_ticker(&position.ticker))
+        .filter_map(|(position, _)| Exchange::for_ticker(&position.ticker))
+        .collect();
+    if exchanges.iter().any(|exchange| exchange.is_open(now)) {
+        return;
+    }
+    let Some(close) = exchanges
+        .iter()
+        .map(|exchange| exchange.last_close(now))
+        .max()
+    else {
+        return;
+    };
+    let mut state = State::default_location();
+    if now - close > 2 * 3600 || state.eod_notified_at.is_some_and(|at| at >= close) {
+        return;
+    }
+    let Some(message) = eod_summary(results, closes, today, &fx::symbol(&config.base_currency))
+    else {
+        return;
+    };
+
+    let sent = match &config.notify_webhook {
+        Some(url) => notify::webhook(url, &format!("Portfolio close: {}", message)),
+        None => notify::desktop("Portfolio close", &message),
+    };
+    match sent {
+        Ok(()) => {
+            state.eod_notified_at = Some(close);
+            if let Err(e) = state.save() {
+                eprintln!("Error saving state: {}", e);
+            }
+        }
+        Err(e) => eprintln!("Error sending end-of-day summary: {}", e),
+    }
+}
+
+/// Publishes portfolio totals and per-position prices, each announced to
+/// Home Assistant through a retained discovery message; `results` must be
+/// in the base currency, the sensors' unit
+fn publish_mqtt(
+    broker: &str,
+    results: &[(Position, FetchResult)],
+    config: &Config,
+    secrets: &dyn SecretStore,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let password = config
+        .mqtt_username
+        .as_ref()
+        .and_then(|_| secrets::api_key(secrets, "mqtt"));
+    let mut client = MqttClient::connect(
+        broker,
+        "xbar-stocks",
+        config.mqtt_username.as_deref(),
+        password.as_deref(),
+    )?;
+
+    let mut cost = 0.0;
+    let mut value = 0.0;
+    let mut sensors = Vec::new();
+    for (position, result) in results {
+        if let Ok(price) = result {
+            cost += position.buy_price * position.shares;
+            value += price * position.shares;
+            let id = mqtt::object_id(&position.ticker);
+            sensors.push((id, format!("{} price", position.ticker), *price));
+        }
+    }
+    sensors.push((
+        "portfolio_value".to_string(),
+        "Portfolio value".to_string(),
+        value,
+    ));
+    sensors.push((
+        "portfolio_profit_loss".to_string(),
+        "Portfolio P/L".to_string(),
+        value - cost,
+    ));
+
+    for (id, name, state) in sensors {
+        let state_topic = format!("{}/{}/state", config.mqtt_topic, id);
+        let discovery = Json::object([
+            ("name", Json::from(name)),
+            ("unique_id", Json::from(format!("xbar_stocks_{}", id))),
+            ("state_topic", Json::from(state_topic.as_str())),
+            (
+                "unit_of_measurement",
+                Json::from(config.base_currency.as_str()),
+            ),
+            (
+                "device",
+                Json::object([
+                    ("identifiers", Json::Array(vec![Json::from("xbar_stocks")])),
+                    ("name", Json::from("Stocks portfolio")),
+                ]),
+            ),
+        ]);
+        client.publish(
+            &format!(
+                "{}/sensor/xbar_stocks_{}/config",
+                config.mqtt_discovery_prefix, id
+            ),
+            &discovery.to_string(),
+            true,
+        )?;
+        client.publish(&state_topic, &format!("{:.2}", state), true)?;
+    }
+    client.disconnect()
+}
+
+/// Metrics for one daemon round; fetch errors accumulate across rounds
+///
+/// Amounts come from `valued`, the results in the base currency, leaving
+/// out positions without an exchange rate; errors come from `results`.
+fn daemon_metrics(
+    results: &[(Position, FetchResult)],
+    valued: &[(Position, FetchResult)],
+    fetch_errors: &mut BTreeMap<(String, String), u64>,
+) -> Metrics {
+    let mut metrics = Metrics::default();
+    for ((position, result), (valued_position, valued_result)) in results.iter().zip(valued) {
+        match (result, valued_result) {
+            (Ok(_), Ok(price)) => {
+                let cost = valued_position.buy_price * position.shares;
+                let value = price * position.shares;
+                metrics.portfolio_cost += cost;
+                metrics.portfolio_value += value;
+                metrics.positions.push(PositionMetric {
+                    ticker: position.ticker.clone(),
+                    price: *price,
+                    value,
+                    profit_loss: value - cost,
+                });
+            }
+            (Ok(_), Err(_)) => {}
+            (Err(e), _) => {
+                let kind = classify_error(e.as_ref()).name().to_string();
+                *fetch_errors
+                    .entry((position.ticker.clone(), kind))
+                    .or_insert(0) += 1;
+            }
+        }
+    }
+    metrics.fetch_errors = fetch_errors.clone();
+    metrics
+}
+
+/// Tickers whose daily closes are kept: priced positions, their
+/// benchmarks and the global benchmark
+fn history_tickers(
+    results: &[(Position, FetchResult)],
+    positions: &[Position],
+    config: &Config,
+) -> Vec<String> {
+    let mut tickers: Vec<String> = results
+        .iter()
+        .filter(|(position, result)| result.is_ok() && !cash::is_cash_ticker(&position.ticker))
+        .map(|(position, _)| position.ticker.clone())
+        .chain(
+            positions
+                .iter()
+                .filter_map(|position| position.benchmark.clone()),
+        )
+        .chain(config.benchmark.clone())
+        .collect();
+    tickers.sort();
+    tickers.dedup();
+    tickers
+}
+
+/// Fetches all prices in parallel using rayon with limited concurrency
+///
+/// Outside trading hours prices are served from the quote cache once a
+/// quote newer than the last session close has been stored. When the first
+/// few fetches all fail the same way the network is treated as down: the
+/// remaining fetches are skipped and cached prices are used instead, which
+/// the returned flag reports. Cached fallbacks older than `stale_after` are
+/// marked stale in the returned quote times.
+fn fetch_prices(
+    pool: &rayon::ThreadPool,
+    positions: &[Position],
+    today: Date,
+    stale_after: Duration,
+    cache_ttl: Duration,
+    refresh: bool,
+) -> (
+    Vec<(Position, FetchResult)>,
+    HashMap<String, QuoteTime>,
+    bool,
+) {
+    let mut cache = QuoteCache::default_location();
+    let now = unix_now();
+    let network_fetches = positions
+        .iter()
+        .filter(|position| !cash::is_cash_ticker(&position.ticker))
+        .count();
+    let detector = OfflineDetector::new(network_fetches.min(3));
+    let forced_offline = xbar_stocks::is_offline();
+
+    let results: Vec<_> = pool.install(|| {
+        positions
+            .par_iter()
+            .map(|position| {
+                // Cash is valued locally, everything else is scraped unless settled
+                if cash::is_cash_ticker(&position.ticker) {
+                    (position.clone(), cash_price(position, today), false, None)
+                } else if let Some(price) = cache
+                    .settled_price(&position.ticker, now)
+                    .filter(|_| !refresh)
+                {
+                    debug!(
+                        "{}: cache hit, market closed since fetched",
+                        position.ticker
+                    );
+                    let closed_at = Exchange::for_ticker(&position.ticker)
+                        .map(|exchange| exchange.last_close(now));
+                    (position.clone(), Ok(price), false, closed_at)
+                } else if let Some(quote) = cache
+                    .get(&position.ticker)
+                    .filter(|quote| !refresh && now - quote.fetched_at < cache_ttl.as_secs() as i64)
+                {
+                    debug!(
+                        "{}: cache hit, {}s old",
+                        position.ticker,
+                        now - quote.fetched_at
+                    );
+                    (
+                        position.clone(),
+                        Ok(quote.price),
+                        false,
+                        Some(quote.fetched_at),
+                    )
+                } else if forced_offline || detector.is_offline() {
+                    debug!("{}: not fetched, offline", position.ticker);
+                    (position.clone(), Err("Offline".into()), false, None)
+                } else {
+                    let started = Instant::now();
+                    let result = fetch_latest_price(&position.ticker);
+                    let elapsed = started.elapsed().as_millis();
+                    match &result {
+                        Ok(price) => {
+                            info!("{}: {} in {} ms", position.ticker, price, elapsed);
+                            detector.record_success();
+                        }
+                        Err(e) => {
+                            let kind = classify_error(e.as_ref());
+                            warn!(
+                                "{}: {} error after {} ms: {}",
+                                position.ticker,
+                                kind.name(),
+                                elapsed,
+                                e
+                            );
+                            detector.record_failure(kind);
+                        }
+                    }
+                    (position.clone(), result, true, Some(now))
+                }
+            })
+            .collect()
+    });
 
-#[derive(Debug, Clone, Deserialize)]
-struct Position {
-    ticker: String,
-    buy_price: f64,
-    shares: f64,
-}
+    let mut fetched_any = false;
+    for (position, result, fetched, _) in &results {
+        if let (true, Ok(price)) = (fetched, result) {
+            cache.insert(&position.ticker, *price, now);
+            fetched_any = true;
+        }
+    }
+    if fetched_any && let Err(e) = cache.save() {
+        eprintln!("Error saving quote cache: {}", e);
+    }
 
-fn load_positions_from_csv(file_path: &str) -> Result<Vec<Position>, Box<dyn std::error::Error>> {
-    let file = File::open(file_path)?;
-    let mut reader = csv::Reader::from_reader(file);
-    let mut positions = Vec::new();
+    let offline = forced_offline || detector.is_offline();
+    let mut quote_times = HashMap::new();
+    let results = results
+        .into_iter()
+        .map(|(position, result, _, as_of)| match result {
+            // Fall back to the last known price, however old
+            Err(_) if offline && cache.get(&position.ticker).is_some() => {
+                let quote = cache.get(&position.ticker);
+                if let Some(quote) = quote {
+                    let age = (now - quote.fetched_at).max(0) as u64;
+                    debug!("{}: cached price from {}s ago", position.ticker, age);
+                    quote_times.insert(
+                        position.ticker.clone(),
+                        QuoteTime {
+                            as_of: quote.fetched_at,
+                            stale: age > stale_after.as_secs(),
+                        },
+                    );
+                }
+                let price = quote.map(|quote| quote.price);
+                (position, price.ok_or_else(|| "Offline".into()))
+            }
+            result => {
+                if let (Ok(_), Some(as_of)) = (&result, as_of) {
+                    quote_times.insert(
+                        position.ticker.clone(),
+                        QuoteTime {
+                            as_of,
+                            stale: false,
+                        },
+                    );
+                }
+                (position, result)
+            }
+        })
+        .collect();
+    (results, quote_times, offline)
+}
 
-    for result in reader.deserialize() {
-        let position: Position = result?;
-        positions.push(position);
+/// Fetches each ticker of the portfolio once, bypassing the cache, and
+/// reports those the provider does not know with symbols that do resolve;
+/// false if any is unknown
+fn validate(csv_paths: &[PathBuf], config: &Config, secrets: &dyn SecretStore) -> bool {
+    let Some((lots, skipped_rows)) = load_portfolio(csv_paths, config, secrets) else {
+        std::process::exit(EXIT_CONFIG);
+    };
+    for row in &skipped_rows {
+        eprintln!("Skipped {}", row);
     }
+    let mut tickers: Vec<String> = lots
+        .into_iter()
+        .map(|position| position.ticker)
+        .filter(|ticker| !cash::is_cash_ticker(ticker))
+        .collect();
+    tickers.sort();
+    tickers.dedup();
 
-    Ok(positions)
+    let pool = fetch_pool(config.fetch_concurrency, tickers.len());
+    let results: Vec<_> = pool.install(|| {
+        tickers
+            .par_iter()
+            .map(|ticker| (ticker, fetch_latest_price(ticker)))
+            .collect()
+    });
+    let mut unknown = 0;
+    for (ticker, result) in results {
+        let error = match result {
+            Ok(price) => {
+                println!("{}  ok ({})", pad_right(ticker, 10), price);
+                continue;
+            }
+            Err(e) => e,
+        };
+        if classify_error(error.as_ref()) != FetchErrorKind::NotFound {
+            println!("{}  not checked: {}", pad_right(ticker, 10), error);
+            continue;
+        }
+        unknown += 1;
+        let candidates = symbols::suggestions(ticker);
+        match candidates
+            .iter()
+            .find(|candidate| fetch_latest_price(candidate).is_ok())
+        {
+            Some(symbol) => println!(
+                "{}  unknown to {}; try {}",
+                pad_right(ticker, 10),
+                config.provider,
+                symbol
+            ),
+            None if candidates.is_empty() => {
+                println!("{}  unknown to {}", pad_right(ticker, 10), config.provider)
+            }
+            None => println!(
+                "{}  unknown to {}, as is {}",
+                pad_right(ticker, 10),
+                config.provider,
+                candidates.join(", ")
+            ),
+        }
+    }
+    if unknown > 0 {
+        eprintln!(
+            "{} of {} tickers not found; search for the right symbol on {}",
+            unknown,
+            tickers.len(),
+            config.provider
+        );
+    }
+    unknown == 0
 }
 
-fn get_csv_path() -> PathBuf {
-    // Check command line arguments
-    let args: Vec<String> = env::args().collect();
-    if args.len() > 1 {
-        return PathBuf::from(&args[1]);
+/// Fetches and prints one quote with its change since the previous close,
+/// which is left out when no daily history is available
+fn run_quote(ticker: &str, json: bool) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let price = fetch_latest_price(ticker)?;
+    let as_of = unix_now();
+    let closes = fetch_daily_closes(ticker, Date::today().add_days(-14)).unwrap_or_default();
+    // After the close the latest daily bar is the quote itself
+    let previous = match closes.as_slice() {
+        [.., (_, before), (_, last)] if (last - price).abs() < 1e-9 => Some(*before),
+        [.., (_, last)] => Some(*last),
+        [] => None,
+    };
+    let change = previous.map(|previous| (price - previous, (price / previous - 1.0) * 100.0));
+    let currency = symbols::currency(ticker);
+
+    if json {
+        let doc = Json::object([
+            ("ticker", Json::from(ticker)),
+            ("price", Json::from(price)),
+            ("currency", Json::from(currency)),
+            ("previous_close", Json::from(previous)),
+            ("change", Json::from(change.map(|change| change.0))),
+            ("change_percent", Json::from(change.map(|change| change.1))),
+            ("as_of", Json::from(as_of)),
+        ]);
+        println!("{}", doc);
+        return Ok(());
     }
+    let symbol = currency.map(fx::symbol).unwrap_or_default();
+    let change = change
+        .map(|(amount, percent)| {
+            format!(
+                "  {}{} ({})",
+                if amount < 0.0 { "-" } else { "+" },
+                format_price(&symbol, amount.abs()),
+                format_percent(percent)
+            )
+        })
+        .unwrap_or_default();
+    println!(
+        "{}  {}{}  at {}",
+        ticker,
+        format_price(&symbol, price),
+        change,
+        local_clock(as_of)
+    );
+    Ok(())
+}
 
-    // Default to ~/.stocks/data.csv
-    let home = env::var("HOME").unwrap_or_else(|_| ".".to_string());
-    PathBuf::from(home).join(".stocks").join("data.csv")
+/// Prints the listings matching a search, one per line, in a form ready to
+/// paste into the portfolio
+fn run_search(query: &str) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let listings = symbols::search(query)?;
+    if listings.is_empty() {
+        eprintln!("Nothing found for {}", query);
+        return Ok(());
+    }
+    let width = |column: fn(&symbols::Listing) -> &str| {
+        listings
+            .iter()
+            .map(|listing| display_width(column(listing)))
+            .max()
+            .unwrap_or(0)
+    };
+    let symbol_width = width(|listing| &listing.symbol);
+    let exchange_width = width(|listing| &listing.exchange);
+    let kind_width = width(|listing| &listing.kind);
+    for listing in &listings {
+        println!(
+            "{}  {}  {}  {}",
+            pad_right(&listing.symbol, symbol_width),
+            pad_right(&listing.exchange, exchange_width),
+            pad_right(&listing.kind, kind_width),
+            listing.name
+        );
+    }
+    Ok(())
 }
 
-fn format_with_separator(value: f64) -> String {
-    let abs_value = value.abs();
-    let integer_part = abs_value as i64;
+/// Verifies tickers added since the positions file last changed
+///
+/// Uses this run's fetch results rather than extra requests. Returns the
+/// symbols the provider does not know; network failures are retried on
+/// the next change rather than reported.
+fn verify_new_tickers(csv_paths: &[PathBuf], results: &[(Position, FetchResult)]) -> Vec<String> {
+    let mut bytes = Vec::new();
+    for file in portfolio_files(csv_paths) {
+        match std::fs::read(file) {
+            Ok(contents) => bytes.extend(contents),
+            Err(_) => return Vec::new(),
+        }
+    }
+    let hash = state::content_hash(&bytes);
+    let mut state = State::default_location();
+    if state.portfolio_hash.as_deref() == Some(hash.as_str()) {
+        return Vec::new();
+    }
+
+    let mut unresolved = Vec::new();
+    for (position, result) in results {
+        if cash::is_cash_ticker(&position.ticker) || state.known_tickers.contains(&position.ticker)
+        {
+            continue;
+        }
+        match result {
+            Ok(_) => state.known_tickers.push(position.ticker.clone()),
+            Err(e) if classify_error(e.as_ref()) == FetchErrorKind::NotFound => {
+                unresolved.push(position.ticker.clone())
+            }
+            Err(_) => {}
+        }
+    }
+
+    state.known_tickers.sort();
+    state.known_tickers.dedup();
+    state.portfolio_hash = Some(hash);
+    if let Err(e) = state.save() {
+        eprintln!("Error saving state: {}", e);
+    }
+    unresolved.sort();
+    unresolved
+}
 
-    // Convert to string and add space separators
-    let int_str = integer_part.to_string();
-    let mut result = String::new();
-    let len = int_str.len();
+/// Writes per-position and total rows for `today` to the history store
+fn record_snapshot(results: &[(Position, FetchResult)], today: Date, symbol: &str) {
+    let mut rows = Vec::new();
+    let mut total_cost = 0.0;
+    let mut total_value = 0.0;
 
-    for (i, ch) in int_str.chars().enumerate() {
-        if i > 0 && (len - i) % 3 == 0 {
-            result.push(' ');
+    for (position, result) in results {
+        let cost = position.buy_price * position.shares;
+        let price = result.as_ref().ok().copied();
+        let value = price.map_or(cost, |price| price * position.shares);
+        if let Err(e) = result {
+            eprintln!("{}: Error - {}", position.ticker, e);
         }
-        result.push(ch);
+
+        total_cost += cost;
+        total_value += value;
+        rows.push(Snapshot {
+            date: today,
+            ticker: position.ticker.clone(),
+            shares: position.shares,
+            cost,
+            price,
+            value,
+        });
     }
+    rows.sort_by(|a, b| a.ticker.cmp(&b.ticker));
+    rows.push(Snapshot {
+        date: today,
+        ticker: TOTAL_TICKER.to_string(),
+        shares: 0.0,
+        cost: total_cost,
+        price: None,
+        value: total_value,
+    });
 
-    result
+    if let Err(e) = HistoryStore::default_location().record_snapshot(today, &rows) {
+        eprintln!("Error writing snapshot: {}", e);
+        std::process::exit(1);
+    }
+    println!(
+        "Recorded snapshot for {}: {} positions, value {}",
+        today,
+        results.len(),
+        format_money(symbol, total_value)
+    );
+    match fetch_status(results) {
+        0 => {}
+        status => std::process::exit(status),
+    }
 }
 
-fn consolidate_positions(positions: Vec<Position>) -> Vec<Position> {
-    let mut consolidated: HashMap<String, (f64, f64)> = HashMap::new();
+/// Thread pool for fetching quotes, with at most `jobs` requests in flight
+/// so the provider is not overwhelmed, and no idle threads beyond one per
+/// ticker
+fn fetch_pool(jobs: usize, tickers: usize) -> rayon::ThreadPool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.min(tickers).max(1))
+        .build()
+        .unwrap()
+}
 
-    // Accumulate total cost and total shares per ticker
-    for position in positions {
-        let entry = consolidated.entry(position.ticker).or_insert((0.0, 0.0));
-        entry.0 += position.buy_price * position.shares; // total cost
-        entry.1 += position.shares; // total shares
+/// Sends log records to stderr with `--verbose` or `RUST_LOG`, and to
+/// `~/.stocks/logs/` when `log.file` is set; stdout is the menu itself
+fn init_logging(config: &Config, verbose: bool) {
+    let stderr_level = env::var("RUST_LOG")
+        .ok()
+        .and_then(|value| logging::parse_filter(&value))
+        .or(verbose.then_some(LevelFilter::Debug));
+    let file_level = config.log_file.then_some(config.log_level);
+    let Some(level) = stderr_level.max(file_level) else {
+        return;
+    };
+    let file = config.log_file.then(logging::default_path);
+    if let Err(e) = logging::init(level, stderr_level.is_some(), file) {
+        eprintln!("Error opening log file: {}", e);
     }
+}
 
-    // Calculate weighted average buy price for each ticker
-    consolidated
-        .into_iter()
-        .map(|(ticker, (total_cost, total_shares))| Position {
-            ticker,
-            buy_price: total_cost / total_shares,
-            shares: total_shares,
-        })
-        .collect()
+/// Writes the sample portfolio where `--demo` looks for the default one,
+/// replacing any earlier copy
+fn write_demo_portfolio() -> io::Result<()> {
+    std::fs::create_dir_all(data_dir())?;
+    std::fs::write(data_dir().join("data.csv"), demo::PORTFOLIO)
 }
 
-fn main() {
-    // Get CSV file path from command line or use default
-    let csv_path = get_csv_path();
-    let csv_path_str = csv_path.to_str().unwrap_or("data.csv");
+/// Exit status when some quotes could not be fetched
+const EXIT_PARTIAL: i32 = 2;
+/// Exit status for an invalid config file, `STOCKS_*` variable, template or
+/// portfolio file
+const EXIT_CONFIG: i32 = 3;
+/// Exit status when not a single quote could be fetched
+const EXIT_FETCH_FAILED: i32 = 4;
 
-    // Load positions from CSV
-    let positions = match load_positions_from_csv(csv_path_str) {
-        Ok(positions) => positions,
+/// Exit status for a round of fetches: 0 when every quote came through,
+/// cash aside
+fn fetch_status(results: &[(Position, FetchResult)]) -> i32 {
+    let quotes = results
+        .iter()
+        .filter(|(position, _)| !cash::is_cash_ticker(&position.ticker));
+    let (total, failed) = quotes.fold((0, 0), |(total, failed), (_, result)| {
+        (total + 1, failed + usize::from(result.is_err()))
+    });
+    match failed {
+        0 => 0,
+        _ if failed == total => EXIT_FETCH_FAILED,
+        _ => EXIT_PARTIAL,
+    }
+}
+
+/// Loads `~/.stocks/config.toml`, exiting with the error on failure
+fn load_config() -> Config {
+    // Settings of one named portfolio apply on top of the shared ones
+    let portfolio = PORTFOLIO.get().filter(|name| *name != ALL_PORTFOLIOS);
+    match Config::load(&Config::default_path(), portfolio.map(String::as_str)) {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("Error loading positions from {}: {}", csv_path_str, e);
-            eprintln!(
-                "Usage: {} [path/to/data.csv]",
-                env::args()
-                    .next()
-                    .unwrap_or_else(|| "xbar-stocks".to_string())
-            );
-            eprintln!("Default location: ~/.stocks/data.csv");
+            eprintln!("Error loading {}: {}", Config::default_path().display(), e);
+            std::process::exit(EXIT_CONFIG);
+        }
+    }
+}
+
+fn main() {
+    // Get command and CSV file path from command line or use default
+    let (cli, options) = parse_command();
+    let Cli {
+        command,
+        watch,
+        sort,
+        csv_paths,
+        ..
+    } = &cli;
+    if options.offline {
+        xbar_stocks::set_offline();
+    }
+    if options.demo
+        && let Err(e) = write_demo_portfolio()
+    {
+        eprintln!("Error writing the demo portfolio: {}", e);
+        std::process::exit(1);
+    }
+    let mut config = load_config();
+    LOCALE.get_or_init(|| config.locale);
+    init_logging(&config, options.verbose);
+    xbar_stocks::set_timeouts(
+        Duration::from_secs(config.connect_timeout_secs),
+        Duration::from_secs(config.fetch_timeout_secs),
+    );
+    if let Some(sort) = sort {
+        config.sort = *sort;
+    }
+    let secrets = match config.secret_store() {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            std::process::exit(EXIT_CONFIG);
+        }
+    };
+
+    if let Command::Init = command {
+        let csv_path = csv_paths.first().cloned().unwrap_or_default();
+        if let Err(e) = wizard::init(&csv_path, &Config::default_path(), secrets.as_ref()) {
+            eprintln!("Setup failed: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Quote { ticker, json } = command {
+        if let Err(e) = run_quote(ticker, *json) {
+            eprintln!("Error fetching {}: {}", ticker, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Search(query) = command {
+        if let Err(e) = run_search(query) {
+            eprintln!("Error searching for {}: {}", query, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Privacy(setting) = command {
+        if let Err(e) = set_privacy(*setting, &config) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::ExportHistory(options) = command {
+        if let Err(e) = export_history(options) {
+            eprintln!("Error exporting history: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::AuthSet(_) | Command::AuthDelete(_) = command {
+        if let Err(e) = run_auth(command, secrets.as_ref()) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Import(broker) = command {
+        if let Err(e) = run_import(*broker, &csv_paths[0]) {
+            eprintln!("Error importing {}: {}", csv_paths[0].display(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Add(trade) = command {
+        let result = if store::is_store(&csv_paths[0]) {
+            verify_ticker(&trade.ticker).and_then(|()| run_store(command, &csv_paths[0], &config))
+        } else {
+            add_to_file(trade, &csv_paths[0], secrets.as_ref())
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Sell(trade) = command {
+        let result = if store::is_store(&csv_paths[0]) {
+            run_store(command, &csv_paths[0], &config)
+        } else {
+            sell_from_file(trade, &csv_paths[0], &config, secrets.as_ref())
+        };
+        if let Err(e) = result {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Remove { ticker, dry_run } = command {
+        if let Err(e) = remove_from_file(ticker, &csv_paths[0], *dry_run, secrets.as_ref()) {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::List = command {
+        list_holdings(csv_paths, &config, secrets.as_ref());
+        return;
+    }
+
+    if let Command::Transactions = command {
+        if let Err(e) = run_store(command, &csv_paths[0], &config) {
+            eprintln!("Error: {}", e);
             std::process::exit(1);
         }
+        return;
+    }
+
+    // First run: offer to create the portfolio instead of failing
+    if let [csv_path] = csv_paths.as_slice()
+        && csv_path != Path::new(STDIN_PATH)
+        && !sheets::is_url(&csv_path.to_string_lossy())
+        && !csv_path.exists()
+    {
+        if wizard::is_interactive() {
+            match wizard::run(csv_path, &Config::default_path(), secrets.as_ref()) {
+                Ok(true) => config = load_config(),
+                Ok(false) => {}
+                Err(e) => {
+                    eprintln!("Setup failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        } else if matches!(command, Command::Show) && cli.format == Format::Xbar && in_menu_bar() {
+            // xbar never shows stderr, so the menu says what to do
+            print!("{}", wizard::setup_menu(csv_path));
+            return;
+        }
+    }
+
+    if let Some(jobs) = options.jobs {
+        config.fetch_concurrency = jobs;
+    }
+
+    if let Command::Daemon = command {
+        run_daemon(csv_paths, &config, secrets.as_ref());
+    }
+
+    if let Command::Validate = command {
+        if !validate(csv_paths, &config, secrets.as_ref()) {
+            std::process::exit(EXIT_CONFIG);
+        }
+        return;
+    }
+
+    let render = || show(&cli, &options, &config, secrets.as_ref());
+    match watch {
+        Some(interval) => run_watch(*interval, csv_paths, || {
+            render();
+        }),
+        None => {
+            // xbar replaces the whole menu with an error on a non-zero exit,
+            // so failed quotes only show in the dropdown there
+            let status = render();
+            let menu =
+                matches!(command, Command::Show) && cli.format == Format::Xbar && in_menu_bar();
+            if status == EXIT_CONFIG || status != 0 && (options.strict || !menu) {
+                std::process::exit(status);
+            }
+        }
+    }
+}
+
+/// Loads the portfolio, prices it and prints the result in the requested
+/// form, returning the exit status of the fetches, or [`EXIT_CONFIG`] after
+/// printing why the portfolio or template could not be loaded
+fn show(cli: &Cli, options: &Options, config: &Config, secrets: &dyn SecretStore) -> i32 {
+    let Cli {
+        command,
+        format,
+        group_by,
+        csv_paths,
+        ..
+    } = cli;
+    let (format, group_by) = (*format, *group_by);
+    // Under --watch a broken save is reported and the next one reloaded
+    let Some((mut lots, skipped_rows)) = load_portfolio(csv_paths, config, secrets) else {
+        return EXIT_CONFIG;
+    };
+    // Report in another currency by making it the base for this run, keeping
+    // positions without a currency column in the configured one
+    let config = &match &options.currency {
+        Some(currency) => {
+            for lot in &mut lots {
+                lot.currency = Some(position_currency(lot, &config.base_currency));
+            }
+            Config {
+                base_currency: currency.clone(),
+                ..config.clone()
+            }
+        }
+        None => config.clone(),
     };
+    // Merged files are combined into one portfolio unless broken down by file
+    if group_by != Some(GroupBy::File) {
+        for lot in &mut lots {
+            lot.source = None;
+        }
+    }
+    let (sold_positions, open_lots): (Vec<_>, Vec<_>) = lots
+        .into_iter()
+        .partition(|position| position.sell_price.is_some());
 
     // Consolidate positions with same ticker (weighted average buy price)
-    let consolidated_positions = consolidate_positions(positions);
+    let consolidated_positions = consolidate_positions(open_lots.clone());
+    let pool = &fetch_pool(config.fetch_concurrency, consolidated_positions.len());
 
-    // Create a custom thread pool with limited parallelism to avoid overwhelming the server
-    // Limit to 3 concurrent connections
-    let pool = rayon::ThreadPoolBuilder::new()
-        .num_threads(7)
-        .build()
-        .unwrap();
+    // Use the daemon's prices when it is running, otherwise fetch all stocks
+    // in parallel using rayon with limited concurrency
+    let today = Date::today();
+    let stale_after = Duration::from_secs(config.stale_after_minutes * 60);
+    // Allow a couple of missed rounds before assuming the daemon has stopped
+    let max_age = (config.daemon_interval_secs * 3) as i64;
+    let live_prices =
+        live_prices(&consolidated_positions, today, max_age).filter(|_| !options.refresh);
+    let ((results, quote_times, offline), updated_at) = match live_prices {
+        Some((prices, written_at)) => (prices, Some(written_at)),
+        None => (
+            fetch_prices(
+                pool,
+                &consolidated_positions,
+                today,
+                stale_after,
+                Duration::from_secs(config.cache_ttl_secs),
+                options.refresh,
+            ),
+            None,
+        ),
+    };
+    let live = updated_at.is_some();
+    let status = fetch_status(&results);
+    if options.strict && status != 0 {
+        for (position, result) in &results {
+            if let Err(e) = result {
+                eprintln!("{}: Error - {}", position.ticker, e);
+            }
+        }
+        return status;
+    }
 
-    // Fetch all stocks in parallel using rayon with limited concurrency
-    let results: Vec<_> = pool.install(|| {
-        consolidated_positions
-            .par_iter()
-            .map(|position| {
-                // Strip .US suffix for Yahoo Finance API
-                let result = fetch_latest_price(&position.ticker);
-                (position.clone(), result)
-            })
-            .collect()
-    });
+    // Totals are reported in the base currency; rows keep each position's own
+    let currencies: Vec<String> = results
+        .iter()
+        .map(|(position, _)| position_currency(position, &config.base_currency))
+        .collect();
+    let fx = FxRates::load(
+        &config.base_currency,
+        currencies.iter().map(String::as_str),
+        offline || live,
+    );
+
+    if let Command::Snapshot = command {
+        // Movers are measured against cached closes only
+        let closes = fetch_closes(
+            pool,
+            &history_tickers(&results, &consolidated_positions, config),
+            history_start(today, config),
+            true,
+            0,
+        );
+        let (valued, valued_closes) = in_base_currency(&results, &closes, &fx);
+        notify_eod(&valued, &valued_closes, config, today);
+        record_snapshot(&valued, today, &fx.symbol());
+        return status;
+    }
+    if let Command::Prices(style) = command {
+        for (position, result) in &results {
+            let Ok(price) = result else { continue };
+            if cash::is_cash_ticker(&position.ticker) {
+                continue;
+            }
+            // Date the price by the exchange's trading day it belongs to
+            let date = quote_times
+                .get(&position.ticker)
+                .zip(Exchange::for_ticker(&position.ticker))
+                .map(|(time, exchange)| exchange.local_time(time.as_of).0)
+                .unwrap_or(today);
+            println!(
+                "{}",
+                price_directive(
+                    *style,
+                    date,
+                    &position.ticker,
+                    *price,
+                    &position_currency(position, &config.base_currency)
+                )
+            );
+        }
+        return status;
+    }
+    // One-time check that tickers added to the file actually resolve
+    let unresolved = if offline {
+        Vec::new()
+    } else {
+        verify_new_tickers(csv_paths, &results)
+    };
+
+    // Historical closes for positions and their benchmarks (cached on disk,
+    // refreshed by the daemon when one is running)
+    let closes = fetch_closes(
+        pool,
+        &history_tickers(&results, &consolidated_positions, config),
+        history_start(today, config),
+        offline || live,
+        config.history_fetches_per_run,
+    );
+    let benchmark_notes = benchmark_notes(&results, &closes, config.benchmark.as_ref(), today);
+    let (valued, valued_closes) = in_base_currency(&results, &closes, &fx);
+    let valued_open_lots = lots_in_base_currency(&open_lots, &fx);
+    let valued_sold = lots_in_base_currency(&sold_positions, &fx);
 
     // Calculate totals and prepare output with sorting
     let mut total_investment = 0.0;
     let mut total_current_value = 0.0;
+    let mut cash_value = 0.0;
     let mut position_data = Vec::new();
+    let mut watch_lines = Vec::new();
+    let group_by = group_by.unwrap_or(if results.iter().any(|(p, _)| p.account.is_some()) {
+        GroupBy::Account
+    } else {
+        GroupBy::Flat
+    });
 
-    for (position, result) in &results {
+    let closes = if options.currency.is_some() {
+        &valued_closes
+    } else {
+        &closes
+    };
+    for ((position, result), (valued_position, valued_result)) in results.iter().zip(&valued) {
+        // Rows are in the position's currency, totals in the base currency,
+        // unless everything is converted with --currency
+        let own_currency = position_currency(position, &config.base_currency);
+        let (position, result, currency) = if options.currency.is_some() {
+            (valued_position, valued_result, config.base_currency.clone())
+        } else {
+            (position, result, own_currency.clone())
+        };
+        if position.shares == 0.0 {
+            watch_lines.push(watch_line(
+                position, result, closes, today, &currency, config,
+            ));
+            continue;
+        }
         let investment = position.buy_price * position.shares;
-        total_investment += investment;
+        if fx.rate(&own_currency).is_some() {
+            total_investment += valued_position.buy_price * valued_position.shares;
+        }
 
-        match result {
-            Ok(current_price) => {
+        match (result, valued_result) {
+            (Ok(current_price), Ok(valued_price)) => {
                 let current_value = current_price * position.shares;
                 let change_percent =
                     ((current_price - position.buy_price) / position.buy_price) * 100.0;
                 let profit_loss = current_value - investment;
 
-                total_current_value += current_value;
+                let valued_value = valued_price * position.shares;
+                total_current_value += valued_value;
+                if cash::is_cash_ticker(&position.ticker) {
+                    cash_value += valued_value;
+                }
 
                 position_data.push((
                     position.ticker.clone(),
+                    position.shares,
                     position.buy_price,
                     *current_price,
                     change_percent,
                     profit_loss,
+                    current_value,
+                    currency,
+                    (position.account.clone(), position.source.clone()),
+                    group_by.keys(position),
                     None, // No error
                 ));
             }
-            Err(e) => {
+            (Err(e), _) | (_, Err(e)) => {
                 position_data.push((
                     position.ticker.clone(),
+                    position.shares,
                     position.buy_price,
                     0.0,               // placeholder
                     f64::NEG_INFINITY, // sort errors to bottom
                     0.0,               // placeholder
+                    0.0,               // placeholder
+                    currency,
+                    (position.account.clone(), position.source.clone()),
+                    group_by.keys(position),
                     Some(e.to_string()),
                 ));
             }
         }
     }
 
-    // Sort by percentage change (highest to lowest)
-    position_data.sort_by(|a, b| b.3.partial_cmp(&a.3).unwrap_or(std::cmp::Ordering::Equal));
-
-    // Generate output lines from sorted data
-    let mut position_lines = Vec::new();
-    for (ticker, buy_price, current_price, change_percent, profit_loss, error) in position_data {
+    // Build the rows, each with the groups it belongs to
+    let now = unix_now();
+    let privacy = State::default_location().privacy.unwrap_or(config.privacy);
+    let mut rows = Vec::new();
+    let mut row_groups = Vec::new();
+    for (
+        ticker,
+        shares,
+        buy_price,
+        current_price,
+        change_percent,
+        profit_loss,
+        current_value,
+        currency,
+        owner,
+        groups,
+        error,
+    ) in position_data
+    {
+        row_groups.push(groups);
+        let symbol = fx::symbol(&currency);
+        let mut row = Row {
+            ticker: ticker.clone(),
+            currency: currency.clone(),
+            symbol,
+            shares,
+            buy_price,
+            price: Ok(current_price),
+            change_percent,
+            day_change: None,
+            profit_loss,
+            value: current_value,
+            rate: fx.rate(&currency).unwrap_or(1.0),
+            weight: current_value * fx.rate(&currency).unwrap_or(1.0) / total_current_value * 100.0,
+            collapsed: false,
+            color: "white".to_string(),
+            sparkline: String::new(),
+            session: None,
+            badge: None,
+            details: Vec::new(),
+            href: (!cash::is_cash_ticker(&ticker)).then(|| config.quote_link(&ticker)),
+        };
         if let Some(err_msg) = error {
-            position_lines.push(format!("{}: Error - {} | color=darkred", ticker, err_msg));
+            row.price = Err(err_msg);
+            row.color = if offline { "gray" } else { "darkred" }.to_string();
+            rows.push(row);
+            continue;
+        }
+        if cash::is_cash_ticker(&ticker) {
+            rows.push(row);
+            continue;
+        }
+        row.collapsed = config
+            .collapse_weight
+            .is_some_and(|threshold| row.weight < threshold)
+            || config
+                .collapse_value
+                .is_some_and(|threshold| row.value * row.rate < threshold);
+        let range = closes
+            .get(&ticker)
+            .and_then(|closes| performance::fifty_two_week_range(closes, today, current_price));
+        let near_low = match (range, config.near_low_percent) {
+            (Some((_, _, position)), Some(threshold)) => position <= threshold,
+            _ => false,
+        };
+        row.day_change = closes
+            .get(&ticker)
+            .and_then(|closes| close_on_or_before(closes, today.add_days(-1)))
+            .map(|previous| (current_price - previous) / previous * 100.0);
+        let quote_time = quote_times.get(&ticker);
+        row.color = if quote_time.is_some_and(|time| time.stale) {
+            "gray".to_string()
+        } else if near_low {
+            "orange".to_string()
+        } else if let Some(heatmap) = &config.heatmap {
+            heatmap.color(change_percent)
         } else {
-            let sign = if profit_loss >= 0.0 { "+" } else { "-" };
-            let color = if profit_loss >= 0.0 {
-                "green"
+            config.palette.color(change_percent).to_string()
+        };
+
+        // Tag positions whose market is not in its regular session
+        row.session = match Exchange::for_ticker(&ticker).map(|exchange| exchange.session_at(now)) {
+            Some(Session::Open) | None => None,
+            Some(session) => Some(session.label()),
+        };
+
+        let lots: Vec<&Position> = open_lots
+            .iter()
+            .filter(|lot| {
+                lot.ticker == ticker && (&lot.account, &lot.source) == (&owner.0, &owner.1)
+            })
+            .collect();
+        row.badge = long_term_badge(&lots, today);
+
+        // Recent closes ending with the live price, padded so columns stay aligned
+        if config.sparkline_days > 0 {
+            let mut points: Vec<f64> = closes
+                .get(&ticker)
+                .map(|closes| {
+                    let recent: Vec<f64> = closes
+                        .iter()
+                        .filter(|(date, _)| *date < today)
+                        .map(|(_, close)| *close)
+                        .collect();
+                    let skip = recent.len().saturating_sub(config.sparkline_days - 1);
+                    recent[skip..].to_vec()
+                })
+                .unwrap_or_default();
+            points.push(current_price);
+            let line = if points.len() > 1 {
+                sparkline(&points)
             } else {
-                "darkred"
+                String::new()
             };
+            row.sparkline = format!("{:<width$} ", line, width = config.sparkline_days);
+        }
 
-            // Format with padding for alignment
-            let profit_str = format!("{}${}", sign, format_with_separator(profit_loss));
-            let percent_str = format!(
-                "({}{:.2}%)",
-                if change_percent >= 0.0 { "+" } else { "" },
-                change_percent
-            );
-
-            position_lines.push(format!(
-                "{:<10} ${:.2} @ ${:.2} {:>11} {:>10} | color={}",
-                ticker, buy_price, current_price, profit_str, percent_str, color
+        // Position figures first, then lots, quote time and indicators
+        // Amounts stay hidden in privacy mode
+        if !privacy {
+            row.details
+                .push(Line::new(format!("Shares: {}", shares), "white"));
+            row.details.push(Line::new(
+                format!(
+                    "Cost basis: {} ({}/share)",
+                    format_money(&row.symbol, buy_price * shares),
+                    format_price(&row.symbol, buy_price)
+                ),
+                "white",
+            ));
+            row.details.push(Line::new(
+                format!("Market value: {}", format_money(&row.symbol, current_value)),
+                "white",
+            ));
+        }
+        if let Some(change) = row.day_change {
+            row.details.push(Line::new(
+                format!("Day change: {}", format_percent(change)),
+                config.palette.color(change),
+            ));
+        }
+        if !privacy {
+            row.details.extend(holding_lines(&lots, today, &row.symbol));
+        }
+        if let Some(time) = quote_time {
+            row.details.push(Line::new(
+                format!(
+                    "As of {}{}",
+                    local_clock(time.as_of),
+                    if time.stale { " (stale)" } else { "" }
+                ),
+                if time.stale { "gray" } else { "white" },
+            ));
+        }
+        if let Some(note) = benchmark_notes.get(&ticker) {
+            row.details.push(Line::new(note.clone(), "white"));
+        }
+        if let Some((low, high, position)) = range {
+            row.details.push(Line::new(
+                format!(
+                    "52w: {} – {} ({:.0}% of range)",
+                    format_price(&row.symbol, low),
+                    format_price(&row.symbol, high),
+                    position
+                ),
+                "white",
+            ));
+        }
+        if config.moving_averages
+            && let Some(averages) = closes.get(&ticker).and_then(|closes| {
+                let values: Vec<f64> = closes.iter().map(|(_, close)| *close).collect();
+                moving_averages(&values, 5)
+            })
+        {
+            let (signal, color) = match averages.recent_cross {
+                Some(Cross::Golden) => (" · golden cross", config.palette.gain.as_str()),
+                Some(Cross::Death) => (" · death cross", config.palette.loss.as_str()),
+                None => ("", "white"),
+            };
+            row.details.push(Line::new(
+                format!(
+                    "SMA50 {} · SMA200 {}{}",
+                    format_price(&row.symbol, averages.sma50),
+                    format_price(&row.symbol, averages.sma200),
+                    signal
+                ),
+                color,
             ));
         }
+        if config.risk_metrics
+            && let Some(own) = closes.get(&ticker)
+        {
+            let returns = indicators::daily_returns(own);
+            let benchmark = consolidated_positions
+                .iter()
+                .find(|position| position.ticker == ticker)
+                .and_then(|position| position.benchmark.clone())
+                .or_else(|| config.benchmark.clone());
+            let beta = benchmark.as_ref().and_then(|benchmark| {
+                let reference = indicators::daily_returns(closes.get(benchmark)?);
+                indicators::beta(&returns, &reference).map(|beta| (benchmark, beta))
+            });
+            if let Some(volatility) = indicators::volatility(&returns) {
+                let beta = match beta {
+                    Some((benchmark, beta)) => format!(" · beta {:.2} vs {}", beta, benchmark),
+                    None => String::new(),
+                };
+                row.details.push(Line::new(
+                    format!("Volatility {:.1}%/yr{}", volatility, beta),
+                    "white",
+                ));
+            }
+        }
+        if config.rsi
+            && let Some(value) = closes.get(&ticker).and_then(|closes| {
+                // Include the live price as the latest session
+                let mut values: Vec<f64> = closes
+                    .iter()
+                    .filter(|(date, _)| *date < today)
+                    .map(|(_, close)| *close)
+                    .collect();
+                values.push(current_price);
+                rsi(&values, 14)
+            })
+        {
+            let (label, color) = if value >= 70.0 {
+                (" overbought", "orange")
+            } else if value <= 30.0 {
+                (" oversold", "dodgerblue")
+            } else {
+                ("", "white")
+            };
+            row.details
+                .push(Line::new(format!("RSI(14): {:.0}{}", value, label), color));
+        }
+        rows.push(row);
     }
 
-    // Display in xbar format
-    let total_profit_loss = total_current_value - total_investment;
-    let total_change_percent =
-        ((total_current_value - total_investment) / total_investment) * 100.0;
+    // Tickers held in several accounts are weighed together
+    let mut allocation: Vec<(String, f64)> = Vec::new();
+    for row in rows.iter().filter(|row| row.price.is_ok()) {
+        match allocation
+            .iter_mut()
+            .find(|(ticker, _)| *ticker == row.ticker)
+        {
+            Some((_, weight)) => *weight += row.weight,
+            None => allocation.push((row.ticker.clone(), row.weight)),
+        }
+    }
+    allocation.sort_by(|a, b| b.1.total_cmp(&a.1));
 
-    // First line: appears in menu bar
-    println!(
-        "{}${} ({}{:.2}%)",
-        if total_profit_loss >= 0.0 { "+" } else { "-" },
-        format_with_separator(total_profit_loss),
-        if total_change_percent >= 0.0 { "+" } else { "" },
-        total_change_percent
-    );
+    let (rows, row_groups) = sort_rows(rows.into_iter().zip(row_groups).collect(), config.sort);
 
-    // Separator for dropdown menu
-    println!("---");
-    //
-    // // Portfolio summary
-    println!(
-        "Investment: ${} | color=white",
-        format_with_separator(total_investment)
-    );
-    println!(
-        "Current: ${} | color=white",
-        format_with_separator(total_current_value)
-    );
-    println!("---");
-    //
-    // Individual positions
-    for line in position_lines {
-        println!("{}", line);
+    let unrealized = total_current_value - total_investment;
+    let (realized, realized_cost) = realized_profit_loss(&valued_sold);
+    let (total_profit_loss, total_change_percent) = if config.include_realized {
+        let profit_loss = unrealized + realized;
+        (
+            profit_loss,
+            profit_loss / (total_investment + realized_cost) * 100.0,
+        )
+    } else {
+        (unrealized, unrealized / total_investment * 100.0)
+    };
+
+    // Title: appears in menu bar, chosen by the display schedule
+    let exchanges: Vec<Exchange> = results
+        .iter()
+        .filter(|(position, _)| !cash::is_cash_ticker(&position.ticker))
+        .filter_map(|(position, _)| Exchange::for_ticker(&position.ticker))
+        .collect();
+    // A subtle moon marks the total when every held market is shut
+    let all_closed = !exchanges.is_empty()
+        && exchanges
+            .iter()
+            .all(|exchange| exchange.session_at(now) == Session::Closed);
+    // Today's direction, or the overall one without yesterday's closes
+    let rising = holdings(&valued, &valued_closes)
+        .and_then(|holdings| performance::period_change(&holdings, today.add_days(-1)))
+        .map_or(total_profit_loss, |(amount, _)| amount)
+        >= 0.0;
+    let (emoji, title_symbol) = match (config.trend_icon, rising) {
+        (TrendIcon::None, _) => ("", None),
+        (TrendIcon::Emoji, true) => ("📈 ", None),
+        (TrendIcon::Emoji, false) => ("📉 ", None),
+        (TrendIcon::Symbol, true) => ("", Some("chart.line.uptrend.xyaxis")),
+        (TrendIcon::Symbol, false) => ("", Some("chart.line.downtrend.xyaxis")),
+    };
+    // With a cycle, xbar rotates through the lines before the first `---`
+    let base_symbol = fx.symbol();
+    let mut titles = config
+        .display
+        .modes_at(&exchanges, now)
+        .into_iter()
+        .map(|mode| {
+            format!(
+                "{}{}{}",
+                emoji,
+                header_line(
+                    mode,
+                    &valued,
+                    &valued_closes,
+                    today,
+                    (total_profit_loss, total_change_percent),
+                    total_current_value,
+                    (!privacy).then_some(base_symbol.as_str())
+                ),
+                if all_closed { " ☾" } else { "" }
+            )
+        });
+    let title = titles.next().unwrap_or_default();
+    let cycle: Vec<String> = titles.collect();
+
+    let mut notices = Vec::new();
+    if xbar_stocks::is_offline() {
+        notices.push(Line::new("Offline mode — showing cached prices", "gray"));
+    } else if offline {
+        notices.push(Line::new("Offline — showing cached prices", "orange"));
+    }
+    if !unresolved.is_empty() {
+        notices.push(Line::new(
+            format!("Unknown tickers: {}", unresolved.join(", ")),
+            "orange",
+        ));
+    }
+    if !skipped_rows.is_empty() {
+        let mut notice = Line::new(
+            format!("Skipped {} invalid rows", skipped_rows.len()),
+            "orange",
+        );
+        notice.submenu = skipped_rows
+            .iter()
+            .map(|row| Line::new(row.clone(), "orange"))
+            .collect();
+        notices.push(notice);
+    }
+
+    let mut returns = Vec::new();
+    returns.extend(performance_line(&valued, &valued_closes, today));
+    if let Some(benchmark) = &config.benchmark {
+        returns.extend(benchmark_line(&valued, &valued_closes, benchmark, today));
+    }
+    // Money-weighted figures only make sense when every position is valued
+    if valued
+        .iter()
+        .all(|(position, result)| result.is_ok() || position.shares == 0.0)
+    {
+        returns.extend(returns_line(
+            &valued_open_lots,
+            &valued_sold,
+            total_investment,
+            total_current_value,
+            today,
+        ));
+    }
+
+    // Subtotals per group; unassigned positions go last
+    let mut groups = Vec::new();
+    if group_by != GroupBy::Flat {
+        let subtotals = group_subtotals(&valued, group_by);
+        let mut names: Vec<&Option<String>> = subtotals.keys().collect();
+        names.sort_by_key(|name| (name.is_none(), *name));
+        for name in names {
+            let (cost, value) = subtotals[name];
+            groups.push(Group {
+                name: name
+                    .clone()
+                    .unwrap_or_else(|| group_by.unassigned_label().to_string()),
+                cost,
+                value,
+                rows: row_groups
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, keys)| keys.contains(name))
+                    .map(|(index, _)| index)
+                    .collect(),
+            });
+        }
+    }
+
+    let mut quotes: Vec<Quote> = Vec::new();
+    for (position, result) in &results {
+        if quotes.iter().any(|quote| quote.ticker == position.ticker) {
+            continue;
+        }
+        let time = quote_times.get(&position.ticker);
+        quotes.push(Quote {
+            ticker: position.ticker.clone(),
+            currency: position_currency(position, &config.base_currency),
+            price: match result {
+                Ok(price) => Ok(*price),
+                Err(e) => Err((classify_error(e.as_ref()), e.to_string())),
+            },
+            as_of: time.map(|time| time.as_of),
+            stale: time.is_some_and(|time| time.stale),
+        });
+    }
+
+    let report = Report {
+        title,
+        cycle,
+        title_symbol,
+        offline,
+        provider: config.provider.clone(),
+        base_currency: config.base_currency.clone(),
+        symbol: fx.symbol(),
+        notices,
+        portfolio_url: config.portfolio_url.clone(),
+        palette: config.palette.clone(),
+        privacy,
+        actions: privacy_toggle(privacy).into_iter().collect(),
+        refresh_command: refresh_command(cli, options),
+        investment: total_investment,
+        current_value: total_current_value,
+        cash: cash_value,
+        profit_loss: total_profit_loss,
+        change_percent: total_change_percent,
+        unrealized,
+        realized: (!sold_positions.is_empty()).then_some((realized, realized_cost)),
+        returns,
+        allocation,
+        updated_at: updated_at.unwrap_or(now),
+        rows,
+        groups,
+        watchlist: watch_lines,
+        archive: archive_lines(&valued_sold, &fx.symbol(), &config.palette, privacy),
+        footer: session_clock_line(&exchanges, now),
+        quotes,
+    };
+    match format.renderer(config) {
+        Ok(renderer) => print!("{}", renderer.render(&report)),
+        Err(e) => {
+            eprintln!("Error loading template: {}", e);
+            return EXIT_CONFIG;
+        }
     }
+    status
 }
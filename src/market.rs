@@ -0,0 +1,219 @@
+use crate::calendar::{self, SUNDAY_INDEX};
+use crate::date::Date;
+
+/// Where an exchange is in its trading day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Session {
+    PreMarket,
+    Open,
+    PostMarket,
+    Closed,
+}
+
+impl Session {
+    /// Short lowercase label for the output
+    pub fn label(&self) -> &'static str {
+        match self {
+            Session::PreMarket => "pre",
+            Session::Open => "open",
+            Session::PostMarket => "post",
+            Session::Closed => "closed",
+        }
+    }
+}
+
+/// Stock exchanges whose trading sessions are known
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Exchange {
+    /// NYSE and NASDAQ, 09:30-16:00 New York time
+    Us,
+    /// Warsaw Stock Exchange, 09:00-17:05 Warsaw time (including the closing auction)
+    Gpw,
+    /// London Stock Exchange, 08:00-16:30 London time
+    Lse,
+    /// Xetra, 09:00-17:30 Frankfurt time
+    Xetra,
+}
+
+impl Exchange {
+    /// Guesses the exchange from a stooq-style ticker
+    ///
+    /// `AAPL.US` trades in New York, `.UK` in London, `.DE` in Frankfurt,
+    /// and bare tickers such as `PKN` are Warsaw listings. US indices like
+    /// `^SPX` follow US hours. Anything else is unknown.
+    pub fn for_ticker(ticker: &str) -> Option<Exchange> {
+        let upper = ticker.to_uppercase();
+        if let Some(index) = upper.strip_prefix('^') {
+            return match index {
+                "SPX" | "DJI" | "NDQ" | "NDX" => Some(Exchange::Us),
+                _ => None,
+            };
+        }
+        match upper.rsplit_once('.') {
+            Some((_, "US")) => Some(Exchange::Us),
+            Some((_, "PL")) => Some(Exchange::Gpw),
+            Some((_, "UK")) => Some(Exchange::Lse),
+            Some((_, "DE")) => Some(Exchange::Xetra),
+            Some(_) => None,
+            None => Some(Exchange::Gpw),
+        }
+    }
+
+    /// Display name of the exchange
+    pub fn name(&self) -> &'static str {
+        match self {
+            Exchange::Us => "NYSE",
+            Exchange::Gpw => "GPW",
+            Exchange::Lse => "LSE",
+            Exchange::Xetra => "Xetra",
+        }
+    }
+
+    /// Regular session as seconds since local midnight (open, close)
+    fn session(&self) -> (i64, i64) {
+        match self {
+            Exchange::Us => (9 * 3600 + 30 * 60, 16 * 3600),
+            Exchange::Gpw => (9 * 3600, 17 * 3600 + 5 * 60),
+            Exchange::Lse => (8 * 3600, 16 * 3600 + 30 * 60),
+            Exchange::Xetra => (9 * 3600, 17 * 3600 + 30 * 60),
+        }
+    }
+
+    /// Extended trading window as seconds since local midnight (start, end)
+    ///
+    /// Pre-market runs from the start until the open, post-market from the
+    /// close until the end. Exchanges without extended hours cover only
+    /// their opening and closing auctions.
+    fn extended_hours(&self) -> (i64, i64) {
+        match self {
+            Exchange::Us => (4 * 3600, 20 * 3600),
+            Exchange::Gpw => (8 * 3600 + 30 * 60, 17 * 3600 + 15 * 60),
+            Exchange::Lse => (7 * 3600 + 50 * 60, 16 * 3600 + 40 * 60),
+            Exchange::Xetra => (8 * 3600, 17 * 3600 + 35 * 60),
+        }
+    }
+
+    /// Offset of the exchange's local time from UTC in seconds at `unix`
+    pub fn utc_offset(&self, unix: i64) -> i64 {
+        match self {
+            Exchange::Us => {
+                if us_dst(unix) {
+                    -4 * 3600
+                } else {
+                    -5 * 3600
+                }
+            }
+            Exchange::Lse => {
+                if eu_dst(unix) {
+                    3600
+                } else {
+                    0
+                }
+            }
+            Exchange::Gpw | Exchange::Xetra => {
+                if eu_dst(unix) {
+                    2 * 3600
+                } else {
+                    3600
+                }
+            }
+        }
+    }
+
+    /// Local date and seconds since local midnight at `unix`
+    pub fn local_time(&self, unix: i64) -> (Date, i64) {
+        let local = unix + self.utc_offset(unix);
+        (
+            Date::from_days(local.div_euclid(86_400)),
+            local.rem_euclid(86_400),
+        )
+    }
+
+    /// True if the exchange holds a regular session on this local date
+    pub fn is_trading_day(&self, date: Date) -> bool {
+        calendar::is_trading_day(*self, date)
+    }
+
+    /// True while the regular session is in progress
+    pub fn is_open(&self, unix: i64) -> bool {
+        let (date, seconds) = self.local_time(unix);
+        let (open, close) = self.session();
+        self.is_trading_day(date) && seconds >= open && seconds < close
+    }
+
+    /// The session phase at `unix`
+    pub fn session_at(&self, unix: i64) -> Session {
+        let (date, seconds) = self.local_time(unix);
+        if !self.is_trading_day(date) {
+            return Session::Closed;
+        }
+        let (open, close) = self.session();
+        let (early, late) = self.extended_hours();
+        if seconds >= open && seconds < close {
+            Session::Open
+        } else if seconds >= early && seconds < open {
+            Session::PreMarket
+        } else if seconds >= close && seconds < late {
+            Session::PostMarket
+        } else {
+            Session::Closed
+        }
+    }
+
+    /// Unix time of the most recent session close at or before `unix`
+    pub fn last_close(&self, unix: i64) -> i64 {
+        let (today, _) = self.local_time(unix);
+        let (_, close) = self.session();
+        (0..30)
+            .map(|back| today.add_days(-back))
+            .filter(|date| self.is_trading_day(*date))
+            .map(|date| self.local_to_unix(date, close))
+            .find(|close_time| *close_time <= unix)
+            .unwrap_or(unix)
+    }
+
+    /// Unix time of the next session open after `unix`, if one is scheduled
+    /// within the next 30 days
+    pub fn next_open(&self, unix: i64) -> Option<i64> {
+        let (today, _) = self.local_time(unix);
+        let (open, _) = self.session();
+        (0..30)
+            .map(|ahead| today.add_days(ahead))
+            .filter(|date| self.is_trading_day(*date))
+            .map(|date| self.local_to_unix(date, open))
+            .find(|open_time| *open_time > unix)
+    }
+
+    /// Unix time the regular session in progress at `unix` closes
+    pub fn current_close(&self, unix: i64) -> Option<i64> {
+        if !self.is_open(unix) {
+            return None;
+        }
+        let (today, _) = self.local_time(unix);
+        let (_, close) = self.session();
+        Some(self.local_to_unix(today, close))
+    }
+
+    /// Converts a local date and time of day to Unix time
+    fn local_to_unix(&self, date: Date, seconds: i64) -> i64 {
+        let local = date.days() * 86_400 + seconds;
+        // Sessions never straddle a DST switch, so the offset at the guess is exact
+        local - self.utc_offset(local - self.utc_offset(local))
+    }
+}
+
+/// US daylight saving time: second Sunday of March to first Sunday of November, 02:00 local
+fn us_dst(unix: i64) -> bool {
+    let year = Date::from_days(unix.div_euclid(86_400)).year;
+    let start = calendar::nth_weekday(year, 3, SUNDAY_INDEX, 2).days() * 86_400 + 7 * 3600; // 02:00 EST
+    let end = calendar::nth_weekday(year, 11, SUNDAY_INDEX, 1).days() * 86_400 + 6 * 3600; // 02:00 EDT
+    unix >= start && unix < end
+}
+
+/// EU summer time: last Sunday of March to last Sunday of October, 01:00 UTC
+fn eu_dst(unix: i64) -> bool {
+    let year = Date::from_days(unix.div_euclid(86_400)).year;
+    let start = calendar::last_sunday(year, 3).days() * 86_400 + 3600;
+    let end = calendar::last_sunday(year, 10).days() * 86_400 + 3600;
+    unix >= start && unix < end
+}
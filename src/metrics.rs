@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// Latest figures for one priced position, in the base currency
+#[derive(Debug, Clone)]
+pub struct PositionMetric {
+    pub ticker: String,
+    pub price: f64,
+    pub value: f64,
+    pub profit_loss: f64,
+}
+
+/// Gauges and counters exposed in the Prometheus text format
+#[derive(Debug, Default)]
+pub struct Metrics {
+    pub positions: Vec<PositionMetric>,
+    pub portfolio_value: f64,
+    pub portfolio_cost: f64,
+    /// Failed fetches so far by (ticker, error kind)
+    pub fetch_errors: BTreeMap<(String, String), u64>,
+}
+
+impl Metrics {
+    /// Renders the metrics in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let per_position =
+            |out: &mut String, name: &str, help: &str, value: fn(&PositionMetric) -> f64| {
+                header(out, name, help, "gauge");
+                for position in &self.positions {
+                    let ticker = escape_label(&position.ticker);
+                    let _ = writeln!(out, "{}{{ticker=\"{}\"}} {}", name, ticker, value(position));
+                }
+            };
+        per_position(
+            &mut out,
+            "stocks_position_price",
+            "Latest price per share",
+            |p| p.price,
+        );
+        per_position(
+            &mut out,
+            "stocks_position_value",
+            "Market value of the position",
+            |p| p.value,
+        );
+        per_position(
+            &mut out,
+            "stocks_position_profit_loss",
+            "Unrealized profit or loss of the position",
+            |p| p.profit_loss,
+        );
+
+        header(
+            &mut out,
+            "stocks_portfolio_value",
+            "Market value of all priced positions",
+            "gauge",
+        );
+        let _ = writeln!(out, "stocks_portfolio_value {}", self.portfolio_value);
+        header(
+            &mut out,
+            "stocks_portfolio_cost",
+            "Invested amount of all priced positions",
+            "gauge",
+        );
+        let _ = writeln!(out, "stocks_portfolio_cost {}", self.portfolio_cost);
+
+        header(
+            &mut out,
+            "stocks_fetch_errors_total",
+            "Failed price fetches",
+            "counter",
+        );
+        for ((ticker, kind), count) in &self.fetch_errors {
+            let _ = writeln!(
+                out,
+                "stocks_fetch_errors_total{{ticker=\"{}\",kind=\"{}\"}} {}",
+                escape_label(ticker),
+                kind,
+                count
+            );
+        }
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// How long a client may take to send its request or read the answer
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Longest request head read before answering
+const MAX_REQUEST: u64 = 16 * 1024;
+
+/// Serves `GET /metrics` on `addr` from a background thread, answering
+/// with whatever text is currently in `body`
+///
+/// Each connection is answered on its own thread, so a client that stalls
+/// holds up only itself until its timeout.
+pub fn serve(addr: &str, body: Arc<Mutex<String>>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let body = Arc::clone(&body);
+            thread::spawn(move || {
+                if let Err(e) = respond(stream, &body) {
+                    eprintln!("Metrics request failed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, body: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Read the headers up to the blank line, so closing the connection
+    // does not reset it under a client still sending them
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 && !line.trim_end().is_empty() {
+        line.clear();
+    }
+    let path = request_line.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, content) = if path == "/metrics" {
+        let text = body.lock().map(|text| text.clone()).unwrap_or_default();
+        ("200 OK", text)
+    } else {
+        ("404 Not Found", "Not found\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content.len(),
+        content
+    )
+}
@@ -0,0 +1,121 @@
+use std::error::Error;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// Minimal MQTT 3.1.1 client: connect, publish at QoS 0, disconnect
+///
+/// That is all a periodic publisher needs, so no broker library is pulled in.
+pub struct MqttClient {
+    stream: TcpStream,
+}
+
+impl MqttClient {
+    /// Connects to `addr` (host:port) with a clean session
+    pub fn connect(
+        addr: &str,
+        client_id: &str,
+        username: Option<&str>,
+        password: Option<&str>,
+    ) -> Result<MqttClient, Box<dyn Error + Send + Sync>> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+        let mut client = MqttClient { stream };
+
+        let mut flags = 0x02; // clean session
+        let mut body = Vec::new();
+        push_string(&mut body, "MQTT");
+        body.push(4); // protocol level 3.1.1
+        if username.is_some() {
+            flags |= 0x80;
+        }
+        if password.is_some() {
+            flags |= 0x40;
+        }
+        body.push(flags);
+        body.extend_from_slice(&60u16.to_be_bytes()); // keep-alive seconds
+        push_string(&mut body, client_id);
+        if let Some(username) = username {
+            push_string(&mut body, username);
+        }
+        if let Some(password) = password {
+            push_string(&mut body, password);
+        }
+        client.send(0x10, &body)?;
+
+        let mut connack = [0u8; 4];
+        client.stream.read_exact(&mut connack)?;
+        match connack {
+            [0x20, 0x02, _, 0] => Ok(client),
+            [0x20, 0x02, _, 4 | 5] => Err("MQTT broker rejected the credentials".into()),
+            [0x20, 0x02, _, code] => {
+                Err(format!("MQTT broker refused connection ({})", code).into())
+            }
+            _ => Err("Unexpected reply from MQTT broker".into()),
+        }
+    }
+
+    /// Publishes a message at QoS 0
+    pub fn publish(
+        &mut self,
+        topic: &str,
+        payload: &str,
+        retain: bool,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut body = Vec::new();
+        push_string(&mut body, topic);
+        body.extend_from_slice(payload.as_bytes());
+        self.send(0x30 | retain as u8, &body)
+    }
+
+    /// Closes the connection cleanly
+    pub fn disconnect(mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.send(0xE0, &[])
+    }
+
+    fn send(&mut self, header: u8, body: &[u8]) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut packet = vec![header];
+        // Remaining length, 7 bits per byte with a continuation bit
+        let mut length = body.len();
+        loop {
+            let mut byte = (length % 128) as u8;
+            length /= 128;
+            if length > 0 {
+                byte |= 0x80;
+            }
+            packet.push(byte);
+            if length == 0 {
+                break;
+            }
+        }
+        packet.extend_from_slice(body);
+        self.stream.write_all(&packet)?;
+        Ok(())
+    }
+}
+
+fn push_string(buffer: &mut Vec<u8>, text: &str) {
+    buffer.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    buffer.extend_from_slice(text.as_bytes());
+}
+
+/// Lowercase identifier safe for MQTT topics and Home Assistant object ids
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::mqtt::object_id;
+///
+/// assert_eq!(object_id("AAPL.US"), "aapl_us");
+/// assert_eq!(object_id("^SPX"), "spx");
+/// ```
+pub fn object_id(ticker: &str) -> String {
+    ticker
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string()
+}
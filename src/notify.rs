@@ -0,0 +1,44 @@
+use crate::http_client;
+use crate::json::Json;
+use std::error::Error;
+use std::process::Command;
+
+/// Shows a desktop notification: Notification Center on macOS through
+/// `osascript`, `notify-send` elsewhere
+pub fn desktop(title: &str, message: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let status = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {} with title {}",
+            applescript_string(message),
+            applescript_string(title)
+        );
+        Command::new("osascript").args(["-e", &script]).status()?
+    } else {
+        Command::new("notify-send")
+            .args([title, message])
+            .status()?
+    };
+    if !status.success() {
+        return Err(format!("notification command failed with {}", status).into());
+    }
+    Ok(())
+}
+
+/// Posts `{"text": ...}` to a webhook, the shape Slack, Mattermost and
+/// Discord-compatible endpoints accept
+pub fn webhook(url: &str, text: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let body = Json::object([("text", Json::from(text))]).to_string();
+    let response = http_client()?
+        .post(url)
+        .header("Content-Type", "application/json")
+        .body(body)
+        .send()?;
+    if !response.status().is_success() {
+        return Err(format!("Webhook answered HTTP{}", response.status()).into());
+    }
+    Ok(())
+}
+
+fn applescript_string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
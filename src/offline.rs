@@ -0,0 +1,56 @@
+use crate::FetchErrorKind;
+use std::sync::Mutex;
+
+/// Spots the "everything fails the same way" pattern of a dead network
+///
+/// Hotel Wi-Fi portals and DNS outages make every request fail identically.
+/// Once `threshold` fetches have failed with the same kind of error and none
+/// has succeeded, the network is considered down and the remaining fetches
+/// can be skipped.
+pub struct OfflineDetector {
+    threshold: usize,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    successes: usize,
+    failures: Vec<FetchErrorKind>,
+}
+
+impl OfflineDetector {
+    /// Creates a detector that trips after `threshold` identical failures
+    pub fn new(threshold: usize) -> OfflineDetector {
+        OfflineDetector {
+            threshold: threshold.max(1),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Records a successful fetch
+    pub fn record_success(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.successes += 1;
+        }
+    }
+
+    /// Records a failed fetch
+    pub fn record_failure(&self, kind: FetchErrorKind) {
+        if let Ok(mut state) = self.state.lock() {
+            state.failures.push(kind);
+        }
+    }
+
+    /// True once enough identical failures have been seen without any success
+    pub fn is_offline(&self) -> bool {
+        let Ok(state) = self.state.lock() else {
+            return false;
+        };
+        if state.successes > 0 || state.failures.len() < self.threshold {
+            return false;
+        }
+        let first = state.failures[0];
+        // An unknown ticker is a per-symbol problem unless it happens everywhere
+        state.failures.iter().all(|kind| *kind == first)
+    }
+}
@@ -0,0 +1,327 @@
+use crate::date::Date;
+use std::io::{self, Write};
+
+/// Marks both ends of a Parquet file
+const MAGIC: &[u8] = b"PAR1";
+
+/// Values of one column, all of the same length
+#[derive(Debug, Clone)]
+pub enum Column {
+    /// Calendar dates, stored as days since 1970-01-01
+    Date(Vec<Date>),
+    /// UTF-8 strings
+    Text(Vec<String>),
+    Double(Vec<f64>),
+    /// Numbers that may be missing, stored as nulls
+    OptionalDouble(Vec<Option<f64>>),
+}
+
+impl Column {
+    /// Number of values
+    pub fn len(&self) -> usize {
+        match self {
+            Column::Date(values) => values.len(),
+            Column::Text(values) => values.len(),
+            Column::Double(values) => values.len(),
+            Column::OptionalDouble(values) => values.len(),
+        }
+    }
+
+    /// True when the column has no values
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The value in `row` as text, empty for a null
+    pub fn text(&self, row: usize) -> String {
+        match self {
+            Column::Date(values) => values[row].to_string(),
+            Column::Text(values) => values[row].clone(),
+            Column::Double(values) => values[row].to_string(),
+            Column::OptionalDouble(values) => values[row]
+                .map(|value| value.to_string())
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Parquet physical type: INT32, DOUBLE or BYTE_ARRAY
+    fn physical_type(&self) -> i32 {
+        match self {
+            Column::Date(_) => 1,
+            Column::Double(_) | Column::OptionalDouble(_) => 5,
+            Column::Text(_) => 6,
+        }
+    }
+
+    /// The page body: definition levels for optional columns, then the
+    /// present values in the plain encoding
+    fn page(&self) -> Vec<u8> {
+        let mut page = Vec::new();
+        match self {
+            Column::Date(values) => {
+                for date in values {
+                    page.extend_from_slice(&(date.days() as i32).to_le_bytes());
+                }
+            }
+            Column::Text(values) => {
+                for text in values {
+                    page.extend_from_slice(&(text.len() as u32).to_le_bytes());
+                    page.extend_from_slice(text.as_bytes());
+                }
+            }
+            Column::Double(values) => {
+                for value in values {
+                    page.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            Column::OptionalDouble(values) => {
+                let levels = definition_levels(values.iter().map(Option::is_some));
+                page.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+                page.extend_from_slice(&levels);
+                for value in values.iter().flatten() {
+                    page.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+        }
+        page
+    }
+}
+
+/// Definition levels of one bit as runs of the RLE/bit-packed hybrid
+/// encoding: 1 for a present value, 0 for a null
+fn definition_levels(present: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut runs: Vec<(bool, u64)> = Vec::new();
+    for value in present {
+        match runs.last_mut() {
+            Some((last, count)) if *last == value => *count += 1,
+            _ => runs.push((value, 1)),
+        }
+    }
+    let mut out = Vec::new();
+    for (value, count) in runs {
+        varint(&mut out, count << 1);
+        out.push(u8::from(value));
+    }
+    out
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Writes structs in the Thrift compact protocol Parquet metadata uses
+struct Thrift {
+    out: Vec<u8>,
+    /// Last field id of each open struct, as ids are written as deltas
+    last: Vec<i16>,
+}
+
+impl Thrift {
+    const I32: u8 = 5;
+    const I64: u8 = 6;
+    const BINARY: u8 = 8;
+    const LIST: u8 = 9;
+    const STRUCT: u8 = 12;
+
+    fn new() -> Thrift {
+        Thrift {
+            out: Vec::new(),
+            last: vec![0],
+        }
+    }
+
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last.last_mut().expect("an open struct");
+        match id - *last {
+            delta @ 1..=15 => self.out.push((delta as u8) << 4 | kind),
+            _ => {
+                self.out.push(kind);
+                varint(&mut self.out, zigzag(i64::from(id)));
+            }
+        }
+        *last = id;
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, Thrift::I32);
+        varint(&mut self.out, zigzag(i64::from(value)));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, Thrift::I64);
+        varint(&mut self.out, zigzag(value));
+    }
+
+    fn string(&mut self, id: i16, value: &str) {
+        self.field(id, Thrift::BINARY);
+        self.string_element(value);
+    }
+
+    fn string_element(&mut self, value: &str) {
+        varint(&mut self.out, value.len() as u64);
+        self.out.extend_from_slice(value.as_bytes());
+    }
+
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, Thrift::LIST);
+        if len < 15 {
+            self.out.push((len as u8) << 4 | kind);
+        } else {
+            self.out.push(0xf0 | kind);
+            varint(&mut self.out, len as u64);
+        }
+    }
+
+    /// Opens a struct field; `id` 0 opens a list element instead
+    fn begin(&mut self, id: i16) {
+        if id != 0 {
+            self.field(id, Thrift::STRUCT);
+        }
+        self.last.push(0);
+    }
+
+    fn end(&mut self) {
+        self.out.push(0);
+        self.last.pop();
+    }
+
+    /// Closes the top-level struct and returns its bytes
+    fn finish(mut self) -> Vec<u8> {
+        self.out.push(0);
+        self.out
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// Writes `columns` as an uncompressed Parquet file with one row group
+///
+/// Dates and text carry the DATE and STRING logical types, so readers such
+/// as pandas or DuckDB load them as dates and strings.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::date::Date;
+/// use xbar_stocks::parquet::{self, Column};
+///
+/// let mut file = Vec::new();
+/// parquet::write(
+///     &mut file,
+///     &[
+///         ("date", Column::Date(vec![Date::parse("2025-01-02").unwrap(); 2])),
+///         ("ticker", Column::Text(vec!["AAPL.US".into(), "PKN".into()])),
+///         ("price", Column::OptionalDouble(vec![Some(243.85), None])),
+///     ],
+/// )
+/// .unwrap();
+/// assert!(file.starts_with(b"PAR1") && file.ends_with(b"PAR1"));
+///
+/// let uneven = [
+///     ("ticker", Column::Text(vec!["AAPL.US".into()])),
+///     ("close", Column::Double(Vec::new())),
+/// ];
+/// assert!(parquet::write(&mut Vec::new(), &uneven).is_err());
+/// ```
+pub fn write(out: &mut impl Write, columns: &[(&str, Column)]) -> io::Result<()> {
+    let rows = columns.first().map_or(0, |(_, column)| column.len());
+    if columns.iter().any(|(_, column)| column.len() != rows) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "columns differ in length",
+        ));
+    }
+
+    out.write_all(MAGIC)?;
+    let mut offset = MAGIC.len() as i64;
+    // Offset and size of each column chunk, one data page each
+    let mut chunks = Vec::new();
+    for (_, column) in columns {
+        let page = column.page();
+        let mut header = Thrift::new();
+        // A version 1 data page of plain values, levels RLE-encoded
+        header.i32(1, 0);
+        header.i32(2, page.len() as i32);
+        header.i32(3, page.len() as i32);
+        header.begin(5);
+        header.i32(1, rows as i32);
+        header.i32(2, 0);
+        header.i32(3, 3);
+        header.i32(4, 3);
+        header.end();
+        let header = header.finish();
+        out.write_all(&header)?;
+        out.write_all(&page)?;
+        let size = (header.len() + page.len()) as i64;
+        chunks.push((offset, size));
+        offset += size;
+    }
+
+    let mut meta = Thrift::new();
+    meta.i32(1, 1);
+    // The schema is a root group followed by its columns
+    meta.list(2, Thrift::STRUCT, columns.len() + 1);
+    meta.begin(0);
+    meta.string(4, "schema");
+    meta.i32(5, columns.len() as i32);
+    meta.end();
+    for (name, column) in columns {
+        meta.begin(0);
+        meta.i32(1, column.physical_type());
+        let optional = matches!(column, Column::OptionalDouble(_));
+        meta.i32(3, i32::from(optional));
+        meta.string(4, name);
+        // Converted type and logical type, for older and newer readers
+        let logical = match column {
+            Column::Date(_) => Some((6, 6)),
+            Column::Text(_) => Some((0, 1)),
+            _ => None,
+        };
+        if let Some((converted, logical)) = logical {
+            meta.i32(6, converted);
+            meta.begin(10);
+            meta.begin(logical);
+            meta.end();
+            meta.end();
+        }
+        meta.end();
+    }
+    meta.i64(3, rows as i64);
+    meta.list(4, Thrift::STRUCT, 1);
+    meta.begin(0);
+    meta.list(1, Thrift::STRUCT, columns.len());
+    for ((name, column), (offset, size)) in columns.iter().zip(&chunks) {
+        meta.begin(0);
+        meta.i64(2, *offset);
+        meta.begin(3);
+        meta.i32(1, column.physical_type());
+        meta.list(2, Thrift::I32, 2);
+        // PLAIN and RLE
+        varint(&mut meta.out, zigzag(0));
+        varint(&mut meta.out, zigzag(3));
+        meta.list(3, Thrift::BINARY, 1);
+        meta.string_element(name);
+        meta.i32(4, 0);
+        meta.i64(5, rows as i64);
+        meta.i64(6, *size);
+        meta.i64(7, *size);
+        meta.i64(9, *offset);
+        meta.end();
+        meta.end();
+    }
+    meta.i64(2, chunks.iter().map(|(_, size)| size).sum());
+    meta.i64(3, rows as i64);
+    meta.end();
+    meta.string(6, "xbar-stocks");
+    let meta = meta.finish();
+
+    out.write_all(&meta)?;
+    out.write_all(&(meta.len() as u32).to_le_bytes())?;
+    out.write_all(MAGIC)
+}
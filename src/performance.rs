@@ -0,0 +1,207 @@
+use crate::date::Date;
+use crate::history::close_on_or_before;
+
+/// Look-back periods shown in the dropdown
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Period {
+    Week,
+    Month,
+    YearToDate,
+}
+
+impl Period {
+    pub const ALL: [Period; 3] = [Period::Week, Period::Month, Period::YearToDate];
+
+    /// Short label used in the output (e.g. "1W")
+    pub fn label(&self) -> &'static str {
+        match self {
+            Period::Week => "1W",
+            Period::Month => "1M",
+            Period::YearToDate => "YTD",
+        }
+    }
+
+    /// The reference date whose close the period is measured from
+    pub fn start(&self, today: Date) -> Date {
+        match self {
+            Period::Week => today.add_days(-7),
+            Period::Month => today.add_months(-1),
+            // Measured from the last close of the previous year
+            Period::YearToDate => Date {
+                year: today.year - 1,
+                month: 12,
+                day: 31,
+            },
+        }
+    }
+}
+
+/// A currently held position as seen by the performance calculation
+pub struct Holding<'a> {
+    pub shares: f64,
+    pub current_price: f64,
+    /// Daily closes, or `None` for positions whose price does not move (cash)
+    pub closes: Option<&'a [(Date, f64)]>,
+}
+
+/// Percentage change of the current holdings' value since `start`
+///
+/// Uses today's share counts throughout, so deposits and withdrawals do
+/// not distort the figure. Returns `None` if any holding lacks a close
+/// on or before `start`.
+pub fn period_return(holdings: &[Holding], start: Date) -> Option<f64> {
+    period_change(holdings, start).map(|(_, percent)| percent)
+}
+
+/// Absolute and percentage change of the current holdings' value since `start`
+pub fn period_change(holdings: &[Holding], start: Date) -> Option<(f64, f64)> {
+    let mut start_value = 0.0;
+    let mut current_value = 0.0;
+
+    for holding in holdings {
+        let start_price = match holding.closes {
+            Some(closes) => close_on_or_before(closes, start)?,
+            None => holding.current_price,
+        };
+        start_value += start_price * holding.shares;
+        current_value += holding.current_price * holding.shares;
+    }
+
+    if start_value <= 0.0 {
+        return None;
+    }
+    let change = current_value - start_value;
+    Some((change, change / start_value * 100.0))
+}
+
+/// Percentage change from the close on or before `start` to the latest close
+pub fn series_return(closes: &[(Date, f64)], start: Date) -> Option<f64> {
+    let start_price = close_on_or_before(closes, start)?;
+    let (_, last) = closes.last()?;
+    change_percent(start_price, *last)
+}
+
+/// Percentage-point difference between a position and its benchmark since `start`
+///
+/// The position is measured up to `current_price`, the benchmark up to its
+/// latest close.
+pub fn relative_return(
+    current_price: f64,
+    closes: &[(Date, f64)],
+    benchmark_closes: &[(Date, f64)],
+    start: Date,
+) -> Option<f64> {
+    let own = change_percent(close_on_or_before(closes, start)?, current_price)?;
+    let benchmark = series_return(benchmark_closes, start)?;
+    Some(own - benchmark)
+}
+
+fn change_percent(from: f64, to: f64) -> Option<f64> {
+    if from <= 0.0 {
+        return None;
+    }
+    Some((to - from) / from * 100.0)
+}
+
+/// Annualized internal rate of return of dated cash flows, in percent
+///
+/// Investments are negative, withdrawals and the current value positive.
+/// Returns `None` unless there is at least one flow of each sign.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::date::Date;
+/// use xbar_stocks::performance::xirr;
+///
+/// let flows = [
+///     (Date::parse("2023-01-01").unwrap(), -1000.0),
+///     (Date::parse("2024-01-01").unwrap(), 1100.0),
+/// ];
+/// assert!((xirr(&flows).unwrap() - 10.0).abs() < 0.01);
+/// ```
+pub fn xirr(flows: &[(Date, f64)]) -> Option<f64> {
+    if !flows.iter().any(|(_, amount)| *amount < 0.0)
+        || !flows.iter().any(|(_, amount)| *amount > 0.0)
+    {
+        return None;
+    }
+    let first = flows.iter().map(|(date, _)| *date).min()?;
+    let npv = |rate: f64| -> f64 {
+        flows
+            .iter()
+            .map(|(date, amount)| {
+                let years = first.days_until(*date) as f64 / 365.0;
+                amount / (1.0 + rate).powf(years)
+            })
+            .sum()
+    };
+
+    // NPV falls as the rate rises, so bisect between -99.99% and +10000%
+    let (mut low, mut high) = (-0.9999, 100.0);
+    if npv(low).signum() == npv(high).signum() {
+        return None;
+    }
+    for _ in 0..200 {
+        let mid = (low + high) / 2.0;
+        if npv(mid) > 0.0 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    Some((low + high) / 2.0 * 100.0)
+}
+
+/// Cumulative time-weighted return over a series of valuations, in percent
+///
+/// Each point is `(invested, value)` in chronological order; changes in the
+/// invested amount between points are treated as deposits or withdrawals
+/// made just before the later valuation, so they do not count as returns.
+pub fn time_weighted_return(points: &[(f64, f64)]) -> Option<f64> {
+    if points.len() < 2 {
+        return None;
+    }
+
+    let mut growth = 1.0;
+    for pair in points.windows(2) {
+        let (previous_cost, previous_value) = pair[0];
+        let (cost, value) = pair[1];
+        let flow = cost - previous_cost;
+        let base = previous_value + flow;
+        if base <= 0.0 {
+            return None;
+        }
+        growth *= value / base;
+    }
+    Some((growth - 1.0) * 100.0)
+}
+
+/// Closing-price range over the year before `today`, including `current_price`
+///
+/// Returns `(low, high, position)` where position is where the current price
+/// sits within the range, from 0% at the low to 100% at the high.
+pub fn fifty_two_week_range(
+    closes: &[(Date, f64)],
+    today: Date,
+    current_price: f64,
+) -> Option<(f64, f64, f64)> {
+    let start = today.add_days(-365);
+    let year: Vec<f64> = closes
+        .iter()
+        .filter(|(date, _)| *date >= start)
+        .map(|(_, close)| *close)
+        .collect();
+    if year.is_empty() {
+        return None;
+    }
+
+    let low = year.iter().copied().fold(current_price, f64::min);
+    let high = year.iter().copied().fold(current_price, f64::max);
+    let position = if high > low {
+        (current_price - low) / (high - low) * 100.0
+    } else {
+        100.0
+    };
+    Some((low, high, position))
+}
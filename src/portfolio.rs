@@ -0,0 +1,222 @@
+use crate::config::{Value, parse_value, strip_comment};
+use crate::json::Json;
+use std::error::Error;
+
+/// Fields of one position in a structured portfolio file, in file order
+pub type Entry = Vec<(String, Value)>;
+
+/// Structured portfolio formats besides CSV
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Toml,
+    Yaml,
+    Json,
+}
+
+impl Format {
+    /// Picks the format from a file extension
+    pub fn from_extension(extension: &str) -> Option<Format> {
+        match extension.to_lowercase().as_str() {
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            "json" => Some(Format::Json),
+            _ => None,
+        }
+    }
+
+    /// Recognizes a structured document by its first character, for input
+    /// without a file name such as stdin; CSV yields `None`
+    pub fn sniff(contents: &[u8]) -> Option<Format> {
+        let text = String::from_utf8_lossy(contents);
+        let start = text.trim_start();
+        if start.starts_with("[[") {
+            Some(Format::Toml)
+        } else if start.starts_with(['[', '{']) {
+            Some(Format::Json)
+        } else {
+            None
+        }
+    }
+
+    /// Parses the positions of a document in this format
+    pub fn parse(&self, text: &str) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+        match self {
+            Format::Toml => parse_toml(text),
+            Format::Yaml => parse_yaml(text),
+            Format::Json => parse_json(text),
+        }
+    }
+}
+
+/// Parses `[[position]]` tables from a `portfolio.toml`
+///
+/// Values follow the config file syntax, except that dates may be written
+/// bare as TOML allows.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::config::Value;
+/// use xbar_stocks::portfolio::parse_toml;
+///
+/// let text = "[[position]]\nticker = \"AAPL.US\"\nshares = 10\ndate = 2024-05-01\n";
+/// let entries = parse_toml(text).unwrap();
+/// assert_eq!(entries[0][1], ("shares".to_string(), Value::Number(10.0)));
+/// assert_eq!(entries[0][2].1, Value::String("2024-05-01".to_string()));
+/// ```
+pub fn parse_toml(text: &str) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+            if !matches!(name.trim(), "position" | "positions") {
+                return Err(format!("line {}: expected [[position]]", index + 1).into());
+            }
+            entries.push(Vec::new());
+            continue;
+        }
+        let Some(entry) = entries.last_mut() else {
+            return Err(format!("line {}: value outside a [[position]] table", index + 1).into());
+        };
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| format!("line {}: expected key = value", index + 1))?;
+        entry.push((
+            key.trim().trim_matches('"').to_string(),
+            scalar(value.trim(), index)?,
+        ));
+    }
+    Ok(entries)
+}
+
+/// Parses a YAML list of positions, either at the top level or under a
+/// `positions:` key
+///
+/// Only the block-list-of-mappings shape is understood, with scalar values
+/// and `[a, b]` flow lists, which is all a portfolio needs.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::config::Value;
+/// use xbar_stocks::portfolio::parse_yaml;
+///
+/// let text = "positions:\n  - ticker: PKN\n    tags: [energy, dividend]\n  - ticker: CASH\n";
+/// let entries = parse_yaml(text).unwrap();
+/// assert_eq!(entries.len(), 2);
+/// assert_eq!(entries[0][0].1, Value::String("PKN".to_string()));
+/// ```
+pub fn parse_yaml(text: &str) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+    let mut entries: Vec<Entry> = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw).trim_end();
+        let content = line.trim_start();
+        if content.is_empty() || content == "---" {
+            continue;
+        }
+        if line == "positions:" {
+            continue;
+        }
+        let field = match content.strip_prefix("- ").or(content.strip_prefix('-')) {
+            Some(rest) => {
+                entries.push(Vec::new());
+                rest.trim()
+            }
+            None => content,
+        };
+        if field.is_empty() {
+            continue;
+        }
+        let Some(entry) = entries.last_mut() else {
+            return Err(format!("line {}: expected a '- ' list item", index + 1).into());
+        };
+        let (key, value) = field
+            .split_once(':')
+            .ok_or_else(|| format!("line {}: expected key: value", index + 1))?;
+        entry.push((key.trim().to_string(), scalar(value.trim(), index)?));
+    }
+    Ok(entries)
+}
+
+/// Parses a JSON array of position objects, bare or under `"positions"`
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::config::Value;
+/// use xbar_stocks::portfolio::parse_json;
+///
+/// let entries = parse_json(r#"{"positions": [{"ticker": "PKN", "shares": 5}]}"#).unwrap();
+/// assert_eq!(entries[0][1], ("shares".to_string(), Value::Number(5.0)));
+/// ```
+pub fn parse_json(text: &str) -> Result<Vec<Entry>, Box<dyn Error + Send + Sync>> {
+    let document = Json::parse(text)?;
+    let items = document
+        .get("positions")
+        .unwrap_or(&document)
+        .as_array()
+        .ok_or("expected an array of positions")?;
+    items
+        .iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let Json::Object(fields) = item else {
+                return Err(format!("position {}: expected an object", index + 1).into());
+            };
+            fields
+                .iter()
+                // A null is the same as leaving the field out
+                .filter(|(_, value)| *value != Json::Null)
+                .map(|(key, value)| {
+                    json_value(value)
+                        .map(|value| (key.clone(), value))
+                        .ok_or_else(|| {
+                            format!("position {}: unsupported value for {}", index + 1, key).into()
+                        })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn json_value(value: &Json) -> Option<Value> {
+    match value {
+        Json::Bool(value) => Some(Value::Bool(*value)),
+        Json::Number(value) => Some(Value::Number(*value)),
+        Json::String(value) => Some(Value::String(value.clone())),
+        Json::Array(items) => items
+            .iter()
+            .map(json_value)
+            .collect::<Option<_>>()
+            .map(Value::Array),
+        Json::Null | Json::Object(_) => None,
+    }
+}
+
+/// A config-style value, with anything else unquoted taken as a string
+/// (bare dates and YAML plain scalars)
+fn scalar(text: &str, index: usize) -> Result<Value, Box<dyn Error + Send + Sync>> {
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return inner
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(|item| scalar(item, index))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array);
+    }
+    if let Some(value) = parse_value(text) {
+        return Ok(value);
+    }
+    let single = text.strip_prefix('\'').and_then(|t| t.strip_suffix('\''));
+    if let Some(inner) = single {
+        return Ok(Value::String(inner.to_string()));
+    }
+    if text.is_empty() || text.starts_with(['"', '[', '{']) {
+        return Err(format!("line {}: invalid value '{}'", index + 1, text).into());
+    }
+    Ok(Value::String(text.to_string()))
+}
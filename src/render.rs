@@ -0,0 +1,1099 @@
+use crate::{format_change, format_money, format_percent, format_price, format_weight};
+use xbar_stocks::FetchErrorKind;
+use xbar_stocks::cash;
+use xbar_stocks::config::{Column, Palette};
+use xbar_stocks::date::local_clock;
+use xbar_stocks::json::Json;
+use xbar_stocks::template::Template;
+use xbar_stocks::width::{display_width, pad_left, pad_right};
+
+/// A line of text with the xbar color name it is shown in; `submenu` lines
+/// open from it
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub text: String,
+    pub color: String,
+    /// Page opened when the line is clicked
+    pub href: Option<String>,
+    /// Rerun the plugin when the line is clicked
+    pub refresh: bool,
+    /// Shown instead of this line while Option is held
+    pub alternate: Option<Box<Line>>,
+    /// Program and arguments run in the background when the line is clicked
+    pub command: Vec<String>,
+    pub submenu: Vec<Line>,
+}
+
+impl Line {
+    pub fn new(text: impl Into<String>, color: impl Into<String>) -> Line {
+        Line {
+            text: text.into(),
+            color: color.into(),
+            href: None,
+            refresh: false,
+            alternate: None,
+            command: Vec::new(),
+            submenu: Vec::new(),
+        }
+    }
+}
+
+/// Latest price of a held or watched ticker
+#[derive(Debug, Clone)]
+pub struct Quote {
+    pub ticker: String,
+    pub currency: String,
+    /// Price, or the kind of failure and its message
+    pub price: Result<f64, (FetchErrorKind, String)>,
+    /// Unix time the price refers to
+    pub as_of: Option<i64>,
+    pub stale: bool,
+}
+
+/// One priced position, in its own currency
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub ticker: String,
+    pub currency: String,
+    /// Sign of the row's amounts: `$` in the base currency
+    pub symbol: String,
+    pub shares: f64,
+    pub buy_price: f64,
+    /// Current price, or why it could not be fetched
+    pub price: Result<f64, String>,
+    pub change_percent: f64,
+    /// Change since the previous close, in percent
+    pub day_change: Option<f64>,
+    pub profit_loss: f64,
+    pub value: f64,
+    /// Exchange rate from the row's currency to the base currency
+    pub rate: f64,
+    /// Share of the portfolio's value, in percent
+    pub weight: f64,
+    /// Small enough to be folded into the "Other" line
+    pub collapsed: bool,
+    /// xbar color name or `#RRGGBB`
+    pub color: String,
+    /// Recent closes padded to a fixed width, empty when sparklines are off
+    pub sparkline: String,
+    /// Session label when the market is not in its regular session
+    pub session: Option<&'static str>,
+    /// Long-term holding countdown
+    pub badge: Option<String>,
+    /// Lots, quote time and indicators shown when the row is opened
+    pub details: Vec<Line>,
+    /// Quote page opened when the row is clicked
+    pub href: Option<String>,
+}
+
+/// Subtotal of one account, file or tag
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub cost: f64,
+    pub value: f64,
+    /// The group's positions as indexes into [`Report::rows`]
+    pub rows: Vec<usize>,
+}
+
+impl Group {
+    /// "IKE: $541 +$81 (+17.72%) · 99.1%", with the share of `total`, or
+    /// only the percentages in privacy mode; amounts carry the base
+    /// currency's `symbol`
+    pub fn heading(&self, total: f64, privacy: bool, symbol: &str) -> String {
+        let profit_loss = self.value - self.cost;
+        let percent = profit_loss / self.cost * 100.0;
+        if privacy {
+            let change = if self.cost > 0.0 {
+                format!(" {}", format_percent(percent))
+            } else {
+                String::new()
+            };
+            return format!(
+                "{}:{} · {}",
+                self.name,
+                change,
+                format_weight(self.value / total * 100.0)
+            );
+        }
+        let change = if self.cost > 0.0 {
+            format!(" {}", format_change(symbol, profit_loss, percent))
+        } else {
+            String::new()
+        };
+        format!(
+            "{}: {}{} · {}",
+            self.name,
+            format_money(symbol, self.value),
+            change,
+            format_weight(self.value / total * 100.0)
+        )
+    }
+}
+
+/// Everything the portfolio math produces, ready for any output backend;
+/// totals are in the base currency
+#[derive(Debug, Clone)]
+pub struct Report {
+    /// Menu-bar title
+    pub title: String,
+    /// Further menu-bar lines xbar rotates through after the title
+    pub cycle: Vec<String>,
+    /// SF Symbol shown beside the menu-bar lines
+    pub title_symbol: Option<&'static str>,
+    /// Prices come from the cache because the network is down
+    pub offline: bool,
+    /// Quote provider the prices were fetched from
+    pub provider: String,
+    pub base_currency: String,
+    /// Written before amounts in the base currency, e.g. `$` or `PLN `
+    pub symbol: String,
+    /// Warnings shown before the summary
+    pub notices: Vec<Line>,
+    /// Broker or portfolio page opened by clicking the summary
+    pub portfolio_url: Option<String>,
+    /// Colors of gains and losses
+    pub palette: Palette,
+    /// Hide amounts, showing only percentages
+    pub privacy: bool,
+    /// Items that run the plugin with other arguments, such as the privacy
+    /// toggle
+    pub actions: Vec<Line>,
+    /// Command the "Refresh now" item runs before xbar reruns the plugin,
+    /// refetching every quote; empty just reruns it
+    pub refresh_command: Vec<String>,
+    pub investment: f64,
+    pub current_value: f64,
+    pub cash: f64,
+    /// Profit or loss in the title, including realized gains when configured
+    pub profit_loss: f64,
+    pub change_percent: f64,
+    pub unrealized: f64,
+    /// Realized profit or loss and the cost of the sold lots, when any were sold
+    pub realized: Option<(f64, f64)>,
+    /// Period, benchmark and money-weighted return summaries
+    pub returns: Vec<String>,
+    /// Share of the portfolio's value per ticker, largest first
+    pub allocation: Vec<(String, f64)>,
+    /// Unix time of the prices
+    pub updated_at: i64,
+    /// Positions sorted by change, cash last
+    pub rows: Vec<Row>,
+    /// Subtotals, or empty for one flat list
+    pub groups: Vec<Group>,
+    pub watchlist: Vec<Line>,
+    /// Realized outcome of each fully sold ticker
+    pub archive: Vec<Line>,
+    /// Exchange clocks
+    pub footer: Option<String>,
+    /// Every ticker priced, held or watched, once each
+    pub quotes: Vec<Quote>,
+}
+
+impl Report {
+    /// Totals and returns, one line each
+    pub fn summary(&self) -> Vec<String> {
+        if self.privacy {
+            let mut summary = vec![format!("Total: {}", format_percent(self.change_percent))];
+            if self.cash > 0.0 {
+                summary.push(format!(
+                    "Cash: {} of account",
+                    format_weight(self.cash / self.current_value * 100.0)
+                ));
+            }
+            if let Some((realized, cost)) = self.realized {
+                summary.push(format!(
+                    "Unrealized: {}",
+                    format_percent(self.unrealized / self.investment * 100.0)
+                ));
+                summary.push(format!(
+                    "Realized: {}",
+                    format_percent(realized / cost * 100.0)
+                ));
+            }
+            summary.extend(self.movers());
+            summary.extend(self.returns.iter().cloned());
+            return summary;
+        }
+        let mut summary = vec![
+            format!(
+                "Investment: {}",
+                format_money(&self.symbol, self.investment)
+            ),
+            format!(
+                "Current: {}",
+                format_money(&self.symbol, self.current_value)
+            ),
+        ];
+        if self.cash > 0.0 {
+            summary.push(format!(
+                "Cash: {} ({} of account)",
+                format_money(&self.symbol, self.cash),
+                format_weight(self.cash / self.current_value * 100.0)
+            ));
+        }
+        if let Some((realized, cost)) = self.realized {
+            summary.push(format!(
+                "Unrealized: {}",
+                format_change(
+                    &self.symbol,
+                    self.unrealized,
+                    self.unrealized / self.investment * 100.0
+                )
+            ));
+            summary.push(format!(
+                "Realized: {}",
+                format_change(&self.symbol, realized, realized / cost * 100.0)
+            ));
+        }
+        summary.extend(self.movers());
+        summary.extend(self.returns.iter().cloned());
+        summary
+    }
+
+    /// Today's best and worst position and the one that moved the total
+    /// most, from the change since the previous close
+    fn movers(&self) -> Vec<String> {
+        let moved: Vec<(&Row, f64)> = self
+            .rows
+            .iter()
+            .filter(|row| row.price.is_ok() && !cash::is_cash_ticker(&row.ticker))
+            .filter_map(|row| Some((row, row.day_change?)))
+            .collect();
+        if moved.len() < 2 {
+            return Vec::new();
+        }
+        let best = moved.iter().max_by(|a, b| a.1.total_cmp(&b.1));
+        let worst = moved.iter().min_by(|a, b| a.1.total_cmp(&b.1));
+        let mut movers = Vec::new();
+        if let (Some((best, best_change)), Some((worst, worst_change))) = (best, worst) {
+            movers.push(format!(
+                "Today: best {} {}, worst {} {}",
+                best.ticker,
+                format_percent(*best_change),
+                worst.ticker,
+                format_percent(*worst_change)
+            ));
+        }
+        // Today's gain or loss in the base currency
+        let contribution = |(row, change): &(&Row, f64)| {
+            let value = row.value * row.rate;
+            value - value / (1.0 + change / 100.0)
+        };
+        let top = moved
+            .iter()
+            .map(|mover| (mover.0, contribution(mover)))
+            .max_by(|a, b| a.1.abs().total_cmp(&b.1.abs()));
+        if let Some((row, amount)) = top
+            && !self.privacy
+        {
+            movers.push(format!(
+                "Top contributor: {} {}{} today",
+                row.ticker,
+                if amount >= 0.0 { "+" } else { "-" },
+                format_money(&self.symbol, amount)
+            ));
+        }
+        movers
+    }
+}
+
+/// Turns a report into the text of one output format
+pub trait Renderer {
+    fn render(&self, report: &Report) -> String;
+}
+
+/// The xbar plugin format: a title, then dropdown lines with `| key=value`
+/// parameters and `--` for submenus
+pub struct XbarRenderer {
+    /// Figures on each position's line
+    pub columns: Vec<Column>,
+    /// Start each position's line with ▲ or ▼ by today's change
+    pub arrows: bool,
+    /// Monospace font of the dropdown, so padded columns line up
+    pub font: Option<String>,
+}
+
+impl XbarRenderer {
+    /// Width of the ticker column: the widest priced ticker, at least ten
+    /// columns
+    fn ticker_width(report: &Report) -> usize {
+        report
+            .rows
+            .iter()
+            .filter(|row| row.price.is_ok())
+            .map(|row| display_width(&row.ticker))
+            .fold(10, usize::max)
+    }
+
+    fn line(&self, out: &mut String, depth: usize, line: &Line) {
+        self.item(out, depth, line, false);
+        if let Some(alternate) = &line.alternate {
+            self.item(out, depth, alternate, true);
+        }
+    }
+
+    fn item(&self, out: &mut String, depth: usize, line: &Line, alternate: bool) {
+        let mut params = format!("color={}", line.color);
+        if let Some(href) = &line.href {
+            params.push_str(&format!(" href={}", href));
+        }
+        if let Some((program, arguments)) = line.command.split_first() {
+            params.push_str(&format!(" bash={}", quote_param(program)));
+            for (i, argument) in arguments.iter().enumerate() {
+                params.push_str(&format!(" param{}={}", i + 1, quote_param(argument)));
+            }
+            params.push_str(" terminal=false");
+        }
+        if line.refresh {
+            params.push_str(" refresh=true");
+        }
+        if alternate {
+            params.push_str(" alternate=true");
+        }
+        if let Some(font) = &self.font {
+            params.push_str(&format!(" font={}", quote_param(font)));
+        }
+        out.push_str(&format!(
+            "{}{} | {}\n",
+            "--".repeat(depth),
+            line.text,
+            params
+        ));
+        for item in &line.submenu {
+            self.line(out, depth + 1, item);
+        }
+    }
+
+    /// A priced position's figures, padded so the lines stay aligned
+    fn columns(&self, row: &Row, price: f64, privacy: bool) -> String {
+        let mut parts = Vec::new();
+        for (i, column) in self.columns.iter().enumerate() {
+            // Figures that reveal the size of the holding
+            if privacy && matches!(column, Column::Shares | Column::Value | Column::ProfitLoss) {
+                continue;
+            }
+            parts.push(match column {
+                // "$150.00 @ $180.50" when the current price follows the buy price
+                Column::Price if i > 0 && self.columns[i - 1] == Column::BuyPrice => {
+                    format!("@ {}", format_price(&row.symbol, price))
+                }
+                Column::Price => format_price(&row.symbol, price),
+                Column::BuyPrice => format_price(&row.symbol, row.buy_price),
+                Column::Shares => format!("{} sh", row.shares),
+                Column::Value => format_money(&row.symbol, row.value),
+                Column::DayChange => match row.day_change {
+                    Some(change) => format!("{:>7} today", format_percent(change)),
+                    None => format!("{:>13}", ""),
+                },
+                Column::ProfitLoss => {
+                    let sign = if row.profit_loss >= 0.0 { "+" } else { "-" };
+                    let profit = format!("{}{}", sign, format_money(&row.symbol, row.profit_loss));
+                    format!("{:>11}", profit)
+                }
+                Column::Change => {
+                    let percent = format!("({})", format_percent(row.change_percent));
+                    format!("{:>10}", percent)
+                }
+                Column::Weight => format!("· {:>6}", format_weight(row.weight)),
+            });
+        }
+        parts.join(" ")
+    }
+
+    fn row(&self, row: &Row, report: &Report) -> Line {
+        let ticker = pad_right(&row.ticker, XbarRenderer::ticker_width(report));
+        let text = match &row.price {
+            // The offline banner already explains the failure; don't repeat it per ticker
+            Err(_) if report.offline => format!("{}: no cached price", row.ticker),
+            Err(e) => format!("{}: Error - {}", row.ticker, e),
+            Ok(_) if cash::is_cash_ticker(&row.ticker) && report.privacy => {
+                format!("{} {}", ticker, format_weight(row.weight))
+            }
+            // Cash is a balance, not a trade: show its value and share of the account
+            Ok(_) if cash::is_cash_ticker(&row.ticker) => {
+                let interest = if row.profit_loss > 0.0 {
+                    format!(
+                        " · +{} interest",
+                        format_money(&row.symbol, row.profit_loss)
+                    )
+                } else {
+                    String::new()
+                };
+                format!(
+                    "{} {} ({}){}",
+                    ticker,
+                    format_money(&row.symbol, row.value),
+                    format_weight(row.weight),
+                    interest
+                )
+            }
+            Ok(price) => {
+                format!(
+                    "{} {}{}{}{}",
+                    ticker,
+                    row.sparkline,
+                    self.columns(row, *price, report.privacy),
+                    row.session
+                        .map(|session| format!(" {}", session))
+                        .unwrap_or_default(),
+                    row.badge
+                        .as_ref()
+                        .map(|badge| format!(" {}", badge))
+                        .unwrap_or_default()
+                )
+            }
+        };
+        let arrow = match row.day_change {
+            _ if !self.arrows => "",
+            Some(change) if change > 0.0 => "▲ ",
+            Some(change) if change < 0.0 => "▼ ",
+            // Keep the tickers aligned
+            _ => "  ",
+        };
+        let mut line = Line::new(format!("{}{}", arrow, text), row.color.clone());
+        line.href = row.href.clone();
+        line.submenu = row.details.clone();
+        // Holding Option swaps percentages for the market value and shares
+        if row.price.is_ok() && !cash::is_cash_ticker(&row.ticker) && !report.privacy {
+            let mut alternate = line.clone();
+            alternate.text = format!(
+                "{}{} {}{} · {} sh",
+                arrow,
+                ticker,
+                row.sparkline,
+                format_money(&row.symbol, row.value),
+                row.shares
+            );
+            line.alternate = Some(Box::new(alternate));
+        }
+        line
+    }
+
+    /// Prints positions, folding the collapsed ones into an "Other" line
+    /// in place of the first of them, with a submenu listing them
+    fn rows(&self, out: &mut String, report: &Report, indexes: impl Iterator<Item = usize>) {
+        let mut lines = Vec::new();
+        let mut other = Vec::new();
+        let mut other_at = None;
+        for row in indexes.map(|index| &report.rows[index]) {
+            if row.collapsed {
+                other_at.get_or_insert(lines.len());
+                other.push(row);
+            } else {
+                lines.push(self.row(row, report));
+            }
+        }
+        if let Some(at) = other_at {
+            lines.insert(at, self.other(&other, report));
+        }
+        for line in &lines {
+            self.line(out, 0, line);
+        }
+    }
+
+    /// "Other (5 positions): +$12", the folded positions in its submenu
+    fn other(&self, rows: &[&Row], report: &Report) -> Line {
+        let profit_loss: f64 = rows.iter().map(|row| row.profit_loss * row.rate).sum();
+        let value: f64 = rows.iter().map(|row| row.value * row.rate).sum();
+        let percent = profit_loss / (value - profit_loss) * 100.0;
+        let change = if report.privacy {
+            format_percent(percent)
+        } else {
+            format!(
+                "{}{}",
+                if profit_loss >= 0.0 { "+" } else { "-" },
+                format_money(&report.symbol, profit_loss)
+            )
+        };
+        let mut line = Line::new(
+            format!(
+                "Other ({} position{}): {}",
+                rows.len(),
+                if rows.len() == 1 { "" } else { "s" },
+                change
+            ),
+            report.palette.color(percent),
+        );
+        line.submenu = rows.iter().map(|row| self.row(row, report)).collect();
+        line
+    }
+}
+
+impl Renderer for XbarRenderer {
+    fn render(&self, report: &Report) -> String {
+        let image = report
+            .title_symbol
+            .map(|symbol| format!(" | sfimage={}", symbol))
+            .unwrap_or_default();
+        let mut out = format!("{}{}\n", report.title, image);
+        for title in &report.cycle {
+            out.push_str(&format!("{}{}\n", title, image));
+        }
+        out.push_str("---\n");
+        for notice in &report.notices {
+            self.line(&mut out, 0, notice);
+        }
+
+        for text in report.summary() {
+            let mut line = Line::new(text, "white");
+            line.href = report.portfolio_url.clone();
+            self.line(&mut out, 0, &line);
+        }
+        if !report.allocation.is_empty() {
+            let mut allocation = Line::new("Allocation", "white");
+            allocation.submenu = report
+                .allocation
+                .iter()
+                .map(|(ticker, weight)| {
+                    // One block per five percent
+                    let bar = "█".repeat((weight / 5.0).round() as usize);
+                    let text = format!(
+                        "{} {:>6} {}",
+                        pad_right(ticker, 10),
+                        format_weight(*weight),
+                        bar
+                    );
+                    Line::new(text.trim_end(), "white")
+                })
+                .collect();
+            self.line(&mut out, 0, &allocation);
+        }
+        self.line(
+            &mut out,
+            0,
+            &Line::new(
+                format!("Updated {}", local_clock(report.updated_at)),
+                "gray",
+            ),
+        );
+        out.push_str("---\n");
+
+        // Individual positions, grouped under subtotals with their weight
+        if report.groups.is_empty() {
+            self.rows(&mut out, report, 0..report.rows.len());
+        }
+        for (i, group) in report.groups.iter().enumerate() {
+            if i > 0 {
+                out.push_str("---\n");
+            }
+            let heading = group.heading(report.current_value, report.privacy, &report.symbol);
+            self.line(&mut out, 0, &Line::new(heading, "white"));
+            self.rows(&mut out, report, group.rows.iter().copied());
+        }
+
+        // Watch-only tickers, outside every total
+        if !report.watchlist.is_empty() {
+            out.push_str("---\n");
+            self.line(&mut out, 0, &Line::new("Watchlist", "white"));
+            for line in &report.watchlist {
+                self.line(&mut out, 0, line);
+            }
+        }
+
+        // Fully sold lots, collapsed into a submenu
+        if !report.archive.is_empty() {
+            out.push_str("---\n");
+            let mut archive = Line::new(format!("Archive ({})", report.archive.len()), "white");
+            archive.submenu = report.archive.clone();
+            self.line(&mut out, 0, &archive);
+        }
+
+        if let Some(footer) = &report.footer {
+            out.push_str("---\n");
+            self.line(&mut out, 0, &Line::new(footer.clone(), "gray"));
+        }
+
+        // Update without waiting for the next xbar cycle
+        out.push_str("---\n");
+        for action in &report.actions {
+            self.line(&mut out, 0, action);
+        }
+        let mut refresh = Line::new("Refresh now", "white");
+        refresh.command = report.refresh_command.clone();
+        refresh.refresh = true;
+        self.line(&mut out, 0, &refresh);
+        out
+    }
+}
+
+/// One JSON document for scripts: the positions, every quote, failed
+/// fetches and the totals in the base currency
+///
+/// Failures carry their error kind and whether a retry may help so scripts
+/// can branch on them.
+pub struct JsonRenderer;
+
+impl JsonRenderer {
+    /// The document, also the model templates are filled in from
+    pub fn document(report: &Report) -> Json {
+        let quote = |ticker: &str| report.quotes.iter().find(|quote| quote.ticker == ticker);
+        let positions = report
+            .rows
+            .iter()
+            .filter_map(|row| {
+                let price = *row.price.as_ref().ok()?;
+                let quote = quote(&row.ticker);
+                Some(Json::object([
+                    ("ticker", Json::from(row.ticker.as_str())),
+                    ("shares", Json::from(row.shares)),
+                    ("buy_price", Json::from(row.buy_price)),
+                    ("price", Json::from(price)),
+                    ("currency", Json::from(row.currency.as_str())),
+                    ("value", Json::from(row.value)),
+                    ("profit_loss", Json::from(row.profit_loss)),
+                    ("change_percent", Json::from(row.change_percent)),
+                    ("day_change_percent", Json::from(row.day_change)),
+                    ("weight", Json::from(row.weight)),
+                    ("as_of", Json::from(quote.and_then(|quote| quote.as_of))),
+                    ("stale", Json::from(quote.is_some_and(|quote| quote.stale))),
+                ]))
+            })
+            .collect();
+        let mut quotes = Vec::new();
+        let mut errors = Vec::new();
+        for quote in &report.quotes {
+            match &quote.price {
+                Ok(price) => quotes.push(Json::object([
+                    ("ticker", Json::from(quote.ticker.as_str())),
+                    ("price", Json::from(*price)),
+                    ("currency", Json::from(quote.currency.as_str())),
+                    ("as_of", Json::from(quote.as_of)),
+                    ("stale", Json::from(quote.stale)),
+                ])),
+                Err((kind, message)) => errors.push(Json::object([
+                    ("ticker", Json::from(quote.ticker.as_str())),
+                    ("kind", Json::from(kind.name())),
+                    ("provider", Json::from(report.provider.as_str())),
+                    ("retryable", Json::from(kind.is_retryable())),
+                    ("message", Json::from(message.as_str())),
+                ])),
+            }
+        }
+        let totals = Json::object([
+            ("currency", Json::from(report.base_currency.as_str())),
+            ("investment", Json::from(report.investment)),
+            ("value", Json::from(report.current_value)),
+            ("cash", Json::from(report.cash)),
+            ("profit_loss", Json::from(report.profit_loss)),
+            ("change_percent", Json::from(report.change_percent)),
+            ("unrealized", Json::from(report.unrealized)),
+            (
+                "realized",
+                Json::from(report.realized.map(|(realized, _)| realized)),
+            ),
+        ]);
+        Json::object([
+            ("title", Json::from(report.title.as_str())),
+            ("offline", Json::from(report.offline)),
+            ("updated_at", Json::from(report.updated_at)),
+            ("positions", Json::Array(positions)),
+            ("quotes", Json::Array(quotes)),
+            ("errors", Json::Array(errors)),
+            ("totals", totals),
+        ])
+    }
+}
+
+impl Renderer for JsonRenderer {
+    fn render(&self, report: &Report) -> String {
+        format!("{}\n", JsonRenderer::document(report))
+    }
+}
+
+/// Output laid out by a user's template, filled in from the JSON document
+pub struct TemplateRenderer {
+    pub template: Template,
+}
+
+impl Renderer for TemplateRenderer {
+    fn render(&self, report: &Report) -> String {
+        self.template.render(&JsonRenderer::document(report))
+    }
+}
+
+/// An aligned table with ANSI colors, for running the tool in a shell
+pub struct TerminalRenderer {
+    /// Off when `NO_COLOR` is set
+    pub color: bool,
+}
+
+impl TerminalRenderer {
+    /// Wraps text in the ANSI code closest to an xbar color name, or a
+    /// 24-bit code for `#RRGGBB`
+    fn paint(&self, text: &str, color: &str) -> String {
+        let rgb;
+        let code = match color {
+            hex if hex.len() == 7 && hex.starts_with('#') => {
+                let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(255);
+                rgb = format!("38;2;{};{};{}", channel(1), channel(3), channel(5));
+                rgb.as_str()
+            }
+            "green" => "32",
+            "darkred" => "31",
+            "orange" => "33",
+            "dodgerblue" => "34",
+            "gray" => "90",
+            "bold" => "1",
+            _ => return text.to_string(),
+        };
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn line(&self, out: &mut String, depth: usize, line: &Line) {
+        let text = format!("{}{}", "  ".repeat(depth), line.text);
+        out.push_str(&self.paint(&text, &line.color));
+        out.push('\n');
+        for item in &line.submenu {
+            self.line(out, depth + 1, item);
+        }
+    }
+
+    /// The cells of a row under [`TABLE_HEADER`], with amounts masked in
+    /// privacy mode
+    fn cells(row: &Row, privacy: bool) -> Vec<String> {
+        let Ok(price) = row.price else {
+            return vec![row.ticker.clone()];
+        };
+        let money = |amount: f64| {
+            if privacy {
+                "•••".to_string()
+            } else {
+                format_money(&row.symbol, amount)
+            }
+        };
+        let weight = format_weight(row.weight);
+        if cash::is_cash_ticker(&row.ticker) {
+            let interest = if row.profit_loss > 0.0 && !privacy {
+                format!("+{}", money(row.profit_loss))
+            } else {
+                String::new()
+            };
+            let blank = String::new;
+            return vec![
+                row.ticker.clone(),
+                blank(),
+                blank(),
+                blank(),
+                money(row.value),
+                interest,
+                blank(),
+                weight,
+            ];
+        }
+        vec![
+            row.ticker.clone(),
+            if privacy {
+                "•••".to_string()
+            } else {
+                format!("{}", row.shares)
+            },
+            format_price(&row.symbol, row.buy_price),
+            format_price(&row.symbol, price),
+            money(row.value),
+            if privacy {
+                money(row.profit_loss)
+            } else {
+                format!(
+                    "{}{}",
+                    if row.profit_loss >= 0.0 { "+" } else { "-" },
+                    money(row.profit_loss)
+                )
+            },
+            format_percent(row.change_percent),
+            weight,
+        ]
+    }
+}
+
+const TABLE_HEADER: [&str; 8] = [
+    "TICKER", "SHARES", "BUY", "PRICE", "VALUE", "P/L", "CHANGE", "WEIGHT",
+];
+
+impl Renderer for TerminalRenderer {
+    fn render(&self, report: &Report) -> String {
+        let mut out = format!("{}\n", self.paint(&report.title, "bold"));
+        for notice in &report.notices {
+            self.line(&mut out, 0, notice);
+        }
+        out.push('\n');
+        for text in report.summary() {
+            out.push_str(&format!("{}\n", text));
+        }
+        let updated = format!("Updated {}", local_clock(report.updated_at));
+        out.push_str(&format!("{}\n\n", self.paint(&updated, "gray")));
+
+        // Columns are sized to fit every row, whichever group it is in
+        let cells: Vec<Vec<String>> = report
+            .rows
+            .iter()
+            .map(|row| TerminalRenderer::cells(row, report.privacy))
+            .collect();
+        let mut widths = TABLE_HEADER.map(str::len);
+        for row in cells.iter().filter(|cells| cells.len() > 1) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(display_width(cell));
+            }
+        }
+        let align = |cells: &[String]| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(widths)
+                .enumerate()
+                .map(|(column, (cell, width))| match column {
+                    0 => pad_right(cell, width),
+                    _ => pad_left(cell, width),
+                })
+                .collect();
+            padded.join("  ")
+        };
+        let table_row = |out: &mut String, index: usize| {
+            let row = &report.rows[index];
+            let mut text = match &row.price {
+                Err(_) if report.offline => {
+                    format!("{}  no cached price", pad_right(&row.ticker, widths[0]))
+                }
+                Err(e) => format!("{}  error: {}", pad_right(&row.ticker, widths[0]), e),
+                Ok(_) => align(&cells[index]),
+            };
+            if let Some(session) = row.session {
+                text.push_str(&format!("  {}", session));
+            }
+            if let Some(badge) = &row.badge {
+                text.push_str(&format!("  {}", badge));
+            }
+            out.push_str(&self.paint(&text, &row.color));
+            out.push('\n');
+        };
+
+        let header: Vec<String> = TABLE_HEADER.iter().map(|name| name.to_string()).collect();
+        out.push_str(&format!("{}\n", self.paint(&align(&header), "bold")));
+        if report.groups.is_empty() {
+            for index in 0..report.rows.len() {
+                table_row(&mut out, index);
+            }
+        }
+        for group in &report.groups {
+            out.push_str(&format!(
+                "{}\n",
+                self.paint(
+                    &group.heading(report.current_value, report.privacy, &report.symbol),
+                    "bold"
+                )
+            ));
+            for &index in &group.rows {
+                table_row(&mut out, index);
+            }
+        }
+
+        if !report.watchlist.is_empty() {
+            out.push_str(&format!("\n{}\n", self.paint("Watchlist", "bold")));
+            for line in &report.watchlist {
+                self.line(&mut out, 0, line);
+            }
+        }
+        if !report.archive.is_empty() {
+            out.push_str(&format!("\n{}\n", self.paint("Archive", "bold")));
+            for line in &report.archive {
+                self.line(&mut out, 0, line);
+            }
+        }
+        if let Some(footer) = &report.footer {
+            out.push_str(&format!("\n{}\n", self.paint(footer, "gray")));
+        }
+        out
+    }
+}
+
+/// Quotes an xbar parameter value that contains spaces
+fn quote_param(value: &str) -> String {
+    if value.contains([' ', '"']) {
+        format!("\"{}\"", value.replace('"', "\\\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Hex code of an xbar color name, for bars that take hex colors
+fn hex_color(color: &str) -> &str {
+    match color {
+        hex if hex.starts_with('#') => hex,
+        "green" => "#00CC66",
+        "darkred" => "#FF5555",
+        "orange" => "#FFAA00",
+        "dodgerblue" => "#1E90FF",
+        "gray" => "#888888",
+        _ => "#FFFFFF",
+    }
+}
+
+/// Color of the total on a bar
+fn bar_color(report: &Report) -> &str {
+    hex_color(if report.offline {
+        "orange"
+    } else {
+        report.palette.color(report.change_percent)
+    })
+}
+
+/// A Waybar custom module: one JSON object with the title as text and the
+/// table as tooltip
+///
+/// The class is `gain`, `loss` or `offline` for styling.
+pub struct WaybarRenderer;
+
+impl Renderer for WaybarRenderer {
+    fn render(&self, report: &Report) -> String {
+        let table = TerminalRenderer { color: false }.render(report);
+        // The title is already on the bar; Waybar reads tooltips as Pango markup
+        let (_, body) = table.split_once('\n').unwrap_or_default();
+        let tooltip = body
+            .trim()
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        let class = if report.offline {
+            "offline"
+        } else if report.profit_loss >= 0.0 {
+            "gain"
+        } else {
+            "loss"
+        };
+        let document = Json::object([
+            ("text", Json::from(report.title.as_str())),
+            ("tooltip", Json::from(tooltip)),
+            ("class", Json::from(class)),
+        ]);
+        format!("{}\n", document)
+    }
+}
+
+/// An i3blocks block: the full text, a short text and a color, one per line
+pub struct I3blocksRenderer;
+
+impl Renderer for I3blocksRenderer {
+    fn render(&self, report: &Report) -> String {
+        format!(
+            "{}\n{:+.1}%\n{}\n",
+            report.title,
+            report.change_percent,
+            bar_color(report)
+        )
+    }
+}
+
+/// A Polybar module line: the colored total followed by each position's
+/// change, using `%{F}` format tags
+pub struct PolybarRenderer {
+    /// Print the total alone
+    pub summary_only: bool,
+}
+
+impl Renderer for PolybarRenderer {
+    fn render(&self, report: &Report) -> String {
+        let mut parts = vec![format!(
+            "%{{F{}}}{}%{{F-}}",
+            bar_color(report),
+            report.title
+        )];
+        if !self.summary_only {
+            for row in &report.rows {
+                if row.price.is_err() || cash::is_cash_ticker(&row.ticker) {
+                    continue;
+                }
+                parts.push(format!(
+                    "{} %{{F{}}}{:+.1}%{{F-}}",
+                    row.ticker,
+                    hex_color(&row.color),
+                    row.change_percent
+                ));
+            }
+        }
+        format!("{}\n", parts.join(" · "))
+    }
+}
+
+/// A Markdown summary and table, for pasting into notes or chat
+pub struct MarkdownRenderer;
+
+impl MarkdownRenderer {
+    fn table(out: &mut String, report: &Report, rows: impl Iterator<Item = usize>) {
+        out.push_str(&format!("| {} |\n", TABLE_HEADER.join(" | ")));
+        let align: String = (0..TABLE_HEADER.len())
+            .map(|column| if column == 0 { " --- |" } else { " ---: |" })
+            .collect();
+        out.push_str(&format!("|{}\n", align));
+        for index in rows {
+            let row = &report.rows[index];
+            let mut cells = TerminalRenderer::cells(row, report.privacy);
+            if let Err(e) = &row.price {
+                // The reason goes where the price would be
+                cells.resize(TABLE_HEADER.len(), String::new());
+                cells[3] = if report.offline {
+                    "no cached price".to_string()
+                } else {
+                    format!("error: {}", e)
+                };
+            }
+            let cells: Vec<String> = cells.iter().map(|cell| cell.replace('|', "\\|")).collect();
+            out.push_str(&format!("| {} |\n", cells.join(" | ")));
+        }
+    }
+}
+
+impl Renderer for MarkdownRenderer {
+    fn render(&self, report: &Report) -> String {
+        let mut out = format!("## Portfolio: {}\n\n", report.title);
+        for notice in &report.notices {
+            out.push_str(&format!("> {}\n", notice.text));
+            for item in &notice.submenu {
+                out.push_str(&format!("> - {}\n", item.text));
+            }
+        }
+        if !report.notices.is_empty() {
+            out.push('\n');
+        }
+        for text in report.summary() {
+            out.push_str(&format!("- {}\n", text));
+        }
+        out.push_str(&format!("- Updated {}\n\n", local_clock(report.updated_at)));
+
+        if report.groups.is_empty() {
+            MarkdownRenderer::table(&mut out, report, 0..report.rows.len());
+        }
+        for (i, group) in report.groups.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(&format!(
+                "### {}\n\n",
+                group.heading(report.current_value, report.privacy, &report.symbol)
+            ));
+            MarkdownRenderer::table(&mut out, report, group.rows.iter().copied());
+        }
+
+        for (heading, lines) in [
+            ("Watchlist", &report.watchlist),
+            ("Archive", &report.archive),
+        ] {
+            if lines.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("\n### {}\n\n", heading));
+            for line in lines {
+                out.push_str(&format!(
+                    "- {}\n",
+                    line.text.split_whitespace().collect::<Vec<_>>().join(" ")
+                ));
+            }
+        }
+        if let Some(footer) = &report.footer {
+            out.push_str(&format!("\n_{}_\n", footer));
+        }
+        out
+    }
+}
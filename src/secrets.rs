@@ -0,0 +1,264 @@
+use crate::keychain;
+use std::collections::BTreeMap;
+use std::env;
+use std::error::Error;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::Command;
+
+type SecretResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
+
+/// A place secrets such as API keys and file passphrases are kept
+///
+/// Implement this to plug in another backend; [`from_name`] builds the
+/// stores that ship with the crate.
+pub trait SecretStore: Send + Sync {
+    /// Looks up a secret, `Ok(None)` if the store has no such key
+    fn get(&self, key: &str) -> SecretResult<Option<String>>;
+
+    /// Stores a secret, replacing any previous value
+    fn set(&self, key: &str, _value: &str) -> SecretResult<()> {
+        Err(format!("This secret store is read-only, cannot set '{}'", key).into())
+    }
+
+    /// Removes a secret
+    fn delete(&self, key: &str) -> SecretResult<()> {
+        Err(format!("This secret store is read-only, cannot delete '{}'", key).into())
+    }
+}
+
+/// The macOS Keychain, via the `security` tool
+pub struct KeychainStore;
+
+impl SecretStore for KeychainStore {
+    fn get(&self, key: &str) -> SecretResult<Option<String>> {
+        Ok(keychain::find_password(key).ok())
+    }
+
+    fn set(&self, key: &str, value: &str) -> SecretResult<()> {
+        keychain::set_password(key, value)
+    }
+
+    fn delete(&self, key: &str) -> SecretResult<()> {
+        keychain::delete_password(key)
+    }
+}
+
+/// Environment variables named `STOCKS_SECRET_<KEY>`, e.g. `api-key:finnhub`
+/// becomes `STOCKS_SECRET_API_KEY_FINNHUB`
+pub struct EnvStore;
+
+impl EnvStore {
+    /// The variable consulted for a key
+    pub fn var_name(key: &str) -> String {
+        let normalized: String = key
+            .chars()
+            .map(|ch| {
+                if ch.is_ascii_alphanumeric() {
+                    ch.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        format!("STOCKS_SECRET_{}", normalized)
+    }
+}
+
+impl SecretStore for EnvStore {
+    fn get(&self, key: &str) -> SecretResult<Option<String>> {
+        Ok(env::var(EnvStore::var_name(key)).ok())
+    }
+}
+
+/// A `key = value` file, by default `~/.stocks/secrets` (created with mode 0600)
+pub struct FileStore {
+    path: PathBuf,
+}
+
+impl FileStore {
+    pub fn new(path: PathBuf) -> FileStore {
+        FileStore { path }
+    }
+
+    fn read(&self) -> SecretResult<BTreeMap<String, String>> {
+        if !self.path.exists() {
+            return Ok(BTreeMap::new());
+        }
+        Ok(fs::read_to_string(&self.path)?
+            .lines()
+            .filter(|line| !line.trim_start().starts_with('#'))
+            .filter_map(|line| line.split_once('='))
+            .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+            .collect())
+    }
+
+    fn write(&self, secrets: &BTreeMap<String, String>) -> SecretResult<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents: String = secrets
+            .iter()
+            .map(|(key, value)| format!("{} = {}\n", key, value))
+            .collect();
+        // A fresh file that is private from the start replaces the old one,
+        // whatever permissions that had
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+        let _ = fs::remove_file(&temp);
+        let mut options = OpenOptions::new();
+        options.create_new(true).write(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options.open(&temp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl SecretStore for FileStore {
+    fn get(&self, key: &str) -> SecretResult<Option<String>> {
+        Ok(self.read()?.remove(key))
+    }
+
+    fn set(&self, key: &str, value: &str) -> SecretResult<()> {
+        let mut secrets = self.read()?;
+        secrets.insert(key.to_string(), value.to_string());
+        self.write(&secrets)
+    }
+
+    fn delete(&self, key: &str) -> SecretResult<()> {
+        let mut secrets = self.read()?;
+        if secrets.remove(key).is_none() {
+            return Err(format!("No secret '{}' in {}", key, self.path.display()).into());
+        }
+        self.write(&secrets)
+    }
+}
+
+/// An external command printing the secret, with `{key}` replaced by the key
+///
+/// For example `pass show xbar-stocks/{key}` or
+/// `secret-tool lookup service xbar-stocks account {key}`. The key is
+/// quoted for the shell, so it is always a single word.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::secrets::{CommandStore, SecretStore};
+///
+/// let store = CommandStore::new("echo {key}");
+/// let key = "api-key:x; echo '$HOME'";
+/// assert_eq!(store.get(key).unwrap(), Some(key.to_string()));
+/// ```
+pub struct CommandStore {
+    command: String,
+}
+
+impl CommandStore {
+    pub fn new(command: &str) -> CommandStore {
+        CommandStore {
+            command: command.to_string(),
+        }
+    }
+}
+
+impl SecretStore for CommandStore {
+    fn get(&self, key: &str) -> SecretResult<Option<String>> {
+        let command = self.command.replace("{key}", &shell_quote(key));
+        let output = Command::new("sh")
+            .args(["-c", &command])
+            .output()
+            .map_err(|e| format!("Failed to run '{}': {}", command, e))?;
+        if !output.status.success() {
+            return Ok(None);
+        }
+        // Like pass, only the first line is the secret
+        let stdout = String::from_utf8(output.stdout)?;
+        Ok(stdout
+            .lines()
+            .next()
+            .map(str::to_string)
+            .filter(|s| !s.is_empty()))
+    }
+}
+
+/// Single-quotes text for `sh`, closing the quotes around any `'` in it
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Tries each store in turn; writes go to the first one
+pub struct ChainStore {
+    stores: Vec<Box<dyn SecretStore>>,
+}
+
+impl ChainStore {
+    pub fn new(stores: Vec<Box<dyn SecretStore>>) -> ChainStore {
+        ChainStore { stores }
+    }
+}
+
+impl SecretStore for ChainStore {
+    fn get(&self, key: &str) -> SecretResult<Option<String>> {
+        for store in &self.stores {
+            if let Some(secret) = store.get(key)? {
+                return Ok(Some(secret));
+            }
+        }
+        Ok(None)
+    }
+
+    fn set(&self, key: &str, value: &str) -> SecretResult<()> {
+        match self.stores.first() {
+            Some(store) => store.set(key, value),
+            None => Err("No secret store configured".into()),
+        }
+    }
+
+    fn delete(&self, key: &str) -> SecretResult<()> {
+        match self.stores.first() {
+            Some(store) => store.delete(key),
+            None => Err("No secret store configured".into()),
+        }
+    }
+}
+
+/// Builds a built-in store by name, always falling back to the environment
+///
+/// `command` is required for the `command` backend and ignored otherwise.
+pub fn from_name(name: &str, command: Option<&str>) -> SecretResult<Box<dyn SecretStore>> {
+    let primary: Box<dyn SecretStore> = match name {
+        "keychain" => Box::new(KeychainStore),
+        "env" => return Ok(Box::new(EnvStore)),
+        "file" => Box::new(FileStore::new(crate::data_dir().join("secrets"))),
+        "command" => {
+            let command = command.ok_or("The command secret store needs secrets.command")?;
+            Box::new(CommandStore::new(command))
+        }
+        other => return Err(format!("Unknown secret store '{}'", other).into()),
+    };
+    Ok(Box::new(ChainStore::new(vec![primary, Box::new(EnvStore)])))
+}
+
+/// Key under which a provider's API key is stored
+pub fn api_key_name(provider: &str) -> String {
+    format!("api-key:{}", provider.to_lowercase())
+}
+
+/// Looks up a provider's API key, falling back to `STOCKS_<PROVIDER>_API_KEY`
+pub fn api_key(store: &dyn SecretStore, provider: &str) -> Option<String> {
+    store
+        .get(&api_key_name(provider))
+        .ok()
+        .flatten()
+        .filter(|key| !key.is_empty())
+        .or_else(|| env::var(format!("STOCKS_{}_API_KEY", provider.to_uppercase())).ok())
+}
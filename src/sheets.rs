@@ -0,0 +1,131 @@
+use crate::json::Json;
+use crate::state::content_hash;
+use crate::{data_dir, http_client};
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// Secret store provider name of the Google OAuth token, set with
+/// `auth set google-sheets`
+pub const TOKEN_PROVIDER: &str = "google-sheets";
+
+/// True if a portfolio path is a URL rather than a file
+pub fn is_url(path: &str) -> bool {
+    path.starts_with("https://") || path.starts_with("http://")
+}
+
+/// The CSV export URL for a Google Sheets link
+///
+/// Links to the editor are turned into their CSV export, keeping the tab
+/// (`gid`); published (`/pub?output=csv`), Sheets API and other URLs are
+/// used as they are.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::sheets::export_url;
+///
+/// assert_eq!(
+///     export_url("https://docs.google.com/spreadsheets/d/abc123/edit#gid=42"),
+///     "https://docs.google.com/spreadsheets/d/abc123/export?format=csv&gid=42"
+/// );
+/// ```
+pub fn export_url(url: &str) -> String {
+    let Some(rest) = url.strip_prefix("https://docs.google.com/spreadsheets/d/") else {
+        return url.to_string();
+    };
+    let (id, tail) = rest.split_once('/').unwrap_or((rest, ""));
+    if id == "e" || !(tail.is_empty() || tail.starts_with("edit")) {
+        return url.to_string();
+    }
+    let gid = tail
+        .split(['#', '?', '&'])
+        .find_map(|part| part.strip_prefix("gid="));
+    match gid {
+        Some(gid) => format!(
+            "https://docs.google.com/spreadsheets/d/{}/export?format=csv&gid={}",
+            id, gid
+        ),
+        None => format!(
+            "https://docs.google.com/spreadsheets/d/{}/export?format=csv",
+            id
+        ),
+    }
+}
+
+/// Downloads a sheet as CSV, sending `token` as an OAuth bearer token for
+/// private sheets and the Sheets API
+///
+/// Sheets API `values` responses are converted from JSON to CSV. Each
+/// download is kept so that the last copy is used, with a warning, when the
+/// sheet cannot be reached.
+pub fn fetch(url: &str, token: Option<&str>) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let cache = cache_path(url);
+    match download(url, token) {
+        Ok(contents) => {
+            if let Some(parent) = cache.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&cache, &contents)?;
+            Ok(contents)
+        }
+        Err(e) if cache.exists() => {
+            eprintln!("Using the last downloaded copy of {}: {}", url, e);
+            Ok(fs::read(&cache)?)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+fn download(url: &str, token: Option<&str>) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let mut request = http_client()?.get(export_url(url));
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    let response = request.send()?;
+    if response.status() != 200 {
+        return Err(format!("Invalid status code HTTP{}", response.status()).into());
+    }
+    let body = response.bytes()?.to_vec();
+    if url.starts_with("https://sheets.googleapis.com/") {
+        return values_to_csv(&String::from_utf8(body)?);
+    }
+    // A private sheet fetched without access redirects to the sign-in page
+    if body.trim_ascii_start().starts_with(b"<") {
+        return Err("got a web page instead of CSV; publish the sheet or store a token with `auth set google-sheets`".into());
+    }
+    Ok(body)
+}
+
+/// Rows of a Sheets API `values` response as CSV
+fn values_to_csv(body: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let document = Json::parse(body)?;
+    let rows = document
+        .get("values")
+        .and_then(Json::as_array)
+        .ok_or("Sheets API response has no values")?;
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_writer(Vec::new());
+    for row in rows {
+        let cells: Vec<String> = row
+            .as_array()
+            .unwrap_or_default()
+            .iter()
+            .map(|cell| match cell {
+                Json::String(text) => text.clone(),
+                Json::Number(number) => number.to_string(),
+                Json::Bool(value) => value.to_string(),
+                _ => String::new(),
+            })
+            .collect();
+        writer.write_record(&cells)?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.to_string())?)
+}
+
+fn cache_path(url: &str) -> PathBuf {
+    data_dir()
+        .join("cache")
+        .join(format!("sheet-{}.csv", content_hash(url.as_bytes())))
+}
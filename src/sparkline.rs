@@ -0,0 +1,31 @@
+/// Block characters from lowest to highest
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Renders values as a one-character-per-point sparkline
+///
+/// A flat series renders at mid height so it is still visible.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::sparkline::sparkline;
+///
+/// assert_eq!(sparkline(&[1.0, 2.0, 3.0, 4.0]), "▁▃▆█");
+/// ```
+pub fn sparkline(values: &[f64]) -> String {
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+
+    values
+        .iter()
+        .map(|value| {
+            if range <= f64::EPSILON {
+                BARS[BARS.len() / 2]
+            } else {
+                let level = ((value - min) / range * (BARS.len() - 1) as f64).round();
+                BARS[level as usize]
+            }
+        })
+        .collect()
+}
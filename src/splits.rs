@@ -0,0 +1,95 @@
+use crate::data_dir;
+use crate::date::Date;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A stock split: every share held before `date` became `ratio` shares
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    pub ticker: String,
+    pub date: Date,
+    pub ratio: f64,
+}
+
+/// Default splits table location, `~/.stocks/splits.csv`
+pub fn default_path() -> PathBuf {
+    data_dir().join("splits.csv")
+}
+
+/// Parses a split ratio written as `4:1`, `1:10` (reverse split) or `4`
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::splits::parse_ratio;
+///
+/// assert_eq!(parse_ratio("4:1"), Some(4.0));
+/// assert_eq!(parse_ratio("1:10"), Some(0.1));
+/// assert_eq!(parse_ratio("20"), Some(20.0));
+/// assert_eq!(parse_ratio("0:1"), None);
+/// ```
+pub fn parse_ratio(value: &str) -> Option<f64> {
+    let ratio = match value.trim().split_once(':') {
+        Some((new, old)) => new.trim().parse::<f64>().ok()? / old.trim().parse::<f64>().ok()?,
+        None => value.trim().parse().ok()?,
+    };
+    (ratio.is_finite() && ratio > 0.0).then_some(ratio)
+}
+
+/// Loads a `ticker,date,ratio` table; a missing file means no splits
+pub fn load(path: &Path) -> Result<Vec<Split>, Box<dyn Error + Send + Sync>> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let contents = fs::read(path)?;
+    let mut reader = csv::Reader::from_reader(contents.as_slice());
+    let mut splits = Vec::new();
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        // Line numbers count the header
+        let line = index + 2;
+        let field = |i: usize| record.get(i).unwrap_or("").trim();
+        let date = Date::parse(field(1))
+            .ok_or_else(|| format!("splits line {}: invalid date '{}'", line, field(1)))?;
+        let ratio = parse_ratio(field(2))
+            .ok_or_else(|| format!("splits line {}: invalid ratio '{}'", line, field(2)))?;
+        splits.push(Split {
+            ticker: field(0).to_string(),
+            date,
+            ratio,
+        });
+    }
+    Ok(splits)
+}
+
+/// Factor to multiply a share count by (and divide a price by) to express a
+/// lot held from `bought` until `sold` in today's shares
+///
+/// Undated purchases are assumed to predate every split, and lots sold before
+/// a split are not affected by it.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::date::Date;
+/// use xbar_stocks::splits::{Split, adjustment};
+///
+/// let splits = vec![Split {
+///     ticker: "AAPL.US".to_string(),
+///     date: Date::parse("2020-08-31").unwrap(),
+///     ratio: 4.0,
+/// }];
+/// let bought = Date::parse("2019-05-01");
+/// assert_eq!(adjustment(&splits, "aapl.us", bought, None), 4.0);
+/// assert_eq!(adjustment(&splits, "AAPL.US", bought, Date::parse("2020-01-02")), 1.0);
+/// ```
+pub fn adjustment(splits: &[Split], ticker: &str, bought: Option<Date>, sold: Option<Date>) -> f64 {
+    splits
+        .iter()
+        .filter(|split| split.ticker.eq_ignore_ascii_case(ticker))
+        .filter(|split| bought.is_none_or(|bought| bought < split.date))
+        .filter(|split| sold.is_none_or(|sold| sold >= split.date))
+        .map(|split| split.ratio)
+        .product()
+}
@@ -0,0 +1,96 @@
+use crate::config::{Value, parse_toml};
+use crate::data_dir;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// Small bits of bookkeeping carried between runs in `~/.stocks/state.toml`
+#[derive(Debug, Clone, Default)]
+pub struct State {
+    path: PathBuf,
+    /// Hash of the positions file as of the last verification
+    pub portfolio_hash: Option<String>,
+    /// Tickers known to resolve with the provider
+    pub known_tickers: Vec<String>,
+    /// Unix time of the market close the last end-of-day summary covered
+    pub eod_notified_at: Option<i64>,
+    /// Privacy mode as last flipped from the menu, overriding the config
+    pub privacy: Option<bool>,
+}
+
+impl State {
+    /// Loads the state; a missing or unreadable file gives an empty state
+    pub fn load(path: PathBuf) -> State {
+        let mut state = State {
+            path,
+            ..State::default()
+        };
+        let Ok(text) = fs::read_to_string(&state.path) else {
+            return state;
+        };
+        let Ok(values) = parse_toml(&text) else {
+            return state;
+        };
+
+        if let Some(Value::String(hash)) = values.get("portfolio_hash") {
+            state.portfolio_hash = Some(hash.clone());
+        }
+        if let Some(Value::Array(tickers)) = values.get("known_tickers") {
+            state.known_tickers = tickers
+                .iter()
+                .filter_map(|value| match value {
+                    Value::String(ticker) => Some(ticker.clone()),
+                    _ => None,
+                })
+                .collect();
+        }
+        if let Some(Value::Number(at)) = values.get("eod_notified_at") {
+            state.eod_notified_at = Some(*at as i64);
+        }
+        if let Some(Value::Bool(enabled)) = values.get("privacy") {
+            state.privacy = Some(*enabled);
+        }
+        state
+    }
+
+    /// Loads the state from its default location
+    pub fn default_location() -> State {
+        State::load(data_dir().join("state.toml"))
+    }
+
+    /// Writes the state back to disk
+    pub fn save(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut text = String::from("# Managed by xbar-stocks, safe to delete\n");
+        if let Some(hash) = &self.portfolio_hash {
+            text.push_str(&format!("portfolio_hash = \"{}\"\n", hash));
+        }
+        let tickers: Vec<String> = self
+            .known_tickers
+            .iter()
+            .map(|ticker| format!("\"{}\"", ticker))
+            .collect();
+        text.push_str(&format!("known_tickers = [{}]\n", tickers.join(", ")));
+        if let Some(at) = self.eod_notified_at {
+            text.push_str(&format!("eod_notified_at = {}\n", at));
+        }
+        if let Some(enabled) = self.privacy {
+            text.push_str(&format!("privacy = {}\n", enabled));
+        }
+        fs::write(&self.path, text)?;
+        Ok(())
+    }
+}
+
+/// 64-bit FNV-1a hash, stable across Rust versions, as lowercase hex
+pub fn content_hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
@@ -0,0 +1,147 @@
+use crate::data_dir;
+use crate::transactions::{self, Kind, Transaction};
+use std::error::Error;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Default database location, `~/.stocks/portfolio.db`
+pub fn default_path() -> PathBuf {
+    data_dir().join("portfolio.db")
+}
+
+/// True if the path names a SQLite store rather than a text portfolio
+pub fn is_store(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| matches!(extension, "db" | "sqlite" | "sqlite3"))
+}
+
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS transactions (
+    id INTEGER PRIMARY KEY,
+    date TEXT NOT NULL,
+    type TEXT NOT NULL CHECK (type IN ('buy', 'sell', 'dividend')),
+    ticker TEXT NOT NULL,
+    shares REAL,
+    price REAL,
+    fee REAL,
+    amount REAL,
+    reinvested INTEGER NOT NULL DEFAULT 0
+);";
+
+/// A transactions ledger kept in a SQLite database
+///
+/// Statements go through the `sqlite3` command-line shell, so no SQLite
+/// library is linked in. Each call runs in one SQLite transaction, and other
+/// tools writing to the same file wait up to five seconds for the lock.
+pub struct Store {
+    path: PathBuf,
+}
+
+impl Store {
+    /// Opens the database, creating it and its table if needed
+    pub fn open(path: &Path) -> Result<Store, Box<dyn Error + Send + Sync>> {
+        let store = Store {
+            path: path.to_path_buf(),
+        };
+        store.run(&[], SCHEMA)?;
+        Ok(store)
+    }
+
+    /// The ledger as `date,type,ticker,...` CSV, as read by
+    /// [`transactions::parse`]; empty when there are no transactions
+    pub fn ledger_csv(&self) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        self.run(
+            &["-csv", "-header"],
+            "SELECT date, type, ticker, shares, price, fee, amount,
+                CASE WHEN reinvested THEN 'true' ELSE 'false' END AS reinvested
+            FROM transactions ORDER BY date, id;",
+        )
+    }
+
+    /// Every stored transaction, oldest first
+    pub fn transactions(&self) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+        transactions::parse(&self.ledger_csv()?)
+    }
+
+    /// The transactions with their row ids as an aligned table
+    pub fn table(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let output = self.run(
+            &["-column", "-header"],
+            "SELECT id, date, type, ticker, shares, price, fee, amount
+            FROM transactions ORDER BY date, id;",
+        )?;
+        Ok(String::from_utf8(output)?)
+    }
+
+    /// Appends a transaction, returning its row id; NaN and infinite amounts
+    /// are refused since SQL has no literal for them
+    pub fn insert(&self, transaction: &Transaction) -> Result<i64, Box<dyn Error + Send + Sync>> {
+        for (column, value) in [
+            ("shares", Some(transaction.shares)),
+            ("price", Some(transaction.price)),
+            ("fee", transaction.fee),
+            ("amount", transaction.amount),
+        ] {
+            if let Some(value) = value
+                && !value.is_finite()
+            {
+                return Err(format!("{}: {} must be a finite number", column, value).into());
+            }
+        }
+        let kind = match transaction.kind {
+            Kind::Buy => "buy",
+            Kind::Sell => "sell",
+            Kind::Dividend => "dividend",
+        };
+        let sql = format!(
+            "BEGIN IMMEDIATE;
+            INSERT INTO transactions (date, type, ticker, shares, price, fee, amount, reinvested)
+            VALUES ('{}', '{}', {}, {}, {}, {}, {}, {});
+            SELECT last_insert_rowid();
+            COMMIT;",
+            transaction.date,
+            kind,
+            quote(&transaction.ticker),
+            transaction.shares,
+            transaction.price,
+            number(transaction.fee),
+            number(transaction.amount),
+            transaction.reinvested as u8,
+        );
+        let output = self.run(&[], &sql)?;
+        Ok(String::from_utf8(output)?.trim().parse()?)
+    }
+
+    /// Runs SQL fed through stdin, stopping at the first error
+    fn run(&self, flags: &[&str], sql: &str) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+        let mut child = Command::new("sqlite3")
+            .arg("-bail")
+            .args(flags)
+            .arg(&self.path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| format!("Failed to run sqlite3: {}", e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            writeln!(stdin, ".timeout 5000")?;
+            stdin.write_all(sql.as_bytes())?;
+        }
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            let message = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("sqlite3 failed: {}", message.trim()).into());
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// SQL string literal
+fn quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn number(value: Option<f64>) -> String {
+    value.map_or_else(|| "NULL".to_string(), |value| value.to_string())
+}
@@ -0,0 +1,167 @@
+use crate::config::url_encode;
+use crate::http_client;
+use crate::json::Json;
+use std::error::Error;
+
+/// Stooq symbols to try for a ticker the provider does not know, most
+/// likely first
+///
+/// Translates Yahoo-style exchange suffixes, writes share classes with a
+/// dash, and offers the US listing for bare symbols.
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::symbols::suggestions;
+///
+/// assert_eq!(suggestions("aapl"), ["AAPL.US"]);
+/// assert_eq!(suggestions("VUSA.L"), ["VUSA.UK"]);
+/// assert_eq!(suggestions("BRK.B"), ["BRK-B.US"]);
+/// assert_eq!(suggestions("PKN.WA"), ["PKN"]);
+/// assert!(suggestions("AAPL.US").is_empty());
+/// ```
+pub fn suggestions(ticker: &str) -> Vec<String> {
+    let ticker = ticker.trim().to_uppercase();
+    let mut candidates = Vec::new();
+    match ticker.rsplit_once('.') {
+        Some((symbol, suffix)) => {
+            let stooq = match suffix {
+                "L" | "LON" => Some("UK"),
+                "T" | "TYO" => Some("JP"),
+                "DE" | "F" | "ETR" | "XETRA" => Some("DE"),
+                "HK" => Some("HK"),
+                "WA" | "PL" => Some(""),
+                "O" | "N" | "OQ" | "NYSE" | "NASDAQ" => Some("US"),
+                // A one-letter suffix is usually a share class
+                class if class.len() == 1 => {
+                    candidates.push(format!("{}-{}.US", symbol, class));
+                    None
+                }
+                _ => None,
+            };
+            match stooq {
+                Some("") => candidates.push(symbol.to_string()),
+                Some(stooq) if stooq != suffix => candidates.push(format!("{}.{}", symbol, stooq)),
+                _ => {}
+            }
+        }
+        None => {
+            candidates.push(format!("{}.US", ticker.replace(['/', ' '], "-")));
+        }
+    }
+    candidates
+}
+
+/// Currency a stooq symbol is quoted in, from its exchange suffix; bare
+/// symbols are Warsaw listings
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::symbols::currency;
+///
+/// assert_eq!(currency("AAPL.US"), Some("USD"));
+/// assert_eq!(currency("PKN"), Some("PLN"));
+/// assert_eq!(currency("^SPX"), None);
+/// ```
+pub fn currency(symbol: &str) -> Option<&'static str> {
+    if symbol.starts_with('^') {
+        return None;
+    }
+    match symbol.to_uppercase().rsplit_once('.') {
+        Some((_, "US")) => Some("USD"),
+        Some((_, "UK")) => Some("GBP"),
+        Some((_, "DE")) => Some("EUR"),
+        Some((_, "JP")) => Some("JPY"),
+        Some((_, "HK")) => Some("HKD"),
+        Some((_, "PL")) | None => Some("PLN"),
+        Some(_) => None,
+    }
+}
+
+/// A listing found by [`search`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Listing {
+    /// Symbol to put in the portfolio, in the provider's form
+    pub symbol: String,
+    /// Exchange name as the search service writes it, e.g. `NASDAQ`
+    pub exchange: String,
+    pub name: String,
+    /// Kind of security, e.g. `EQUITY` or `ETF`
+    pub kind: String,
+}
+
+/// Looks up listings matching a company name, ISIN or symbol with Yahoo's
+/// symbol search, writing each symbol as stooq does
+pub fn search(query: &str) -> Result<Vec<Listing>, Box<dyn Error + Send + Sync>> {
+    let url = format!(
+        "https://query2.finance.yahoo.com/v1/finance/search?q={}&quotesCount=10&newsCount=0",
+        url_encode(query.trim())
+    );
+    let response = http_client()?.get(url).send()?;
+    if response.status() != 200 {
+        return Err(format!("Invalid status code HTTP{}", response.status()).into());
+    }
+    parse_search(&response.text()?)
+}
+
+/// Listings in a Yahoo symbol-search response
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::symbols::parse_search;
+///
+/// let body = r#"{"quotes": [
+///     {"symbol": "AAPL", "exchange": "NMS", "exchDisp": "NASDAQ", "longname": "Apple Inc.", "quoteType": "EQUITY"},
+///     {"symbol": "VUSA.L", "exchange": "LSE", "shortname": "VANGUARD S&P 500", "quoteType": "ETF"}
+/// ]}"#;
+/// let listings = parse_search(body).unwrap();
+/// assert_eq!(listings[0].symbol, "AAPL.US");
+/// assert_eq!(listings[0].exchange, "NASDAQ");
+/// assert_eq!(listings[1].symbol, "VUSA.UK");
+/// assert_eq!(listings[1].name, "VANGUARD S&P 500");
+/// ```
+pub fn parse_search(body: &str) -> Result<Vec<Listing>, Box<dyn Error + Send + Sync>> {
+    let document = Json::parse(body)?;
+    let quotes = document
+        .get("quotes")
+        .and_then(Json::as_array)
+        .ok_or("search response has no quotes")?;
+    let text = |quote: &Json, keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| quote.get(key).and_then(Json::as_str))
+            .unwrap_or("")
+            .to_string()
+    };
+    Ok(quotes
+        .iter()
+        .filter_map(|quote| {
+            let symbol = quote.get("symbol").and_then(Json::as_str)?;
+            let exchange = text(quote, &["exchange"]);
+            Some(Listing {
+                symbol: stooq_symbol(symbol, &exchange),
+                exchange: text(quote, &["exchDisp", "exchange"]),
+                name: text(quote, &["longname", "shortname"]),
+                kind: text(quote, &["quoteType"]),
+            })
+        })
+        .collect())
+}
+
+/// Stooq form of a Yahoo symbol: exchange suffixes translated and US
+/// listings, which Yahoo leaves bare, given `.US`
+fn stooq_symbol(symbol: &str, exchange: &str) -> String {
+    let us = matches!(
+        exchange,
+        "NMS" | "NGM" | "NCM" | "NYQ" | "PCX" | "ASE" | "BTS" | "NAS" | "NYS"
+    );
+    if symbol.contains('.') || us {
+        suggestions(symbol)
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| symbol.to_uppercase())
+    } else {
+        symbol.to_uppercase()
+    }
+}
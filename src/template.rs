@@ -0,0 +1,240 @@
+use crate::json::Json;
+use std::error::Error;
+
+/// A parsed template in a small subset of Handlebars
+///
+/// Supported tags:
+///
+/// - `{{name}}` and `{{totals.value}}`: a value, looked up in the current
+///   item first and then in the enclosing ones; `{{this}}` is the item itself
+///   and `{{@index}}` its position in an `each` loop
+/// - `{{fixed value 2}}` and `{{signed value 2}}`: a number with that many
+///   decimals, the latter always with its sign
+/// - `{{#each list}}...{{/each}}`
+/// - `{{#if value}}...{{else}}...{{/if}}` and `{{#unless value}}...{{/unless}}`,
+///   where `null`, `false`, `0`, `""` and `[]` are false
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::json::Json;
+/// use xbar_stocks::template::Template;
+///
+/// let template = Template::parse(
+///     "{{#each positions}}{{ticker}} {{signed change 1}}%{{#if stale}} (stale){{/if}}\n{{/each}}",
+/// )
+/// .unwrap();
+/// let model = Json::parse(r#"{"positions": [{"ticker": "PKN", "change": 2.25, "stale": true}]}"#)
+///     .unwrap();
+/// assert_eq!(template.render(&model), "PKN +2.2% (stale)\n");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Value(Expr),
+    Each(String, Vec<Node>),
+    If(String, Vec<Node>, Vec<Node>),
+}
+
+/// What a `{{...}}` tag prints
+#[derive(Debug, Clone)]
+enum Expr {
+    Path(String),
+    Index,
+    /// A number with a fixed number of decimals, optionally always signed
+    Number {
+        path: String,
+        digits: usize,
+        signed: bool,
+    },
+}
+
+impl Expr {
+    fn parse(tag: &str) -> Result<Expr, Box<dyn Error + Send + Sync>> {
+        let words: Vec<&str> = tag.split_whitespace().collect();
+        match words.as_slice() {
+            ["@index"] => Ok(Expr::Index),
+            [path] => Ok(Expr::Path(path.to_string())),
+            [helper @ ("fixed" | "signed"), path, digits] => Ok(Expr::Number {
+                path: path.to_string(),
+                digits: digits
+                    .parse()
+                    .map_err(|_| format!("{{{{{}}}}}: expected a number of decimals", tag))?,
+                signed: *helper == "signed",
+            }),
+            _ => Err(format!("unknown tag {{{{{}}}}}", tag).into()),
+        }
+    }
+}
+
+/// What ended a run of nodes
+enum End {
+    Eof,
+    Else,
+    Close(String),
+}
+
+/// Items being rendered, innermost last, with their loop index
+type Scopes<'a> = Vec<(&'a Json, Option<usize>)>;
+
+impl Template {
+    /// Parses a template, checking that every block is closed
+    pub fn parse(text: &str) -> Result<Template, Box<dyn Error + Send + Sync>> {
+        let mut rest = text;
+        match parse_nodes(&mut rest)? {
+            (nodes, End::Eof) => Ok(Template { nodes }),
+            (_, End::Else) => Err("{{else}} outside an {{#if}}".into()),
+            (_, End::Close(name)) => Err(format!("unexpected {{{{/{}}}}}", name).into()),
+        }
+    }
+
+    /// Fills the template in from a model such as the `--format json`
+    /// document; missing values print as nothing
+    pub fn render(&self, model: &Json) -> String {
+        let mut out = String::new();
+        render_nodes(&self.nodes, &mut vec![(model, None)], &mut out);
+        out
+    }
+}
+
+fn parse_nodes(rest: &mut &str) -> Result<(Vec<Node>, End), Box<dyn Error + Send + Sync>> {
+    let mut nodes = Vec::new();
+    loop {
+        let Some(start) = rest.find("{{") else {
+            if !rest.is_empty() {
+                nodes.push(Node::Text(rest.to_string()));
+            }
+            *rest = "";
+            return Ok((nodes, End::Eof));
+        };
+        if start > 0 {
+            nodes.push(Node::Text(rest[..start].to_string()));
+        }
+        let length = rest[start..].find("}}").ok_or("unterminated {{ tag")?;
+        let tag = rest[start + 2..start + length].trim().to_string();
+        *rest = &rest[start + length + 2..];
+
+        if tag == "else" {
+            return Ok((nodes, End::Else));
+        }
+        if let Some(name) = tag.strip_prefix('/') {
+            return Ok((nodes, End::Close(name.trim().to_string())));
+        }
+        let Some(opening) = tag.strip_prefix('#') else {
+            nodes.push(Node::Value(Expr::parse(&tag)?));
+            continue;
+        };
+        let (name, argument) = opening
+            .split_once(' ')
+            .map(|(name, argument)| (name, argument.trim().to_string()))
+            .ok_or_else(|| format!("{{{{#{}}}}} needs a value", opening))?;
+        let (body, end) = parse_nodes(rest)?;
+        let node = match (name, end) {
+            ("each", End::Close(closing)) if closing == "each" => Node::Each(argument, body),
+            ("if" | "unless", End::Else) => match parse_nodes(rest)? {
+                (otherwise, End::Close(closing)) if closing == name => match name {
+                    "if" => Node::If(argument, body, otherwise),
+                    _ => Node::If(argument, otherwise, body),
+                },
+                _ => return Err(format!("{{{{#{}}}}} is not closed", name).into()),
+            },
+            ("if", End::Close(closing)) if closing == "if" => Node::If(argument, body, Vec::new()),
+            ("unless", End::Close(closing)) if closing == "unless" => {
+                Node::If(argument, Vec::new(), body)
+            }
+            ("each" | "if" | "unless", _) => {
+                return Err(format!("{{{{#{}}}}} is not closed", name).into());
+            }
+            _ => return Err(format!("unknown block {{{{#{}}}}}", name).into()),
+        };
+        nodes.push(node);
+    }
+}
+
+fn render_nodes<'a>(nodes: &'a [Node], scopes: &mut Scopes<'a>, out: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Value(expr) => out.push_str(&value(expr, scopes)),
+            Node::Each(path, body) => {
+                let Some(items) = lookup(path, scopes).and_then(Json::as_array) else {
+                    continue;
+                };
+                for (index, item) in items.iter().enumerate() {
+                    scopes.push((item, Some(index)));
+                    render_nodes(body, scopes, out);
+                    scopes.pop();
+                }
+            }
+            Node::If(path, then, otherwise) => {
+                let branch = if lookup(path, scopes).is_some_and(truthy) {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(branch, scopes, out);
+            }
+        }
+    }
+}
+
+fn value(expr: &Expr, scopes: &Scopes) -> String {
+    match expr {
+        Expr::Index => scopes
+            .last()
+            .and_then(|(_, index)| *index)
+            .map(|index| index.to_string())
+            .unwrap_or_default(),
+        Expr::Path(path) => lookup(path, scopes).map(text).unwrap_or_default(),
+        Expr::Number {
+            path,
+            digits,
+            signed,
+        } => match lookup(path, scopes) {
+            Some(Json::Number(number)) if *signed => format!("{:+.*}", digits, number),
+            Some(Json::Number(number)) => format!("{:.*}", digits, number),
+            Some(other) => text(other),
+            None => String::new(),
+        },
+    }
+}
+
+/// Finds a dotted path in the innermost item that has its first key
+fn lookup<'a>(path: &str, scopes: &Scopes<'a>) -> Option<&'a Json> {
+    let mut keys = path.split('.');
+    let first = keys.next()?;
+    let mut current = if first == "this" {
+        scopes.last()?.0
+    } else {
+        scopes.iter().rev().find_map(|(item, _)| item.get(first))?
+    };
+    for key in keys {
+        current = current.get(key)?;
+    }
+    Some(current)
+}
+
+fn truthy(value: &Json) -> bool {
+    match value {
+        Json::Null => false,
+        Json::Bool(value) => *value,
+        Json::Number(value) => *value != 0.0,
+        Json::String(value) => !value.is_empty(),
+        Json::Array(items) => !items.is_empty(),
+        Json::Object(_) => true,
+    }
+}
+
+/// A value as it appears in the output: strings without quotes, `null` empty
+fn text(value: &Json) -> String {
+    match value {
+        Json::Null => String::new(),
+        Json::String(value) => value.clone(),
+        other => other.to_string(),
+    }
+}
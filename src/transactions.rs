@@ -0,0 +1,381 @@
+use crate::date::Date;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Whether a transaction adds or removes shares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Buy,
+    Sell,
+    /// Cash paid out by the holding; only reinvested dividends change the
+    /// share count
+    Dividend,
+}
+
+/// One row of a transactions ledger
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub date: Date,
+    pub kind: Kind,
+    pub ticker: String,
+    pub shares: f64,
+    /// Price per share
+    pub price: f64,
+    /// Broker fee or commission for the whole transaction
+    pub fee: Option<f64>,
+    /// Cash paid for a dividend
+    pub amount: Option<f64>,
+    /// The dividend bought more shares of the same holding (DRIP)
+    pub reinvested: bool,
+    /// Currency of the price and amounts (ISO code); the base currency when
+    /// not given
+    pub currency: Option<String>,
+}
+
+impl Transaction {
+    /// Price per share with the fee spread over the shares: a buy costs more
+    /// and a sell yields less
+    pub fn net_price(&self) -> f64 {
+        let fee = self.fee.unwrap_or(0.0) / self.shares;
+        match self.kind {
+            Kind::Buy => self.price + fee,
+            Kind::Sell | Kind::Dividend => self.price - fee,
+        }
+    }
+}
+
+/// Shares still held from one purchase
+#[derive(Debug, Clone)]
+pub struct Lot {
+    pub ticker: String,
+    pub date: Date,
+    pub shares: f64,
+    /// Cost per share, including the purchase fee
+    pub price: f64,
+}
+
+/// Shares bought and later sold
+#[derive(Debug, Clone)]
+pub struct ClosedLot {
+    pub ticker: String,
+    pub bought: Date,
+    pub sold: Date,
+    pub shares: f64,
+    /// Cost per share, including the purchase fee
+    pub buy_price: f64,
+    /// Proceeds per share, net of the sale fee
+    pub sell_price: f64,
+}
+
+/// Open and closed lots derived from a ledger
+#[derive(Debug, Clone, Default)]
+pub struct Holdings {
+    pub open: Vec<Lot>,
+    pub closed: Vec<ClosedLot>,
+}
+
+/// How sells are matched against earlier buys
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CostBasis {
+    /// Oldest shares are sold first
+    Fifo,
+    /// Newest shares are sold first
+    Lifo,
+    /// Every open lot shrinks proportionally at the weighted-average cost
+    Average,
+}
+
+impl CostBasis {
+    /// Parses a method name as written in the config
+    pub fn parse(name: &str) -> Option<CostBasis> {
+        match name.to_lowercase().as_str() {
+            "fifo" => Some(CostBasis::Fifo),
+            "lifo" => Some(CostBasis::Lifo),
+            "average" => Some(CostBasis::Average),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Row {
+    date: String,
+    #[serde(rename = "type")]
+    kind: String,
+    ticker: String,
+    #[serde(default)]
+    shares: Option<f64>,
+    #[serde(default)]
+    price: Option<f64>,
+    #[serde(default)]
+    fee: Option<f64>,
+    #[serde(default)]
+    amount: Option<f64>,
+    #[serde(default)]
+    reinvested: Option<bool>,
+    #[serde(default)]
+    currency: Option<String>,
+}
+
+/// True if a CSV header row describes a ledger (`date,type,ticker,...`)
+/// rather than a positions file
+pub fn is_ledger(contents: &[u8]) -> bool {
+    let header = contents
+        .split(|byte| *byte == b'\n')
+        .find(|line| !line.starts_with(b"#"))
+        .unwrap_or(&[]);
+    String::from_utf8_lossy(header)
+        .split(',')
+        .any(|column| column.trim().eq_ignore_ascii_case("type"))
+}
+
+/// Parses a `date,type,ticker,shares,price,fee` ledger, oldest first
+///
+/// An optional `currency` column names the currency each row is quoted in.
+/// Dividend rows fill the optional `amount` column instead of shares and
+/// price, and set `reinvested` to `true` when the payout bought more shares.
+/// A reinvested row may give the shares and price the broker reported;
+/// otherwise [`reinvest_dividends`] derives them from the day's close.
+pub fn parse(contents: &[u8]) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    let mut reader = csv::Reader::from_reader(contents);
+    let mut transactions = Vec::new();
+    for (index, row) in reader.deserialize::<Row>().enumerate() {
+        // Line numbers count the header
+        let line = index + 2;
+        let row = row?;
+        let date = Date::parse(&row.date)
+            .ok_or_else(|| format!("line {}: invalid date '{}'", line, row.date))?;
+        let kind = match row.kind.to_lowercase().as_str() {
+            "buy" => Kind::Buy,
+            "sell" => Kind::Sell,
+            "dividend" => Kind::Dividend,
+            other => {
+                return Err(format!("line {}: unknown transaction type '{}'", line, other).into());
+            }
+        };
+        let (shares, price) = match (kind, row.shares, row.price) {
+            (Kind::Dividend, None, None) => (0.0, 0.0),
+            (_, Some(shares), Some(price)) if shares > 0.0 => (shares, price),
+            (_, Some(_), Some(_)) => {
+                return Err(format!("line {}: shares must be positive", line).into());
+            }
+            _ => return Err(format!("line {}: expected both shares and price", line).into()),
+        };
+        if kind == Kind::Dividend && !row.amount.is_some_and(|amount| amount > 0.0) {
+            return Err(format!("line {}: dividend needs a positive amount", line).into());
+        }
+        transactions.push(Transaction {
+            date,
+            kind,
+            ticker: row.ticker,
+            shares,
+            price,
+            fee: row.fee,
+            amount: row.amount,
+            reinvested: row.reinvested.unwrap_or(false),
+            currency: row.currency.filter(|currency| !currency.is_empty()),
+        });
+    }
+    // Stable sort keeps same-day rows in file order
+    transactions.sort_by_key(|transaction| transaction.date);
+    Ok(transactions)
+}
+
+/// Turns reinvested dividends into share purchases
+///
+/// Rows that already carry shares and price are bought as given; the rest
+/// buy `amount` worth (less the fee) at `price_on(ticker, date)`. Dividends
+/// paid out as cash are kept as they are.
+pub fn reinvest_dividends(
+    transactions: &[Transaction],
+    price_on: impl Fn(&str, Date) -> Option<f64>,
+) -> Result<Vec<Transaction>, Box<dyn Error + Send + Sync>> {
+    transactions
+        .iter()
+        .map(|transaction| {
+            if transaction.kind != Kind::Dividend || !transaction.reinvested {
+                return Ok(transaction.clone());
+            }
+            let (shares, price) = if transaction.shares > 0.0 {
+                (transaction.shares, transaction.price)
+            } else {
+                let price = price_on(&transaction.ticker, transaction.date).ok_or_else(|| {
+                    format!(
+                        "{}: no price on {} to reinvest the dividend at; add shares and price to the row",
+                        transaction.ticker, transaction.date
+                    )
+                })?;
+                let cash = transaction.amount.unwrap_or(0.0) - transaction.fee.unwrap_or(0.0);
+                (cash / price, price)
+            };
+            Ok(Transaction {
+                kind: Kind::Buy,
+                shares,
+                price,
+                ..transaction.clone()
+            })
+        })
+        .collect()
+}
+
+/// Replays a ledger into the lots still held and the lots sold
+///
+/// With [`CostBasis::Average`] every open lot of the ticker shrinks
+/// proportionally, so the remaining lots keep their dates and the average
+/// cost is unchanged. FIFO and LIFO close whole lots in date order, splitting
+/// the last one touched.
+///
+/// # Example
+///
+/// Fees are folded into the prices: the $10 buy fee makes the first lot
+/// cost $101 a share, and the $15 sell fee nets $129 a share.
+///
+/// ```
+/// use xbar_stocks::transactions::{self, CostBasis};
+///
+/// let ledger = transactions::parse(
+///     b"date,type,ticker,shares,price,fee
+/// 2024-01-02,buy,ACME,10,100,10
+/// 2024-03-01,buy,ACME,10,120,0
+/// 2024-06-03,sell,ACME,15,130,15
+/// ",
+/// )
+/// .unwrap();
+/// let run = |method| {
+///     let holdings = transactions::holdings(&ledger, method).unwrap();
+///     let open: Vec<(String, f64, f64)> = holdings
+///         .open
+///         .iter()
+///         .map(|lot| (lot.date.to_string(), lot.shares, lot.price))
+///         .collect();
+///     let realized: Vec<(f64, f64)> = holdings
+///         .closed
+///         .iter()
+///         .map(|lot| (lot.shares, (lot.sell_price - lot.buy_price) * lot.shares))
+///         .collect();
+///     (open, realized)
+/// };
+///
+/// // The oldest lot goes first, then half of the second
+/// let (open, realized) = run(CostBasis::Fifo);
+/// assert_eq!(open, [("2024-03-01".to_string(), 5.0, 120.0)]);
+/// assert_eq!(realized, [(10.0, 280.0), (5.0, 45.0)]);
+///
+/// // The newest lot goes first, then half of the oldest
+/// let (open, realized) = run(CostBasis::Lifo);
+/// assert_eq!(open, [("2024-01-02".to_string(), 5.0, 101.0)]);
+/// assert_eq!(realized, [(10.0, 90.0), (5.0, 140.0)]);
+///
+/// // Both lots shrink to a quarter, sold at the $110.50 average cost
+/// let (open, realized) = run(CostBasis::Average);
+/// assert_eq!(
+///     open,
+///     [("2024-01-02".to_string(), 2.5, 101.0), ("2024-03-01".to_string(), 2.5, 120.0)]
+/// );
+/// assert_eq!(realized, [(15.0, 277.5)]);
+/// ```
+pub fn holdings(
+    transactions: &[Transaction],
+    method: CostBasis,
+) -> Result<Holdings, Box<dyn Error + Send + Sync>> {
+    let mut open: BTreeMap<&str, Vec<Lot>> = BTreeMap::new();
+    let mut closed = Vec::new();
+
+    for transaction in transactions {
+        let lots = open.entry(&transaction.ticker).or_default();
+        match transaction.kind {
+            // Cash dividends do not change the lots
+            Kind::Dividend => {}
+            Kind::Buy => lots.push(Lot {
+                ticker: transaction.ticker.clone(),
+                date: transaction.date,
+                shares: transaction.shares,
+                price: transaction.net_price(),
+            }),
+            Kind::Sell => {
+                let held: f64 = lots.iter().map(|lot| lot.shares).sum();
+                // Allow for rounding in fractional share counts
+                if transaction.shares > held + 1e-9 {
+                    return Err(format!(
+                        "{}: selling {} shares on {} but only {} held",
+                        transaction.ticker, transaction.shares, transaction.date, held
+                    )
+                    .into());
+                }
+                match method {
+                    CostBasis::Average => sell_average(lots, transaction, held, &mut closed),
+                    CostBasis::Fifo => sell_in_order(lots, transaction, false, &mut closed),
+                    CostBasis::Lifo => sell_in_order(lots, transaction, true, &mut closed),
+                }
+                lots.retain(|lot| lot.shares > 1e-9);
+            }
+        }
+    }
+
+    Ok(Holdings {
+        open: open.into_values().flatten().collect(),
+        closed,
+    })
+}
+
+/// Closes a sale at the weighted-average cost of `lots`, shrinking each
+/// of them by the same fraction
+fn sell_average(
+    lots: &mut [Lot],
+    transaction: &Transaction,
+    held: f64,
+    closed: &mut Vec<ClosedLot>,
+) {
+    let cost: f64 = lots.iter().map(|lot| lot.shares * lot.price).sum();
+    let bought = lots
+        .iter()
+        .map(|lot| lot.date)
+        .min()
+        .unwrap_or(transaction.date);
+    closed.push(ClosedLot {
+        ticker: transaction.ticker.clone(),
+        bought,
+        sold: transaction.date,
+        shares: transaction.shares,
+        buy_price: cost / held,
+        sell_price: transaction.net_price(),
+    });
+
+    let remaining = ((held - transaction.shares) / held).max(0.0);
+    for lot in lots.iter_mut() {
+        lot.shares *= remaining;
+    }
+}
+
+/// Closes a sale against `lots` oldest first, or newest first for LIFO
+fn sell_in_order(
+    lots: &mut [Lot],
+    transaction: &Transaction,
+    newest_first: bool,
+    closed: &mut Vec<ClosedLot>,
+) {
+    // Lots are kept in purchase order since the ledger is sorted by date
+    let mut order: Vec<usize> = (0..lots.len()).collect();
+    if newest_first {
+        order.reverse();
+    }
+    let mut to_sell = transaction.shares;
+    for index in order {
+        if to_sell <= 1e-9 {
+            break;
+        }
+        let lot = &mut lots[index];
+        let shares = lot.shares.min(to_sell);
+        closed.push(ClosedLot {
+            ticker: transaction.ticker.clone(),
+            bought: lot.date,
+            sold: transaction.date,
+            shares,
+            buy_price: lot.price,
+            sell_price: transaction.net_price(),
+        });
+        lot.shares -= shares;
+        to_sell -= shares;
+    }
+}
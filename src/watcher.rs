@@ -0,0 +1,48 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Notices edits to files by polling their modification times
+///
+/// Cheap enough to check every few hundred milliseconds and works the same
+/// on macOS and Linux, including editors that replace the file on save.
+/// A watched directory changes when any file directly inside it does.
+pub struct FileWatcher {
+    paths: Vec<PathBuf>,
+    modified: Vec<Option<SystemTime>>,
+}
+
+impl FileWatcher {
+    /// Starts watching `paths` from their current state
+    pub fn new(paths: &[PathBuf]) -> FileWatcher {
+        FileWatcher {
+            paths: paths.to_vec(),
+            modified: paths.iter().map(|path| modified(path)).collect(),
+        }
+    }
+
+    /// True once per change since the last call (or since creation)
+    pub fn changed(&mut self) -> bool {
+        let current: Vec<_> = self.paths.iter().map(|path| modified(path)).collect();
+        if current == self.modified {
+            return false;
+        }
+        self.modified = current;
+        true
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    let metadata = fs::metadata(path).ok()?;
+    let own = metadata.modified().ok();
+    if !metadata.is_dir() {
+        return own;
+    }
+    // Adding or removing files touches the directory, editing them does not
+    fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .chain(own)
+        .max()
+}
@@ -0,0 +1,64 @@
+/// Columns a character takes in a monospace font: none for combining marks
+/// and other zero-width characters, two for East Asian wide characters and
+/// emoji
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F
+        | 0x0483..=0x0489
+        | 0x0591..=0x05BD
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Columns text takes in a monospace font
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::width::display_width;
+///
+/// assert_eq!(display_width("AAPL.US"), 7);
+/// assert_eq!(display_width("トヨタ"), 6);
+/// assert_eq!(display_width("📈 +2%"), 6);
+/// ```
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+/// Pads text with spaces on the right to `width` columns
+///
+/// # Example
+///
+/// ```
+/// use xbar_stocks::width::pad_right;
+///
+/// assert_eq!(pad_right("トヨタ", 8), "トヨタ  ");
+/// ```
+pub fn pad_right(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(text));
+    format!("{}{}", text, " ".repeat(padding))
+}
+
+/// Pads text with spaces on the left to `width` columns
+pub fn pad_left(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(display_width(text));
+    format!("{}{}", " ".repeat(padding), text)
+}
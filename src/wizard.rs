@@ -0,0 +1,253 @@
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::path::Path;
+use xbar_stocks::config::PROVIDERS;
+use xbar_stocks::data_dir;
+use xbar_stocks::encryption;
+use xbar_stocks::secrets::SecretStore;
+
+/// True when a person is at the keyboard (xbar runs plugins without a terminal)
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && io::stdout().is_terminal()
+}
+
+/// Prints a prompt and reads one trimmed line; `None` on end of input
+fn prompt(input: &mut impl BufRead, label: &str, default: &str) -> io::Result<Option<String>> {
+    if default.is_empty() {
+        print!("{}: ", label);
+    } else {
+        print!("{} [{}]: ", label, default);
+    }
+    io::stdout().flush()?;
+
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let value = line.trim();
+    Ok(Some(if value.is_empty() {
+        default.to_string()
+    } else {
+        value.to_string()
+    }))
+}
+
+/// Prompts until the answer parses as a positive number
+fn prompt_number(input: &mut impl BufRead, label: &str) -> io::Result<Option<f64>> {
+    loop {
+        let Some(answer) = prompt(input, label, "")? else {
+            return Ok(None);
+        };
+        match answer.replace(',', ".").parse::<f64>() {
+            Ok(value) if value > 0.0 => return Ok(Some(value)),
+            _ => println!("  Please enter a positive number"),
+        }
+    }
+}
+
+/// Interactive first-run setup writing `data.csv` and, if missing, `config.toml`
+///
+/// Returns `Ok(false)` if the user entered no positions.
+pub fn run(
+    csv_path: &Path,
+    config_path: &Path,
+    secrets: &dyn SecretStore,
+) -> Result<bool, Box<dyn Error>> {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    println!("No portfolio found at {}.", csv_path.display());
+    println!("Let's set one up (press Ctrl-D to cancel).");
+    println!();
+
+    let mut config = None;
+    if !config_path.exists() {
+        let provider = loop {
+            let Some(answer) = prompt(
+                &mut input,
+                &format!("Price provider ({})", PROVIDERS.join(", ")),
+                PROVIDERS[0],
+            )?
+            else {
+                return Ok(false);
+            };
+            if PROVIDERS.contains(&answer.as_str()) {
+                break answer;
+            }
+            println!("  Unknown provider '{}'", answer);
+        };
+        let Some(currency) = prompt(&mut input, "Base currency", "USD")? else {
+            return Ok(false);
+        };
+        config = Some(format!(
+            "provider = \"{}\"\nbase_currency = \"{}\"\n",
+            provider,
+            currency.to_uppercase()
+        ));
+        println!();
+    }
+
+    println!("Add your positions; leave the ticker empty to finish.");
+    println!("Use stooq symbols, e.g. AAPL.US for US stocks or PKN for Warsaw.");
+    let mut rows = Vec::new();
+    while let Some(ticker) = prompt(&mut input, "Ticker", "")? {
+        if ticker.is_empty() {
+            break;
+        }
+        let Some(shares) = prompt_number(&mut input, "  Shares")? else {
+            break;
+        };
+        let Some(buy_price) = prompt_number(&mut input, "  Buy price")? else {
+            break;
+        };
+        rows.push((ticker.to_uppercase(), buy_price, shares));
+    }
+
+    if rows.is_empty() {
+        println!("No positions entered, nothing written.");
+        return Ok(false);
+    }
+
+    if let Some(parent) = csv_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["ticker", "buy_price", "shares"])?;
+    for (ticker, buy_price, shares) in &rows {
+        writer.write_record([ticker.clone(), buy_price.to_string(), shares.to_string()])?;
+    }
+    // Encrypts when the path ends in .age or .gpg
+    encryption::write_file(csv_path, &writer.into_inner()?, secrets).map_err(|e| e.to_string())?;
+    println!("Wrote {} positions to {}", rows.len(), csv_path.display());
+
+    if let Some(config) = config {
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(config_path, config)?;
+        println!("Wrote {}", config_path.display());
+    }
+    println!();
+    Ok(true)
+}
+
+/// Portfolio written by `init`, explaining its columns
+const SAMPLE_PORTFOLIO: &str = "\
+# Your portfolio: one row per purchase (lot). Lines starting with # are ignored.
+#
+#   ticker     stooq symbol: AAPL.US for New York, VUSA.UK for London, PKN for Warsaw
+#              (`xbar-stocks search <name>` finds it), CASH for a cash balance
+#   buy_price  price paid per share, in the currency the ticker trades in
+#   shares     number of shares; 0 keeps the ticker on the watchlist only
+#
+# Optional columns: currency, account, tags, date, fee, sell_price, sell_date,
+# benchmark, and apy for cash. Replace the rows below with your own.
+ticker,buy_price,shares,account
+AAPL.US,150.00,10,Brokerage
+MSFT.US,320.00,5,Brokerage
+CASH,1,1000,Brokerage
+NVDA.US,0,0,
+";
+
+/// Settings written by `init`, every one commented out at its default
+const SAMPLE_CONFIG: &str = "\
+# Settings for xbar-stocks. Every setting is optional; remove the # to change
+# one. STOCKS_<SECTION>_<KEY> environment variables override this file.
+
+# Quote provider
+# provider = \"stooq\"
+# Currency the totals are reported in
+# base_currency = \"USD\"
+# Ticker to compare the portfolio against
+# benchmark = \"^SPX\"
+
+[fetch]
+# Quotes fetched at the same time
+# concurrency = 7
+# Seconds a request may take, and to connect
+# timeout = 15
+# connect_timeout = 5
+# Reuse a quote fetched less than this many seconds ago
+# cache_ttl = 0
+
+[display]
+# Figures on each line: buy, price, shares, value, day, profit, change, weight
+# columns = [\"buy\", \"price\", \"profit\", \"change\", \"weight\"]
+# Order of the positions: ticker, value, profit, change or day, with :asc or :desc
+# sort = \"change:desc\"
+# Days of closes in each position's sparkline, 0 to hide it
+# sparkline = 10
+# Show percentages only
+# privacy = false
+# Minutes after which a cached price is marked stale
+# stale_after = 60
+
+[log]
+# Also log fetches to ~/.stocks/logs/stocks.log
+# file = false
+# level = \"info\"
+";
+
+/// Creates the data directory with a sample portfolio and config, keeping
+/// files that already exist, and prints how to install the plugin
+pub fn init(
+    csv_path: &Path,
+    config_path: &Path,
+    secrets: &dyn SecretStore,
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(data_dir())?;
+    if csv_path.exists() {
+        println!("Kept {}", csv_path.display());
+    } else {
+        if let Some(parent) = csv_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Encrypts when the path ends in .age or .gpg
+        encryption::write_file(csv_path, SAMPLE_PORTFOLIO.as_bytes(), secrets)
+            .map_err(|e| e.to_string())?;
+        println!("Wrote a sample portfolio to {}", csv_path.display());
+    }
+    if config_path.exists() {
+        println!("Kept {}", config_path.display());
+    } else {
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(config_path, SAMPLE_CONFIG)?;
+        println!("Wrote the settings to {}", config_path.display());
+    }
+
+    let program = std::env::current_exe()?;
+    let plugins = "$HOME/Library/Application Support/xbar/plugins";
+    println!();
+    println!("Next steps:");
+    println!(
+        "  1. Replace the sample rows in {} with your positions",
+        csv_path.display()
+    );
+    println!("  2. Install xbar from https://xbarapp.com");
+    println!("  3. Add the plugin, refreshing every 5 minutes:");
+    println!("       mkdir -p \"{}\"", plugins);
+    println!(
+        "       cp \"{}\" \"{}/xbar-stocks.5m.o\"",
+        program.display(),
+        plugins
+    );
+    println!("  4. Choose Refresh all in xbar's menu");
+    println!();
+    println!("Check the portfolio first with `xbar-stocks --format terminal`.");
+    Ok(())
+}
+
+/// The xbar menu shown when there is no portfolio yet, offering to run `init`
+pub fn setup_menu(csv_path: &Path) -> String {
+    let program = std::env::current_exe()
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| "xbar-stocks".to_string());
+    format!(
+        "Stocks: set up\n---\nNo portfolio at {}\nCreate a sample portfolio | bash={} param1=init terminal=true refresh=true\n",
+        csv_path.display(),
+        program
+    )
+}